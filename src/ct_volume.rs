@@ -1,27 +1,41 @@
+use crate::coordinates::Matrix4x4;
+use crate::volume::Volume;
 use anyhow::Result;
-use std::fmt;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+// CT volumes hold rescaled Hounsfield units
+pub type CTVolume = Volume<i16>;
 
-// Define the CTVolume struct to hold 3D data
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-#[derive(Clone)]
-pub struct CTVolume {
-    pub(crate) dimensions: (usize, usize, usize), // (rows, columns, number of slices)
-    pub(crate) voxel_spacing: (f32, f32, f32), // (spacing_x, spacing_y, spacing_z)
-    // pub(crate) voxel_data: Vec<Vec<i16>>, // 3D voxel data flattened into slices
-    pub(crate) voxel_data: Vec<i16>, // 3D voxel data 
+// PT (SUV or activity concentration) and MR volumes, whose values do not fit `i16`
+pub type FloatVolume = Volume<f32>;
+
+// Opaque handle passing a CT volume to JavaScript; wasm_bindgen cannot export generic types
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub struct CTVolumeHandle {
+    pub(crate) volume: CTVolume,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<CTVolume> for CTVolumeHandle {
+    fn from(volume: CTVolume) -> Self {
+        Self { volume }
+    }
 }
 
-impl fmt::Debug for CTVolume {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("CTVolume")
-            .field("dimensions", &self.dimensions)
-            .field("voxel_spacing", &self.voxel_spacing)
-            .field("voxel_data", &format!("{} slices", self.voxel_data.len()))
-            .finish()
+// Opaque handle passing a float volume to JavaScript
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub struct FloatVolumeHandle {
+    pub(crate) volume: FloatVolume,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<FloatVolume> for FloatVolumeHandle {
+    fn from(volume: FloatVolume) -> Self {
+        Self { volume }
     }
 }
 
@@ -29,7 +43,31 @@ pub trait CTVolumeGenerator {
     fn generate_ct_volume(&self, image_series_id: &str) -> Result<CTVolume>;
 }
 
+// A set of volumes sharing a common reference (world) base
 pub struct Geometry {
     volumes: Vec<CTVolume>,
-    base: crate::coordinates::Matrix4x4<f32>,
-}
\ No newline at end of file
+    base: Matrix4x4<f64>, // Maps world coordinates to patient coordinates (LPS, mm)
+}
+
+impl Geometry {
+    pub fn new(base: Matrix4x4<f64>) -> Self {
+        Self {
+            volumes: Vec::new(),
+            base,
+        }
+    }
+
+    pub fn add_volume(&mut self, volume: CTVolume) {
+        self.volumes.push(volume);
+    }
+
+    pub fn volumes(&self) -> &[CTVolume] {
+        &self.volumes
+    }
+
+    // Maps voxel indices of the volume at `index` to world coordinates of this geometry
+    pub fn index_to_world(&self, index: usize) -> Option<Matrix4x4<f64>> {
+        let volume = self.volumes.get(index)?;
+        Some(self.base.inv()?.multiply(&volume.index_to_patient))
+    }
+}
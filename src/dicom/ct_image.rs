@@ -1,140 +1,215 @@
-use super::dicom_helper::get_value;
+use super::dicom_helper::{get_value, get_values, MissingAttribute};
+use super::multi_frame::impl_frames_from_object;
+use super::pixel_data::{is_big_endian, read_pixel_data, stored_values, transfer_syntax_uid};
 use crate::define_dicom_struct;
-use anyhow::{anyhow, Result, Context};
+use anyhow::{Result, Context};
 use dicom_object::{FileDicomObject, InMemDicomObject};
-use std::borrow::Cow;
 
 define_dicom_struct!(CTImage, {
     (uid, String, "(0008,0018) SOPInstanceUID", false),              // Unique identifier for the image
     (series_uid, String, "(0020,000E) SeriesInstanceUID", false),  // SeriesID is required
+    (frame_number, u32, derived, true),                            // 1-based frame of an Enhanced multi-frame object (Optional)
+    (instance_number, i32, "(0020,0013) InstanceNumber", true),   // Position of the image in its series (Optional)
+    (acquisition_number, i32, "(0020,0012) AcquisitionNumber", true), // Acquisition the image belongs to (Optional)
     (rows, u16, "(0028,0010) Rows", false),                         // Rows (Mandatory)
     (columns, u16, "(0028,0011) Columns", false),                    // Columns (Mandatory)
     (pixel_spacing, (f32, f32), "(0028,0030) PixelSpacing", true),   // PixelSpacing (Optional)
     (slice_thickness, f32, "(0018,0050) SliceThickness", true),      // SliceThickness (Optional)
     (spacing_between_slices, f32, "(0018,0088) SpacingBetweenSlices", true), // SpacingBetweenSlices (Optional)
+    (gantry_detector_tilt, f32, "(0018,1120) GantryDetectorTilt", true), // Gantry tilt in degrees (Optional)
+    (kvp, f32, "(0018,0060) KVP", true),                             // Peak kilovoltage of the tube (Optional)
+    (convolution_kernel, String, "(0018,1210) ConvolutionKernel", true), // Reconstruction kernel (Optional)
     (image_position_patient, (f32, f32, f32), "(0020,0032) ImagePositionPatient", true), // ImagePositionPatient (Optional)
     (image_orientation_patient, (f32, f32, f32, f32, f32, f32), "(0020,0037) ImageOrientationPatient", true), // ImageOrientationPatient (Optional)
     (rescale_slope, f32, "(0028,1053) RescaleSlope", true),          // RescaleSlope (Optional)
     (rescale_intercept, f32, "(0028,1052) RescaleIntercept", true),  // RescaleIntercept (Optional)
     (window_center, f32, "(0028,1050) WindowCenter", true),          // WindowCenter (Optional)
     (window_width, f32, "(0028,1051) WindowWidth", true),            // WindowWidth (Optional)
+    (bits_allocated, u16, "(0028,0100) BitsAllocated", false),       // BitsAllocated (Mandatory)
+    (bits_stored, u16, "(0028,0101) BitsStored", false),             // BitsStored (Mandatory)
+    (high_bit, u16, "(0028,0102) HighBit", false),                   // HighBit (Mandatory)
     (pixel_representation, u16, "(0028,0103) PixelRepresentation", false), // Pixel Representation (Mandatory, but important for interpretation)
+    (transfer_syntax_uid, String, "(0002,0010) TransferSyntaxUID", false), // Transfer syntax the file was encoded with
     (pixel_data, Vec<u8>, "(7FE0,0010) PixelData", false)            // PixelData (Mandatory)
 });
 
+impl_frames_from_object!(CTImage);
+
 impl CTImage {
     // Function to parse the DICOM file and generate the CTImage structure
     pub fn from_bytes(dicom_data: &[u8]) -> Result<CTImage> {
         // Parse the DICOM file into a `FileDicomObject`
         let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
 
+    // Function to generate the CTImage structure from an already parsed DICOM file
+    pub fn from_object(obj: &FileDicomObject<InMemDicomObject>) -> Result<CTImage> {
         // Populate fields based on DICOM tags
         Ok(CTImage {
-            uid: get_value::<String>(&obj, "SOPInstanceUID")
-                .ok_or_else(|| anyhow!("Missing SOPInstanceUID"))?,
-            series_uid: get_value::<String>(&obj, "SeriesInstanceUID")
-                .ok_or_else(|| anyhow!("Missing SeriesInstanceUID"))?,
-            rows: get_value::<u16>(&obj, "Rows").ok_or_else(|| anyhow!("Missing Rows"))?,
-            columns: get_value::<u16>(&obj, "Columns").ok_or_else(|| anyhow!("Missing Columns"))?,
-            pixel_spacing: {
-                let spacing = get_value::<String>(&obj, "PixelSpacing");
-                spacing.and_then(|v| {
-                    let vals: Vec<f32> = v
-                        .split('\\')
-                        .filter_map(|s| s.parse::<f32>().ok())
-                        .collect();
-                    if vals.len() == 2 {
-                        Some((vals[0], vals[1]))
-                    } else {
-                        None
-                    }
-                })
-            },
-            slice_thickness: get_value::<f32>(&obj, "SliceThickness"),
-            spacing_between_slices: get_value::<f32>(&obj, "SpacingBetweenSlices"),
-            image_position_patient: {
-                let pos = get_value::<String>(&obj, "ImagePositionPatient");
-                pos.and_then(|v| {
-                    let vals: Vec<f32> = v
-                        .split('\\')
-                        .filter_map(|s| s.parse::<f32>().ok())
-                        .collect();
-                    if vals.len() == 3 {
-                        Some((vals[0], vals[1], vals[2]))
-                    } else {
-                        None
-                    }
-                })
-            },
-            image_orientation_patient: {
-                let orientation = get_value::<String>(&obj, "ImageOrientationPatient");
-                orientation.and_then(|v| {
-                    let vals: Vec<f32> = v
-                        .split('\\')
-                        .filter_map(|s| s.parse::<f32>().ok())
-                        .collect();
-                    if vals.len() == 6 {
-                        Some((vals[0], vals[1], vals[2], vals[3], vals[4], vals[5]))
-                    } else {
-                        None
-                    }
-                })
-            },
-            rescale_slope: get_value::<f32>(&obj, "RescaleSlope"),
-            rescale_intercept: get_value::<f32>(&obj, "RescaleIntercept"),
-            window_center: get_value::<f32>(&obj, "WindowCenter"),
-            window_width: get_value::<f32>(&obj, "WindowWidth"),
-            pixel_representation: get_value::<u16>(&obj, "PixelRepresentation")
-                .ok_or_else(|| anyhow!("Missing PixelRepresentation"))?,
-            pixel_data: obj.element_by_name("PixelData")?.to_bytes()?.to_vec(), // Pixel data is mandatory
+            uid: get_value::<String>(obj, "SOPInstanceUID")
+                .ok_or(MissingAttribute("SOPInstanceUID"))?,
+            series_uid: get_value::<String>(obj, "SeriesInstanceUID")
+                .ok_or(MissingAttribute("SeriesInstanceUID"))?,
+            frame_number: None,
+            instance_number: get_value::<i32>(obj, "InstanceNumber"),
+            acquisition_number: get_value::<i32>(obj, "AcquisitionNumber"),
+            rows: get_value::<u16>(obj, "Rows").ok_or(MissingAttribute("Rows"))?,
+            columns: get_value::<u16>(obj, "Columns").ok_or(MissingAttribute("Columns"))?,
+            pixel_spacing: get_values::<f32>(obj, "PixelSpacing")
+                .and_then(|v| match v[..] { [r, c] => Some((r, c)), _ => None }),
+            slice_thickness: get_value::<f32>(obj, "SliceThickness"),
+            spacing_between_slices: get_value::<f32>(obj, "SpacingBetweenSlices"),
+            gantry_detector_tilt: get_value::<f32>(obj, "GantryDetectorTilt"),
+            kvp: get_value::<f32>(obj, "KVP"),
+            convolution_kernel: get_value::<String>(obj, "ConvolutionKernel"),
+            image_position_patient: get_values::<f32>(obj, "ImagePositionPatient")
+                .and_then(|v| match v[..] { [x, y, z] => Some((x, y, z)), _ => None }),
+            image_orientation_patient: get_values::<f32>(obj, "ImageOrientationPatient")
+                .and_then(|v| match v[..] { [a, b, c, d, e, f] => Some((a, b, c, d, e, f)), _ => None }),
+            rescale_slope: get_value::<f32>(obj, "RescaleSlope"),
+            rescale_intercept: get_value::<f32>(obj, "RescaleIntercept"),
+            window_center: get_value::<f32>(obj, "WindowCenter"),
+            window_width: get_value::<f32>(obj, "WindowWidth"),
+            bits_allocated: get_value::<u16>(obj, "BitsAllocated")
+                .ok_or(MissingAttribute("BitsAllocated"))?,
+            bits_stored: get_value::<u16>(obj, "BitsStored")
+                .ok_or(MissingAttribute("BitsStored"))?,
+            high_bit: get_value::<u16>(obj, "HighBit").ok_or(MissingAttribute("HighBit"))?,
+            pixel_representation: get_value::<u16>(obj, "PixelRepresentation")
+                .ok_or(MissingAttribute("PixelRepresentation"))?,
+            transfer_syntax_uid: transfer_syntax_uid(obj),
+            pixel_data: read_pixel_data(obj)?, // Decoded to native samples; empty for header-only reads
         })
     }
 
-    pub fn get_pixel_data(&self) -> Result<Vec<i16>> {
-        let pixel_data = &self.pixel_data; // Original pixel data as Vec<u8>
-        let pixel_representation = self.pixel_representation;
+    // Stored pixel values, masked to BitsStored at HighBit and sign-extended for PixelRepresentation 1
+    pub fn get_stored_values(&self) -> Result<Vec<i32>> {
+        self.get_stored_values_from(&self.pixel_data)
+    }
+
+    // Stored pixel values of native samples kept apart from the image, e.g. in the pixel cache
+    pub fn get_stored_values_from(&self, pixel_data: &[u8]) -> Result<Vec<i32>> {
+        stored_values(
+            pixel_data,
+            self.bits_allocated,
+            self.bits_stored,
+            self.high_bit,
+            self.pixel_representation,
+            is_big_endian(&self.transfer_syntax_uid),
+        )
+    }
+
+    // Pixel values after applying rescale slope and intercept, in a type wide enough for any
+    // modality LUT output (e.g. 16-bit unsigned data with a negative intercept)
+    pub fn get_pixel_data_i32(&self) -> Result<Vec<i32>> {
+        self.get_pixel_data_i32_from(&self.pixel_data)
+    }
+
+    pub fn get_pixel_data_i32_from(&self, pixel_data: &[u8]) -> Result<Vec<i32>> {
         let rescale_slope = self.rescale_slope.unwrap_or(1.0); // Default to 1.0 if not provided
         let rescale_intercept = self.rescale_intercept.unwrap_or(0.0); // Default to 0.0 if not provided
 
+        let stored = self
+            .get_stored_values_from(pixel_data)
+            .context("Failed to process pixel data from DICOM file")?;
+
         // Define a small epsilon for float comparison
         const EPSILON: f32 = 1e-6;
 
-        // Case 1: No rescaling and pixel data is signed (pixel_representation == 1)
-        if (rescale_slope - 1.0).abs() < EPSILON && (rescale_intercept - 0.0).abs() < EPSILON && pixel_representation == 1 {
-            // No transformation is needed, return the data as borrowed
-            let data: Vec<i16> = pixel_data
-                .chunks_exact(2)
-                .map(|chunk| {
-                    if chunk.len() != 2 {
-                        anyhow::bail!("Invalid pixel data chunk size: expected 2 bytes, found {}", chunk.len());
-                    }
-                    Ok(i16::from_ne_bytes([chunk[0], chunk[1]])) // Example for 16-bit signed
-                })
-                .collect::<Result<_>>()
-                .context("Failed to process pixel data from DICOM file")?;
-
-            return Ok(data); 
+        // No rescaling needed, the stored values are the output values
+        if (rescale_slope - 1.0).abs() < EPSILON && rescale_intercept.abs() < EPSILON {
+            return Ok(stored);
         }
 
-        // Case 2: Transformation required (rescale or pixel_representation is different)
-        let transformed_data: Vec<i16> = pixel_data
-            .chunks_exact(2)
-            .map(|chunk| {
-                if chunk.len() != 2 {
-                    anyhow::bail!("Invalid pixel data chunk size: expected 2 bytes, found {}", chunk.len());
-                }
-                let raw_value = match pixel_representation {
-                    0 => u16::from_ne_bytes([chunk[0], chunk[1]]) as i16, // Unsigned to signed conversion
-                    1 => i16::from_ne_bytes([chunk[0], chunk[1]]),         // Already signed
-                    _ => anyhow::bail!("Unsupported pixel representation: {}", pixel_representation),
-                };
-                // Apply rescale slope and intercept
-                let transformed_value = (raw_value as f32) * rescale_slope + rescale_intercept;
-                Ok(transformed_value.round() as i16)
-            })
-            .collect::<Result<_>>()
+        // Apply rescale slope and intercept in double precision to keep 32-bit values exact
+        let slope = rescale_slope as f64;
+        let intercept = rescale_intercept as f64;
+        Ok(stored
+            .into_iter()
+            .map(|v| (v as f64 * slope + intercept).round() as i32)
+            .collect())
+    }
+
+    // Whether every rescaled value this image can hold is representable as `i16`
+    pub fn fits_in_i16(&self) -> bool {
+        let slope = self.rescale_slope.unwrap_or(1.0) as f64;
+        let intercept = self.rescale_intercept.unwrap_or(0.0) as f64;
+        let (min, max) = if self.pixel_representation == 1 {
+            (-(1i64 << (self.bits_stored.max(1) - 1)), (1i64 << (self.bits_stored.max(1) - 1)) - 1)
+        } else {
+            (0, (1i64 << self.bits_stored.min(32)) - 1)
+        };
+        let a = min as f64 * slope + intercept;
+        let b = max as f64 * slope + intercept;
+        let range = i16::MIN as f64..=i16::MAX as f64;
+        range.contains(&a.round()) && range.contains(&b.round())
+    }
+
+    // Pixel values after applying rescale slope and intercept, as `i16`.
+    // Values outside the `i16` range are clamped rather than wrapped; use `get_pixel_data_i32`
+    // when `fits_in_i16` is false and the full range matters.
+    pub fn get_pixel_data(&self) -> Result<Vec<i16>> {
+        self.get_pixel_data_from(&self.pixel_data)
+    }
+
+    pub fn get_pixel_data_from(&self, pixel_data: &[u8]) -> Result<Vec<i16>> {
+        let data = self
+            .get_pixel_data_i32_from(pixel_data)
             .context("Failed to transform pixel data with rescale slope and intercept")?;
+        Ok(data
+            .into_iter()
+            .map(|v| v.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::test_util::test_ct_image;
+
+    fn test_image(bits_allocated: u16, bits_stored: u16, high_bit: u16, pixel_representation: u16, pixel_data: Vec<u8>) -> CTImage {
+        CTImage {
+            columns: (pixel_data.len() / (bits_allocated as usize / 8)) as u16,
+            bits_allocated,
+            bits_stored,
+            high_bit,
+            pixel_representation,
+            pixel_data,
+            ..test_ct_image()
+        }
+    }
+
+    #[test]
+    fn test_unsigned_12_bit_with_intercept() {
+        // 4095 stored in 12 bits with garbage in the overlay bits above HighBit
+        let mut img = test_image(16, 12, 11, 0, vec![0xFF, 0xFF, 0x00, 0x00]);
+        img.rescale_intercept = Some(-1024.0);
+        assert_eq!(img.get_pixel_data().unwrap(), vec![3071, -1024]);
+    }
+
+    #[test]
+    fn test_signed_sign_extension() {
+        // 0x0FFF is -1 in 12-bit two's complement
+        let img = test_image(16, 12, 11, 1, vec![0xFF, 0x0F, 0x00, 0x08]);
+        assert_eq!(img.get_stored_values().unwrap(), vec![-1, -2048]);
+    }
+
+    #[test]
+    fn test_big_endian_and_wide_values() {
+        let mut img = test_image(16, 16, 15, 0, vec![0xFF, 0xFE, 0x00, 0x01]);
+        img.transfer_syntax_uid = "1.2.840.10008.1.2.2".to_string();
+        img.rescale_intercept = Some(-1024.0);
+        assert!(!img.fits_in_i16());
+        assert_eq!(img.get_pixel_data_i32().unwrap(), vec![65534 - 1024, 1 - 1024]);
+        assert_eq!(img.get_pixel_data().unwrap(), vec![i16::MAX, -1023]);
+    }
 
-        Ok(transformed_data)
+    #[test]
+    fn test_format_tags_marks_derived_fields() {
+        let tags = test_image(16, 16, 15, 0, vec![0, 0]).format_tags();
+        assert!(tags.contains("frame_number (derived): None (Optional)"));
+        assert!(tags.contains("(0028,0010) Rows: 1"));
     }
 }
@@ -1,14 +1,16 @@
 use dicom_object::InMemDicomObject;
+use std::fmt;
 
 // #[cfg(target_arch = "wasm32")]
 use paste::*;
 
 #[macro_export]
 macro_rules! define_dicom_struct {
-    // Main macro to define a struct with fields, types, DICOM tags, and optionality
-    ($name:ident, { $(($field_name:ident, $field_type:ty, $dicom_tag:expr, $is_optional:tt)),* $(,)? }) => {
+    // Main macro to define a struct with fields, types, DICOM tags, and optionality.
+    // Fields computed while parsing rather than read from an attribute use `derived` as tag.
+    ($name:ident, { $(($field_name:ident, $field_type:ty, $dicom_tag:tt, $is_optional:tt)),* $(,)? }) => {
         // #[cfg_attr(target_arch = "wasm32", wasm_bindgen)] // Allow use in WASM
-        #[derive(Debug, Clone, serde::Serialize)]
+        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
         pub struct $name {
             // Generate struct fields based on optionality
             $(
@@ -23,6 +25,7 @@ macro_rules! define_dicom_struct {
             // Constructor function to create struct instances
             // #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
             // #[cfg(not(target_arch = "wasm32"))]
+            #[allow(clippy::too_many_arguments)]
             pub fn new($($field_name: $crate::define_dicom_struct!(@constructor_type $field_type, $is_optional)),*) -> Self {
                 $name {
                     $(
@@ -36,7 +39,14 @@ macro_rules! define_dicom_struct {
             pub fn format_tags(&self) -> String {
                 let mut result = String::new();
                 $(
-                    $crate::define_dicom_struct!(@to_string $field_name, $field_type, $dicom_tag, $is_optional, self, result);
+                    $crate::define_dicom_struct!(
+                        @to_string $field_name,
+                        $field_type,
+                        $crate::define_dicom_struct!(@tag $field_name, $dicom_tag),
+                        $is_optional,
+                        self,
+                        result
+                    );
                 )*
                 result
             }
@@ -61,6 +71,14 @@ macro_rules! define_dicom_struct {
         // }
     };
 
+    // Helper rule naming the source of a field in `format_tags`
+    (@tag $field_name:ident, derived) => {
+        concat!(stringify!($field_name), " (derived)")
+    };
+    (@tag $field_name:ident, $dicom_tag:literal) => {
+        $dicom_tag
+    };
+
     // Helper rule to wrap type in Option if the field is optional
     (@optional $field_type:ty, true) => {
         Option<$field_type>
@@ -116,6 +134,26 @@ macro_rules! define_dicom_struct {
     };
 }
 
+/// A required attribute absent from a data set, named by its keyword.
+///
+/// Entity builders return it so the ingest report can tell incomplete files from invalid ones
+/// without depending on the wording of error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingAttribute(pub &'static str);
+
+impl fmt::Display for MissingAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Missing {}", self.0)
+    }
+}
+
+impl std::error::Error for MissingAttribute {}
+
+// Whether an error was caused by a missing required attribute, under any added context
+pub fn is_missing_attribute(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<MissingAttribute>())
+}
+
 // Helper function to safely retrieve a tag value and convert it to a type T
 pub fn get_value<T>(obj: &InMemDicomObject, tag: &str) -> Option<T>
 where
@@ -126,3 +164,45 @@ where
         .and_then(|e| e.value().to_str().ok())
         .and_then(|v| v.parse::<T>().ok())
 }
+
+// Helper function to retrieve a multi-valued (backslash separated) tag as a vector of T
+pub fn get_values<T>(obj: &InMemDicomObject, tag: &str) -> Option<Vec<T>>
+where
+    T: std::str::FromStr,
+{
+    obj.element_by_name(tag)
+        .ok()
+        .and_then(|e| e.value().to_str().ok())
+        .and_then(|v| v.split('\\').map(|s| s.trim().parse::<T>().ok()).collect())
+}
+
+// Helper function to retrieve the first item of a sequence tag
+pub fn get_first_item<'a>(obj: &'a InMemDicomObject, tag: &str) -> Option<&'a InMemDicomObject> {
+    obj.element_by_name(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .and_then(|items| items.first())
+}
+
+// Helper function to retrieve all items of a sequence tag, empty when the tag is absent
+pub fn get_items<'a>(obj: &'a InMemDicomObject, tag: &str) -> &'a [InMemDicomObject] {
+    obj.element_by_name(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .unwrap_or(&[])
+}
+
+// Parse a DICOM TM value (HHMMSS.FFFFFF, components after HH optional) into seconds since midnight
+pub fn parse_time(value: &str) -> Option<f64> {
+    let value = value.trim().replace(':', "");
+    let (hms, frac) = match value.split_once('.') {
+        Some((hms, frac)) => (hms.to_string(), format!("0.{}", frac)),
+        None => (value.clone(), "0".to_string()),
+    };
+    if hms.len() < 2 || hms.len() % 2 != 0 || !hms.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize| hms.get(i..i + 2).map_or(Some(0.0), |s| s.parse::<f64>().ok());
+    let seconds = field(0)? * 3600.0 + field(2)? * 60.0 + field(4)?;
+    Some(seconds + frac.parse::<f64>().ok()?)
+}
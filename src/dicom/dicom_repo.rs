@@ -1,21 +1,46 @@
 use super::ct_image::CTImage;
+use super::image_instance::{DicomImage, ImageInstance};
 use super::image_series::ImageSeries;
 use super::patient::Patient;
+use super::pixel_cache::{load_pixel_data, read_header, PixelCache, DEFAULT_PIXEL_CACHE_BUDGET};
+use super::query::{image_order, study_order, series_order, Links};
+use super::series_geometry::{
+    dot, mean_slice_spacing, median_slice_spacing, norm, resample_slices, shear_angle, shift_slice,
+    sort_along_normal, stack_shear, sub, tilt_mismatch, to_tuple, to_vec3, SeriesGeometryReport,
+    SliceOrientation, Vec3, VolumeAssemblyOptions, DUPLICATE_TOLERANCE, SHEAR_TOLERANCE,
+};
+use super::query::{RepoQuery, SeriesMatch};
+use super::dvh::{Dvh, DvhOptions};
+use super::roi_mask::{MaskVolume, RasterizeOptions};
+use super::rt_dose::{DoseVolume, RTDose};
+use super::rt_plan::{PlanConsistencyReport, RTPlan};
+use super::rt_struct::StructureSet;
 use super::studyset::StudySet;
-use crate::ct_volume::CTVolume;
+use crate::ct_volume::{CTVolume, FloatVolume};
+use crate::volume::{index_to_patient_matrix, Volume, Voxel};
 use crate::CTVolumeGenerator;
 use anyhow::{anyhow, Result};
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[derive(Debug, Clone)]
 pub struct DicomRepo {
-    patients: HashMap<String, Patient>, // Map of patient ID to Patient
-    study_sets: HashMap<String, StudySet>, // Map of study ID to StudySet
-    image_series: HashMap<String, ImageSeries>, // Map of series ID to ImageSeries
-    ct_images: HashMap<String, CTImage>, // Map of image ID to CTImage
+    pub(crate) patients: HashMap<String, Patient>, // Map of patient ID to Patient
+    pub(crate) study_sets: HashMap<String, StudySet>, // Map of study ID to StudySet
+    pub(crate) image_series: HashMap<String, ImageSeries>, // Map of series ID to ImageSeries
+    pub(crate) images: HashMap<String, ImageInstance>, // Map of image ID to image of any supported modality
+    pub(crate) structure_sets: HashMap<String, StructureSet>, // Map of SOPInstanceUID to RT structure set
+    pub(crate) doses: HashMap<String, RTDose>, // Map of SOPInstanceUID to RT dose grid
+    pub(crate) plans: HashMap<String, RTPlan>, // Map of SOPInstanceUID to RT plan
+    pub(crate) pixel_sources: HashMap<String, PathBuf>, // Map of image or dose ID to the file it was read from; header-only entities load their pixel data from it
+    pub(crate) studies_by_patient: Links, // Secondary index: patient ID to study UIDs
+    pub(crate) series_by_study: Links,    // Secondary index: study UID to series UIDs
+    pub(crate) images_by_series: Links,   // Secondary index: series UID to image keys
+    pub(crate) unread_images: Links,      // Series UID to the UIDs of header-less images listed by a DICOMDIR, read from their pixel source on demand
+    pixel_cache: Arc<Mutex<PixelCache>>, // Pixel data loaded on demand, shared between clones
 }
 
 impl DicomRepo {
@@ -25,7 +50,16 @@ impl DicomRepo {
             patients: HashMap::new(),
             study_sets: HashMap::new(),
             image_series: HashMap::new(),
-            ct_images: HashMap::new(),
+            images: HashMap::new(),
+            structure_sets: HashMap::new(),
+            doses: HashMap::new(),
+            plans: HashMap::new(),
+            pixel_sources: HashMap::new(),
+            studies_by_patient: Links::default(),
+            series_by_study: Links::default(),
+            images_by_series: Links::default(),
+            unread_images: Links::default(),
+            pixel_cache: Arc::new(Mutex::new(PixelCache::new(DEFAULT_PIXEL_CACHE_BUDGET))),
         }
     }
 
@@ -36,17 +70,504 @@ impl DicomRepo {
 
     // Add or update a study
     pub fn add_study(&mut self, study: StudySet) {
-        self.study_sets.insert(study.uid.clone(), study);
+        self.studies_by_patient.link(&study.patient_id, &study.uid);
+        if let Some(old) = self.study_sets.insert(study.uid.clone(), study) {
+            self.relink_study(&old);
+        }
     }
 
     // Add or update an image series
     pub fn add_image_series(&mut self, series: ImageSeries) {
-        self.image_series.insert(series.uid.clone(), series);
+        self.series_by_study.link(&series.study_uid, &series.uid);
+        if let Some(old) = self.image_series.insert(series.uid.clone(), series) {
+            let new_study = &self.image_series[&old.uid].study_uid;
+            if *new_study != old.study_uid {
+                self.series_by_study.unlink(&old.study_uid, &old.uid);
+            }
+        }
+    }
+
+    // Add or update an image of any supported modality
+    pub fn add_image(&mut self, image: ImageInstance) {
+        let key = image.key();
+        self.unread_images.unlink(image.series_uid(), image.uid());
+        self.images_by_series.link(image.series_uid(), &key);
+        if let Some(old) = self.images.insert(key.clone(), image) {
+            if old.series_uid() != self.images[&key].series_uid() {
+                self.images_by_series.unlink(old.series_uid(), &key);
+            }
+        }
+    }
+
+    // Drop the link of a replaced study to its former patient
+    fn relink_study(&mut self, old: &StudySet) {
+        if self.study_sets[&old.uid].patient_id != old.patient_id {
+            self.studies_by_patient.unlink(&old.patient_id, &old.uid);
+        }
+    }
+
+    // Add or update an RT structure set
+    pub fn add_structure_set(&mut self, structure_set: StructureSet) {
+        self.structure_sets.insert(structure_set.uid.clone(), structure_set);
+    }
+
+    // Add or update an RT dose grid
+    pub fn add_dose(&mut self, dose: RTDose) {
+        self.doses.insert(dose.uid.clone(), dose);
+    }
+
+    // Add or update an RT plan
+    pub fn add_plan(&mut self, plan: RTPlan) {
+        self.plans.insert(plan.uid.clone(), plan);
     }
 
     // Add or update a CT image
     pub fn add_ct_image(&mut self, image: CTImage) {
-        self.ct_images.insert(image.uid.clone(), image);
+        self.add_image(ImageInstance::CT(image));
+    }
+
+    // Record the file an image was read from, to load the pixel data of header-only images
+    pub fn add_pixel_source(&mut self, image_key: String, path: PathBuf) {
+        self.pixel_sources.insert(image_key, path);
+    }
+
+    // Register an image listed by a DICOMDIR without reading its file; `read_series` reads the
+    // headers of the listed images once the series is opened
+    pub fn add_unread_image(&mut self, series_uid: &str, image_uid: &str, path: PathBuf) {
+        if self.has_image(series_uid, image_uid) {
+            return;
+        }
+        self.unread_images.link(series_uid, image_uid);
+        self.pixel_sources.insert(image_uid.to_string(), path);
+    }
+
+    // Whether an image with this SOPInstanceUID is stored in a series, either as a single image or
+    // as the frames of a multi-frame image, keyed `uid#frame`
+    fn has_image(&self, series_uid: &str, image_uid: &str) -> bool {
+        self.images.contains_key(image_uid)
+            || self
+                .images_by_series
+                .children(series_uid)
+                .any(|key| self.images.get(key).is_some_and(|image| image.uid() == image_uid))
+    }
+
+    /// Merges the entities of another repository into this one, e.g. files parsed after the
+    /// repository was built.
+    ///
+    /// Entities present in both are replaced by those of `other`; cached pixel data of
+    /// replaced images is dropped so it is reloaded from the new source.
+    pub fn merge(&mut self, mut other: DicomRepo) {
+        self.patients.extend(other.patients);
+        for study in other.study_sets.into_values() {
+            self.add_study(study);
+        }
+        for series in other.image_series.into_values() {
+            self.add_image_series(series);
+        }
+        // A replaced image loses all of its frames, including those its replacement no longer has;
+        // the replacement may not come from the file of its predecessor either
+        let replaced: HashSet<String> = other.images.values().map(|image| image.uid().to_string()).collect();
+        let stale: Vec<String> = self
+            .images
+            .iter()
+            .filter(|(_, image)| replaced.contains(image.uid()))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            self.remove_image(&key);
+        }
+        for image in other.images.into_values() {
+            self.add_image(image);
+        }
+        self.structure_sets.extend(other.structure_sets);
+        for (uid, dose) in other.doses {
+            self.pixel_sources.remove(&uid);
+            self.doses.insert(uid, dose);
+        }
+        self.plans.extend(other.plans);
+        for series_uid in other.unread_images.parents() {
+            for uid in other.unread_images.children(series_uid) {
+                if self.has_image(series_uid, uid) {
+                    other.pixel_sources.remove(uid);
+                } else {
+                    self.unread_images.link(series_uid, uid);
+                }
+            }
+        }
+        self.pixel_sources.extend(other.pixel_sources);
+    }
+
+    // Remove an image with its pixel source and cached pixel data
+    pub fn remove_image(&mut self, image_key: &str) -> Option<ImageInstance> {
+        self.pixel_sources.remove(image_key);
+        self.lock_pixel_cache().remove(image_key);
+        let image = self.images.remove(image_key)?;
+        self.images_by_series.unlink(image.series_uid(), image_key);
+        Some(image)
+    }
+
+    // Remove a series and all of its images; the study and patient are kept
+    pub fn remove_series(&mut self, series_uid: &str) -> Option<ImageSeries> {
+        let keys: Vec<String> = self.images_by_series.children(series_uid).cloned().collect();
+        for key in keys {
+            self.remove_image(&key);
+        }
+        let unread: Vec<String> = self.unread_images.children(series_uid).cloned().collect();
+        for uid in unread {
+            self.unread_images.unlink(series_uid, &uid);
+            self.pixel_sources.remove(&uid);
+        }
+        let series = self.image_series.remove(series_uid)?;
+        self.series_by_study.unlink(&series.study_uid, series_uid);
+        Some(series)
+    }
+
+    // Remove an RT structure set
+    pub fn remove_structure_set(&mut self, uid: &str) -> Option<StructureSet> {
+        self.structure_sets.remove(uid)
+    }
+
+    // Remove an RT dose grid with its pixel source
+    pub fn remove_dose(&mut self, uid: &str) -> Option<RTDose> {
+        self.pixel_sources.remove(uid);
+        self.doses.remove(uid)
+    }
+
+    // Remove an RT plan
+    pub fn remove_plan(&mut self, uid: &str) -> Option<RTPlan> {
+        self.plans.remove(uid)
+    }
+
+    // Number of images stored for a series, including those listed by a DICOMDIR and not read yet
+    pub fn series_image_count(&self, series_uid: &str) -> usize {
+        self.images_by_series.count(series_uid) + self.unread_images.count(series_uid)
+    }
+
+    // Number of images of a series listed by a DICOMDIR whose files are not read yet
+    pub fn unread_image_count(&self, series_uid: &str) -> usize {
+        self.unread_images.count(series_uid)
+    }
+
+    /// Reads the headers of the images of a series that were listed by a DICOMDIR, so the
+    /// series can be browsed image by image and stacked into a volume.
+    ///
+    /// Only the attributes before the pixel data are read; the pixel data is loaded on demand
+    /// like that of header-only images. Returns the number of images (frames) added.
+    ///
+    /// # Errors
+    /// Returns an error if a listed file cannot be read or holds no usable image; the images
+    /// read before it are kept and the rest stay unread.
+    pub fn read_series(&mut self, series_uid: &str) -> Result<usize> {
+        let uids: Vec<String> = self.unread_images.children(series_uid).cloned().collect();
+        let mut added = 0;
+        for uid in uids {
+            let Some(path) = self.pixel_sources.get(&uid).cloned() else {
+                self.unread_images.unlink(series_uid, &uid);
+                continue;
+            };
+            let header = read_header(&path)?;
+            let frames = ImageInstance::frames_from_object(&header)
+                .map_err(|err| anyhow!("Failed to read image {} from {}: {:#}", uid, path.display(), err))?;
+            // Frames of a multi-frame file are keyed by frame, not by the listed UID
+            self.unread_images.unlink(series_uid, &uid);
+            self.pixel_sources.remove(&uid);
+            for image in frames {
+                self.pixel_sources.insert(image.key(), path.clone());
+                self.add_image(image);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    // Studies of a patient, ordered by study date
+    pub(crate) fn studies_of(&self, patient_id: &str) -> Vec<&StudySet> {
+        let mut studies: Vec<&StudySet> = self
+            .studies_by_patient
+            .children(patient_id)
+            .filter_map(|uid| self.study_sets.get(uid))
+            .collect();
+        studies.sort_by(|a, b| study_order(a, b));
+        studies
+    }
+
+    // Series of a study, ordered by series number
+    pub(crate) fn series_of(&self, study_uid: &str) -> Vec<&ImageSeries> {
+        let mut series: Vec<&ImageSeries> = self
+            .series_by_study
+            .children(study_uid)
+            .filter_map(|uid| self.image_series.get(uid))
+            .collect();
+        series.sort_by(|a, b| series_order(a, b));
+        series
+    }
+
+    // Images of a series, ordered by instance and frame number
+    pub(crate) fn images_of(&self, series_uid: &str) -> Vec<&ImageInstance> {
+        let mut images: Vec<&ImageInstance> = self
+            .images_by_series
+            .children(series_uid)
+            .filter_map(|key| self.images.get(key))
+            .collect();
+        images.sort_by(|a, b| image_order(a, b));
+        images
+    }
+
+    // Series sharing a frame of reference, ordered by study, then series number
+    pub(crate) fn series_in_frame_of_reference(&self, frame_of_reference_uid: &str) -> Vec<&ImageSeries> {
+        let mut series: Vec<&ImageSeries> = self
+            .image_series
+            .values()
+            .filter(|series| series.frame_of_reference_uid.as_deref() == Some(frame_of_reference_uid))
+            .collect();
+        series.sort_by(|a, b| a.study_uid.cmp(&b.study_uid).then_with(|| series_order(a, b)));
+        series
+    }
+
+    // Structure sets drawn on a series: those referencing it explicitly, or sharing its frame
+    // of reference; ordered by label
+    pub(crate) fn structure_sets_of(&self, series_uid: &str) -> Vec<&StructureSet> {
+        let frame_of_reference = self
+            .image_series
+            .get(series_uid)
+            .and_then(|series| series.frame_of_reference_uid.as_deref());
+        let mut structure_sets: Vec<&StructureSet> = self
+            .structure_sets
+            .values()
+            .filter(|set| {
+                set.referenced_series_uid.as_deref() == Some(series_uid)
+                    || (frame_of_reference.is_some() && set.frame_of_reference_uid.as_deref() == frame_of_reference)
+            })
+            .collect();
+        structure_sets.sort_by(|a, b| a.label.cmp(&b.label).then_with(|| a.uid.cmp(&b.uid)));
+        structure_sets
+    }
+
+    // Image series a structure set was drawn on: the referenced series when it is loaded,
+    // otherwise a series of its frame of reference, preferring CT
+    pub(crate) fn referenced_series_of(&self, structure_set: &StructureSet) -> Option<&ImageSeries> {
+        if let Some(series) = structure_set
+            .referenced_series_uid
+            .as_ref()
+            .and_then(|uid| self.image_series.get(uid))
+        {
+            return Some(series);
+        }
+        let candidates = self.series_in_frame_of_reference(structure_set.frame_of_reference_uid.as_deref()?);
+        candidates
+            .iter()
+            .find(|series| series.modality == "CT")
+            .or(candidates.first())
+            .copied()
+    }
+
+    // Dose grids sharing the frame of reference of a series, ordered by UID
+    pub(crate) fn doses_of(&self, series_uid: &str) -> Vec<&RTDose> {
+        let Some(frame_of_reference) = self
+            .image_series
+            .get(series_uid)
+            .and_then(|series| series.frame_of_reference_uid.as_deref())
+        else {
+            return Vec::new();
+        };
+        let mut doses: Vec<&RTDose> = self
+            .doses
+            .values()
+            .filter(|dose| dose.frame_of_reference_uid.as_deref() == Some(frame_of_reference))
+            .collect();
+        doses.sort_by(|a, b| a.uid.cmp(&b.uid));
+        doses
+    }
+
+    // Plans sharing the frame of reference of a series, ordered by label
+    pub(crate) fn plans_of(&self, series_uid: &str) -> Vec<&RTPlan> {
+        let Some(frame_of_reference) = self
+            .image_series
+            .get(series_uid)
+            .and_then(|series| series.frame_of_reference_uid.as_deref())
+        else {
+            return Vec::new();
+        };
+        let mut plans: Vec<&RTPlan> = self
+            .plans
+            .values()
+            .filter(|plan| plan.frame_of_reference_uid.as_deref() == Some(frame_of_reference))
+            .collect();
+        plans.sort_by(|a, b| a.label.cmp(&b.label).then_with(|| a.uid.cmp(&b.uid)));
+        plans
+    }
+
+    // Axis-aligned box (mm) around the pixels of the images of a series
+    fn series_extent(&self, series_uid: &str) -> Option<(Vec3, Vec3)> {
+        let mut extent: Option<(Vec3, Vec3)> = None;
+        for image in self.images_of(series_uid) {
+            let image = image.as_image();
+            let (Some(position), Some(orientation), Some((row_spacing, column_spacing))) = (
+                image.image_position_patient(),
+                image.image_orientation_patient(),
+                image.pixel_spacing(),
+            ) else {
+                continue;
+            };
+            let (rx, ry, rz, cx, cy, cz) = orientation;
+            let width = (image.columns().max(1) - 1) as f64 * column_spacing as f64;
+            let height = (image.rows().max(1) - 1) as f64 * row_spacing as f64;
+            for (u, v) in [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)] {
+                let corner = [
+                    position.0 as f64 + u * rx as f64 + v * cx as f64,
+                    position.1 as f64 + u * ry as f64 + v * cy as f64,
+                    position.2 as f64 + u * rz as f64 + v * cz as f64,
+                ];
+                let (min, max) = extent.get_or_insert((corner, corner));
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(corner[axis]);
+                    max[axis] = max[axis].max(corner[axis]);
+                }
+            }
+        }
+        extent
+    }
+
+    // Check the frame of reference of a plan against its images, structure set and doses
+    pub(crate) fn plan_consistency(&self, plan_uid: &str) -> Result<PlanConsistencyReport> {
+        let plan = self
+            .plans
+            .get(plan_uid)
+            .ok_or_else(|| anyhow!("RTPlan with uid '{}' not found", plan_uid))?;
+        let frame_of_reference = plan.frame_of_reference_uid.as_deref();
+        let image_series = frame_of_reference
+            .map(|uid| self.series_in_frame_of_reference(uid))
+            .unwrap_or_default();
+
+        let structure_set = plan
+            .referenced_structure_set_uid
+            .as_ref()
+            .map(|uid| (uid, self.structure_sets.get(uid)));
+        let mut mismatched_doses: Vec<String> = self
+            .doses
+            .values()
+            .filter(|dose| {
+                dose.referenced_plan_uid.as_deref() == Some(plan_uid)
+                    && dose.frame_of_reference_uid.as_deref() != frame_of_reference
+            })
+            .map(|dose| dose.uid.clone())
+            .collect();
+        mismatched_doses.sort();
+
+        // Isocenters are checked against the CT the plan would be overlaid on
+        let extent = image_series
+            .iter()
+            .find(|series| series.modality == "CT")
+            .or(image_series.first())
+            .and_then(|series| self.series_extent(&series.uid));
+        let isocenters_outside_images = match extent {
+            Some((min, max)) => plan
+                .beams
+                .iter()
+                .filter(|beam| {
+                    beam.isocenter()
+                        .is_some_and(|p| (0..3).any(|axis| p[axis] < min[axis] || p[axis] > max[axis]))
+                })
+                .map(|beam| beam.number)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(PlanConsistencyReport {
+            plan_uid: plan.uid.clone(),
+            frame_of_reference_uid: plan.frame_of_reference_uid.clone(),
+            image_series: image_series.iter().map(|series| series.uid.clone()).collect(),
+            missing_structure_set: structure_set
+                .filter(|(_, set)| set.is_none())
+                .map(|(uid, _)| uid.clone()),
+            structure_set_frame_of_reference: structure_set
+                .and_then(|(_, set)| set)
+                .and_then(|set| set.frame_of_reference_uid.clone())
+                .filter(|uid| Some(uid.as_str()) != frame_of_reference),
+            mismatched_doses,
+            isocenters_outside_images,
+        })
+    }
+
+    // Build the volume of a dose grid, reading the grid from its file for header-only doses
+    fn dose_volume(&self, dose_uid: &str) -> Result<DoseVolume> {
+        let dose = self
+            .doses
+            .get(dose_uid)
+            .ok_or_else(|| anyhow!("RTDose with uid '{}' not found", dose_uid))?;
+        match self.pixel_sources.get(dose_uid) {
+            Some(path) if dose.pixel_data.is_empty() => RTDose::from_file(path)?.to_volume(),
+            _ => dose.to_volume(),
+        }
+    }
+
+    // Distinct acquisition numbers found among the images of a series, ascending
+    pub(crate) fn acquisitions_of(&self, series_uid: &str) -> Vec<i32> {
+        let acquisitions: BTreeSet<i32> = self
+            .images_by_series
+            .children(series_uid)
+            .filter_map(|key| self.images.get(key))
+            .filter_map(|image| image.acquisition_number())
+            .collect();
+        acquisitions.into_iter().collect()
+    }
+
+    // Set the memory budget (bytes) of the cache holding pixel data loaded on demand
+    pub fn set_pixel_cache_budget(&self, budget: usize) {
+        self.lock_pixel_cache().set_budget(budget);
+    }
+
+    // Bytes currently held by the pixel cache
+    pub fn pixel_cache_usage(&self) -> usize {
+        self.lock_pixel_cache().used()
+    }
+
+    // A poisoned lock only means a loader panicked; the cache itself stays consistent
+    fn lock_pixel_cache(&self) -> MutexGuard<'_, PixelCache> {
+        self.pixel_cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Pixel data of a header-only image, from the cache or loaded from its file; None for
+    // images that hold their pixel data or were not read from a file
+    fn load_pixel_data(&self, image: &ImageInstance) -> Result<Option<Arc<Vec<u8>>>> {
+        if image.has_pixel_data() {
+            return Ok(None);
+        }
+        let key = image.key();
+        let Some(path) = self.pixel_sources.get(&key) else {
+            return Ok(None);
+        };
+        if let Some(data) = self.lock_pixel_cache().get(&key) {
+            return Ok(Some(data));
+        }
+
+        // Loading decodes every frame of the file, so cache them all
+        let frames = load_pixel_data(path)?;
+        let mut cache = self.lock_pixel_cache();
+        let mut requested = None;
+        for (frame_key, data) in frames {
+            let data = Arc::new(data);
+            if frame_key == key {
+                requested = Some(Arc::clone(&data));
+            }
+            cache.insert(frame_key, data);
+        }
+        requested
+            .map(Some)
+            .ok_or_else(|| anyhow!("Image {} not found in {}", key, path.display()))
+    }
+
+    // Run `f` on the image and its pixel data, loading it first for header-only images. Loaded
+    // data is passed straight from the cache, so the image is neither cloned nor copied into.
+    fn with_pixel_data<R>(
+        &self,
+        image: &ImageInstance,
+        f: impl FnOnce(&ImageInstance, &[u8]) -> Result<R>,
+    ) -> Result<R> {
+        match self.load_pixel_data(image)? {
+            Some(data) => f(image, &data),
+            None => f(image, image.pixel_data()),
+        }
     }
 
     pub fn to_string(&self) -> String {
@@ -60,21 +581,13 @@ impl DicomRepo {
             result.push_str(&format!("  Sex: {:?}\n", patient.sex));
 
             // Find study sets for the patient
-            for study_set in self
-                .study_sets
-                .values()
-                .filter(|s| s.patient_id == patient.patient_id)
-            {
+            for study_set in self.studies_of(&patient.patient_id) {
                 result.push_str(&format!("  StudySet: {}\n", study_set.uid));
-                result.push_str(&format!("    Date: {}\n", study_set.date));
+                result.push_str(&format!("    Date: {:?}\n", study_set.date));
                 result.push_str(&format!("    Description: {:?}\n", study_set.description));
 
                 // Find image series for the study set
-                for image_series in self
-                    .image_series
-                    .values()
-                    .filter(|is| is.study_uid == study_set.uid)
-                {
+                for image_series in self.series_of(&study_set.uid) {
                     result.push_str(&format!("    ImageSeries: {}\n", image_series.uid));
                     result.push_str(&format!("      Modality: {}\n", image_series.modality));
                     result.push_str(&format!(
@@ -82,98 +595,382 @@ impl DicomRepo {
                         image_series.description
                     ));
 
-                    // Find CT images for the image series
-                    for ct_image in self
-                        .ct_images
-                        .values()
-                        .filter(|img| img.series_uid == image_series.uid)
-                    {
-                        result.push_str(&format!("      CTImage: {}\n", ct_image.uid));
-                        result.push_str(&format!("        Rows: {}\n", ct_image.rows));
-                        result.push_str(&format!("        Columns: {}\n", ct_image.columns));
+                    // Find images for the image series
+                    for image in self.images_of(&image_series.uid) {
+                        result.push_str(&format!("      {}Image: {}\n", image.modality(), image.uid()));
+                        result.push_str(&format!("        Rows: {}\n", image.rows()));
+                        result.push_str(&format!("        Columns: {}\n", image.columns()));
                         result.push_str(&format!(
                             "        PixelSpacing: {:?}\n",
-                            ct_image.pixel_spacing
+                            image.pixel_spacing()
                         ));
                     }
                 }
+
+                // Structure sets are not image series, so they are listed under their study
+                let mut structure_sets: Vec<&StructureSet> = self
+                    .structure_sets
+                    .values()
+                    .filter(|set| set.study_uid == study_set.uid)
+                    .collect();
+                structure_sets.sort_by(|a, b| a.label.cmp(&b.label));
+                for structure_set in structure_sets {
+                    result.push_str(&format!("    StructureSet: {}\n", structure_set.uid));
+                    result.push_str(&format!("      Label: {}\n", structure_set.label));
+                    result.push_str(&format!("      ROIs: {}\n", structure_set.rois.len()));
+                }
+
+                let mut plans: Vec<&RTPlan> = self.plans.values().filter(|plan| plan.study_uid == study_set.uid).collect();
+                plans.sort_by(|a, b| a.label.cmp(&b.label));
+                for plan in plans {
+                    result.push_str(&format!("    RTPlan: {}\n", plan.uid));
+                    result.push_str(&format!("      Label: {}\n", plan.label));
+                    result.push_str(&format!("      Beams: {}\n", plan.beams.len()));
+                }
+
+                let mut doses: Vec<&RTDose> = self.doses.values().filter(|dose| dose.study_uid == study_set.uid).collect();
+                doses.sort_by(|a, b| a.uid.cmp(&b.uid));
+                for dose in doses {
+                    result.push_str(&format!("    RTDose: {}\n", dose.uid));
+                    result.push_str(&format!("      Type: {} {} ({})\n", dose.dose_summation_type, dose.dose_type, dose.dose_units));
+                    result.push_str(&format!("      Grid: {}x{}x{}\n", dose.columns, dose.rows, dose.number_of_frames));
+                }
             }
         }
         result
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-impl CTVolumeGenerator for DicomRepo {
-    fn generate_ct_volume(&self, image_series_id: &str) -> Result<CTVolume> {
+impl DicomRepo {
+    // Collect the images of a series, sorted along the slice normal
+    fn sorted_series_images(
+        &self,
+        image_series_id: &str,
+        acquisition_number: Option<i32>,
+    ) -> Result<(Vec<&ImageInstance>, SliceOrientation, Vec<f64>)> {
         // Retrieve the ImageSeries by ID
         let series = self
             .image_series
             .get(image_series_id)
             .ok_or_else(|| anyhow!("ImageSeries with ID '{}' not found", image_series_id))?;
 
-        // Collect all CTImages belonging to the ImageSeries
-        let mut ct_images: Vec<&CTImage> = self
-            .ct_images
-            .values()
-            .filter(|img| img.series_uid == series.uid)
-            .collect();
+        let unread = self.unread_images.count(&series.uid);
+        if unread > 0 {
+            return Err(anyhow!(
+                "ImageSeries '{}' has {} images listed by a DICOMDIR that are not read yet; call read_series first",
+                image_series_id,
+                unread
+            ));
+        }
+
+        // Collect all images belonging to the ImageSeries, or to one of its acquisitions
+        let mut images = self.images_of(&series.uid);
+        if let Some(acquisition) = acquisition_number {
+            images.retain(|img| img.acquisition_number() == Some(acquisition));
+            if images.is_empty() {
+                return Err(anyhow!(
+                    "No images of acquisition {} found for ImageSeries with ID '{}'",
+                    acquisition,
+                    image_series_id
+                ));
+            }
+        }
 
-        if ct_images.is_empty() {
+        if images.is_empty() {
             return Err(anyhow!(
-                "No CTImages found for ImageSeries with ID '{}'",
+                "No images found for ImageSeries with ID '{}'",
                 image_series_id
             ));
         }
 
-        // Sort CTImages by their z-position
-        ct_images.sort_by(|a, b| {
-            let z_a = a.image_position_patient.map(|pos| pos.2).unwrap_or(0.0);
-            let z_b = b.image_position_patient.map(|pos| pos.2).unwrap_or(0.0);
-            z_a.partial_cmp(&z_b).unwrap_or(Ordering::Equal)
-        });
+        // Sort images by the projection of their position onto the slice normal
+        let (orientation, positions) = sort_along_normal(&mut images)?;
+        Ok((images, orientation, positions))
+    }
+
+    // Check a series for duplicates, gaps, non-uniform spacing and non-parallel slices
+    fn series_geometry_report(&self, image_series_id: &str) -> Result<SeriesGeometryReport> {
+        let (images, orientation, positions) = self.sorted_series_images(image_series_id, None)?;
+        let keys: Vec<String> = images.iter().map(|img| img.key()).collect();
+        Ok(SeriesGeometryReport::from_sorted(
+            image_series_id,
+            &images,
+            &keys,
+            &orientation,
+            &positions,
+        ))
+    }
+
+    // Stack the CT images of a series into a volume of Hounsfield units
+    fn assemble_volume(&self, image_series_id: &str, options: &VolumeAssemblyOptions) -> Result<CTVolume> {
+        let modality = self.image_series.get(image_series_id).map(|series| series.modality.as_str());
+        if let Some(modality) = modality.filter(|modality| *modality != "CT") {
+            return Err(anyhow!(
+                "ImageSeries '{}' is {}, not CT; use a float volume to keep its full range",
+                image_series_id,
+                modality
+            ));
+        }
+        self.stack_volume(image_series_id, options, |img| {
+            // Clamping HU to i16 would silently flatten the values beyond its range
+            if let ImageInstance::CT(ct) = img {
+                if !ct.fits_in_i16() {
+                    return Err(anyhow!(
+                        "Image {} of ImageSeries '{}' rescales beyond the i16 range of a CT volume; use a float volume to keep its full range",
+                        img.key(),
+                        image_series_id
+                    ));
+                }
+            }
+            self.with_pixel_data(img, |img, data| img.get_pixel_data_from(data))
+        })
+    }
+
+    // Stack the images of a series into a float volume: SUV for PT series whose images all carry
+    // what SUV needs, activity (Bq/ml) for other PT series, and rescaled signal for MR
+    fn assemble_float_volume(&self, image_series_id: &str, options: &VolumeAssemblyOptions) -> Result<FloatVolume> {
+        let suv = self.is_suv_series(image_series_id);
+        self.stack_volume(image_series_id, options, |img| {
+            self.with_pixel_data(img, |img, data| match img {
+                ImageInstance::PT(pt) if suv => pt.get_suv_values_from(data),
+                ImageInstance::PT(pt) => pt.get_pixel_data_f32_from(data),
+                other => other.get_pixel_data_f32_from(data),
+            })
+        })
+    }
+
+    // The grid a series is stacked into, with every voxel zero; built from the image headers
+    // alone, e.g. to rasterize structures without loading pixel data
+    fn series_grid(&self, image_series_id: &str, options: &VolumeAssemblyOptions) -> Result<MaskVolume> {
+        self.stack_volume(image_series_id, options, |img| {
+            Ok(vec![0; img.rows() as usize * img.columns() as usize])
+        })
+    }
+
+    // Whether a PT series can be converted to SUV as a whole, so no slice mixes units
+    fn is_suv_series(&self, series_uid: &str) -> bool {
+        let images = self.images_of(series_uid);
+        !images.is_empty()
+            && images
+                .iter()
+                .all(|img| matches!(img, ImageInstance::PT(pt) if pt.suv_factor().is_ok()))
+    }
+
+    // Stack the images of a series into a volume, ordered along the slice normal, with the
+    // voxel values `pixels` reads from each image, loading its pixel data if it needs them
+    fn stack_volume<T: Voxel>(
+        &self,
+        image_series_id: &str,
+        options: &VolumeAssemblyOptions,
+        pixels: impl Fn(&ImageInstance) -> Result<Vec<T>>,
+    ) -> Result<Volume<T>> {
+        let (mut images, orientation, mut positions) =
+            self.sorted_series_images(image_series_id, options.acquisition_number)?;
+
+        // Drop images re-sent at the position of their predecessor
+        if options.remove_duplicates {
+            let mut kept_images = Vec::with_capacity(images.len());
+            let mut kept_positions: Vec<f64> = Vec::with_capacity(positions.len());
+            for (img, position) in images.into_iter().zip(positions) {
+                if kept_positions
+                    .last()
+                    .is_some_and(|last| position - last <= DUPLICATE_TOLERANCE)
+                {
+                    continue;
+                }
+                kept_images.push(img);
+                kept_positions.push(position);
+            }
+            images = kept_images;
+            positions = kept_positions;
+        }
 
         // Validate consistency of rows, columns, and metadata from the first image
-        let rows = ct_images[0].rows;
-        let columns = ct_images[0].columns;
-        let pixel_spacing = ct_images[0]
-            .pixel_spacing
-            .ok_or_else(|| anyhow!("PixelSpacing is missing in the first CTImage"))?;
-        let slice_thickness = ct_images[0].slice_thickness.unwrap_or(1.0);
+        let rows = images[0].rows();
+        let columns = images[0].columns();
+        let pixel_spacing = images[0]
+            .pixel_spacing()
+            .ok_or_else(|| anyhow!("PixelSpacing is missing in the first image"))?;
 
         // Ensure all images have consistent dimensions
-        if !ct_images
+        if !images
             .iter()
-            .all(|img| img.rows == rows && img.columns == columns)
+            .all(|img| img.rows() == rows && img.columns() == columns)
         {
             return Err(anyhow!(
                 "Inconsistent image dimensions in ImageSeries '{}'",
-                series.uid
+                image_series_id
             ));
         }
 
-        let voxel_spacing = (pixel_spacing.0, pixel_spacing.1, slice_thickness);
+        let origin = images[0].image_position_patient().unwrap_or((0.0, 0.0, 0.0));
 
-        // Pre-allocate the vector with enough capacity to hold all voxel data
-        let total_voxels = rows as usize * columns as usize * ct_images.len();
-        let mut voxel_data: Vec<i16> = Vec::with_capacity(total_voxels);
+        // A tilted gantry stacks slices off their normal; either shift each slice back onto an
+        // orthogonal grid or keep the shear and stack along the actual direction of travel
+        let shear = stack_shear(&images, &orientation, &positions);
+        let sheared = norm(&shear) > SHEAR_TOLERANCE;
+        let correct_tilt = sheared && options.correct_gantry_tilt;
+        let gantry_tilt = images[0].gantry_detector_tilt();
+        if tilt_mismatch(gantry_tilt, shear_angle(&shear)) {
+            log::warn!(
+                "GantryDetectorTilt {:?} of ImageSeries '{}' disagrees with the {:.2}° shear of its slice positions; stacking follows the positions",
+                gantry_tilt,
+                image_series_id,
+                shear_angle(&shear)
+            );
+        }
+        let first_position = to_vec3(origin);
+        let pixel_count = rows as usize * columns as usize;
+        let slice_data = |img: &ImageInstance, position: f64| -> Result<Vec<T>> {
+            let data = pixels(img)?;
+            if data.len() != pixel_count {
+                return Err(anyhow!(
+                    "Image {} holds {} pixels, expected {}",
+                    img.key(),
+                    data.len(),
+                    pixel_count
+                ));
+            }
+            let ipp = match img.image_position_patient() {
+                Some(ipp) if correct_tilt => to_vec3(ipp),
+                _ => return Ok(data),
+            };
+            // In-plane offset of this slice from where an orthogonal stack would put it
+            let along_normal = position - positions[0];
+            let offset = sub(&ipp, &first_position);
+            let in_plane = [
+                offset[0] - along_normal * orientation.normal[0],
+                offset[1] - along_normal * orientation.normal[1],
+                offset[2] - along_normal * orientation.normal[2],
+            ];
+            let dx = dot(&in_plane, &orientation.row) / pixel_spacing.1 as f64;
+            let dy = dot(&in_plane, &orientation.column) / pixel_spacing.0 as f64;
+            let fill = data
+                .iter()
+                .copied()
+                .reduce(|min, v| if v < min { v } else { min })
+                .unwrap_or_default();
+            Ok(shift_slice(&data, rows as usize, columns as usize, dx, dy, fill))
+        };
 
-        for img in &ct_images {
-            let data = img.get_pixel_data()?; // Retrieve pixel data for the image
-            voxel_data.extend(data); // Append the data to the voxel_data vector
+        // Kept duplicates would take up slices of their own and compress the volume along the normal
+        if positions.windows(2).any(|w| w[1] - w[0] <= DUPLICATE_TOLERANCE) {
+            return Err(anyhow!(
+                "Cannot stack ImageSeries '{}' with duplicate slice positions; remove duplicates or select an acquisition",
+                image_series_id
+            ));
         }
 
-        // Return the constructed CTVolume
-        Ok(CTVolume {
-            dimensions: (rows as usize, columns as usize, ct_images.len()),
+        let (voxel_data, slice_count, slice_spacing) = if options.resample && images.len() > 1 {
+            let spacing = options
+                .target_spacing
+                .map(|s| s as f64)
+                .or_else(|| median_slice_spacing(&positions))
+                .ok_or_else(|| anyhow!("Cannot determine slice spacing of ImageSeries '{}'", image_series_id))?;
+
+            let slices = images
+                .iter()
+                .zip(&positions)
+                .map(|(img, &position)| slice_data(img, position))
+                .collect::<Result<Vec<_>>>()?;
+            let (voxel_data, count) = resample_slices(&slices, &positions, spacing);
+            (voxel_data, count, spacing as f32)
+        } else {
+            // Slice spacing comes from the actual positions; the tags only matter for single slices
+            let slice_spacing = mean_slice_spacing(&positions)
+                .map(|s| s as f32)
+                .or(images[0].spacing_between_slices())
+                .or(images[0].slice_thickness())
+                .unwrap_or(1.0);
+
+            // Pre-allocate the vector with enough capacity to hold all voxel data
+            let total_voxels = rows as usize * columns as usize * images.len();
+            let mut voxel_data: Vec<T> = Vec::with_capacity(total_voxels);
+
+            for (img, &position) in images.iter().zip(&positions) {
+                let data = slice_data(img, position)?; // Retrieve pixel data for the image
+                voxel_data.extend(data); // Append the data to the voxel_data vector
+            }
+            (voxel_data, images.len(), slice_spacing)
+        };
+
+        // Without correction the slice axis follows the sheared stack: one slice step moves
+        // `slice_spacing` along the normal plus the matching in-plane shift
+        let (slice_direction, slice_spacing) = if sheared && !correct_tilt {
+            let step = [
+                orientation.normal[0] + shear[0],
+                orientation.normal[1] + shear[1],
+                orientation.normal[2] + shear[2],
+            ];
+            let length = norm(&step);
+            (
+                [step[0] / length, step[1] / length, step[2] / length],
+                slice_spacing * length as f32,
+            )
+        } else {
+            (orientation.normal, slice_spacing)
+        };
+
+        let voxel_spacing = (pixel_spacing.0, pixel_spacing.1, slice_spacing);
+
+        Volume::from_parts(
+            (rows as usize, columns as usize, slice_count),
             voxel_spacing,
+            index_to_patient_matrix(
+                origin,
+                to_tuple(&orientation.row),
+                to_tuple(&orientation.column),
+                to_tuple(&slice_direction),
+                voxel_spacing,
+            ),
             voxel_data,
-        })
+        )
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CTVolumeGenerator for DicomRepo {
+    fn generate_ct_volume(&self, image_series_id: &str) -> Result<CTVolume> {
+        self.assemble_volume(image_series_id, &VolumeAssemblyOptions::default())
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl DicomRepo {
+    // Generate a volume with explicit control over duplicates and resampling
+    pub fn generate_ct_volume_with_options(
+        &self,
+        image_series_id: &str,
+        options: &VolumeAssemblyOptions,
+    ) -> Result<CTVolume> {
+        self.assemble_volume(image_series_id, options)
+    }
+
+    // Generate a full-precision volume of a PT or MR series; PT values are SUV when
+    // `is_suv_volume` is true for the series, activity concentration otherwise
+    pub fn generate_float_volume(&self, image_series_id: &str) -> Result<FloatVolume> {
+        self.assemble_float_volume(image_series_id, &VolumeAssemblyOptions::default())
+    }
+
+    // Generate a float volume with explicit control over duplicates and resampling
+    pub fn generate_float_volume_with_options(
+        &self,
+        image_series_id: &str,
+        options: &VolumeAssemblyOptions,
+    ) -> Result<FloatVolume> {
+        self.assemble_float_volume(image_series_id, options)
+    }
+
+    // Whether the float volume of a series holds SUV rather than activity concentration
+    pub fn is_suv_volume(&self, image_series_id: &str) -> bool {
+        self.is_suv_series(image_series_id)
+    }
+
+    // Report gaps, duplicates, non-uniform spacing and non-parallel slices of a series
+    pub fn validate_series_geometry(&self, image_series_id: &str) -> Result<SeriesGeometryReport> {
+        self.series_geometry_report(image_series_id)
+    }
+
     pub fn get_all_patients(&self) -> Vec<&Patient> {
         self.patients.values().collect()
     }
@@ -183,28 +980,160 @@ impl DicomRepo {
         self.patients.get(patient_id)
     }
 
-    // Query studies by patient
+    // Query studies by patient, ordered by study date
     pub fn get_studies_by_patient(&self, patient_id: &str) -> Vec<&StudySet> {
-        self.study_sets
-            .values()
-            .filter(|s| s.patient_id == patient_id)
-            .collect()
+        self.studies_of(patient_id)
     }
 
-    // Query series by study
+    // Query series by study, ordered by series number
     pub fn get_series_by_study(&self, study_id: &str) -> Vec<&ImageSeries> {
-        self.image_series
-            .values()
-            .filter(|s| s.study_uid == study_id)
-            .collect()
+        self.series_of(study_id)
+    }
+
+    // Query images by series, ordered by instance number
+    pub fn get_images_by_series(&self, series_id: &str) -> Vec<&ImageInstance> {
+        self.images_of(series_id)
+    }
+
+    // Query the series sharing a frame of reference, i.e. spatially registered to each other
+    pub fn get_series_by_frame_of_reference(&self, frame_of_reference_uid: &str) -> Vec<&ImageSeries> {
+        self.series_in_frame_of_reference(frame_of_reference_uid)
+    }
+
+    // Distinct acquisition numbers of the images of a series, in ascending order
+    pub fn get_acquisitions(&self, series_id: &str) -> Vec<i32> {
+        self.acquisitions_of(series_id)
+    }
+
+    // Query an RT structure set by SOPInstanceUID
+    pub fn get_structure_set(&self, uid: &str) -> Option<&StructureSet> {
+        self.structure_sets.get(uid)
+    }
+
+    // Query the structure sets drawn on a series, linked by reference or frame of reference
+    pub fn get_structure_sets_by_series(&self, series_id: &str) -> Vec<&StructureSet> {
+        self.structure_sets_of(series_id)
+    }
+
+    // Query the image series a structure set was drawn on
+    pub fn get_referenced_series(&self, structure_set_uid: &str) -> Option<&ImageSeries> {
+        self.referenced_series_of(self.structure_sets.get(structure_set_uid)?)
+    }
+
+    // Query an RT dose grid by SOPInstanceUID
+    pub fn get_dose(&self, uid: &str) -> Option<&RTDose> {
+        self.doses.get(uid)
+    }
+
+    // Query the dose grids sharing the frame of reference of a series
+    pub fn get_doses_by_series(&self, series_id: &str) -> Vec<&RTDose> {
+        self.doses_of(series_id)
     }
 
-    // Query images by series
-    pub fn get_images_by_series(&self, series_id: &str) -> Vec<&CTImage> {
-        self.ct_images
+    // Query the dose grids calculated for a plan
+    pub fn get_doses_by_plan(&self, plan_uid: &str) -> Vec<&RTDose> {
+        let mut doses: Vec<&RTDose> = self
+            .doses
             .values()
-            .filter(|img| img.series_uid == series_id)
-            .collect()
+            .filter(|dose| dose.referenced_plan_uid.as_deref() == Some(plan_uid))
+            .collect();
+        doses.sort_by(|a, b| a.uid.cmp(&b.uid));
+        doses
+    }
+
+    // Query an RT plan by SOPInstanceUID
+    pub fn get_plan(&self, uid: &str) -> Option<&RTPlan> {
+        self.plans.get(uid)
+    }
+
+    // Query the plans sharing the frame of reference of a series
+    pub fn get_plans_by_series(&self, series_id: &str) -> Vec<&RTPlan> {
+        self.plans_of(series_id)
+    }
+
+    // Query the plan a dose grid was calculated for
+    pub fn get_plan_of_dose(&self, dose_uid: &str) -> Option<&RTPlan> {
+        self.plans.get(self.doses.get(dose_uid)?.referenced_plan_uid.as_ref()?)
+    }
+
+    // Check that a plan shares the frame of reference of its images, structure set and doses
+    pub fn validate_plan(&self, plan_uid: &str) -> Result<PlanConsistencyReport> {
+        self.plan_consistency(plan_uid)
+    }
+
+    // Build a dose grid as a volume in DoseUnits on its own grid; use `Volume::resample_onto`
+    // to overlay it on a CT volume
+    pub fn generate_dose_volume(&self, dose_uid: &str) -> Result<DoseVolume> {
+        self.dose_volume(dose_uid)
+    }
+
+    /// Computes the DVH of a ROI, rasterized on the grid of the image series the structure set
+    /// was drawn on. Only the headers of the images are needed, not their pixel data.
+    ///
+    /// # Errors
+    /// - If the structure set, the ROI, its image series or the dose is not found.
+    /// - If the dose is relative rather than in Gy.
+    /// - If the dose and the structure set lie in different frames of reference.
+    pub fn compute_dvh(
+        &self,
+        structure_set_uid: &str,
+        roi_number: i32,
+        dose_uid: &str,
+        options: &DvhOptions,
+    ) -> Result<Dvh> {
+        let structure_set = self
+            .structure_sets
+            .get(structure_set_uid)
+            .ok_or_else(|| anyhow!("Structure set with uid '{}' not found", structure_set_uid))?;
+        let roi = structure_set
+            .roi(roi_number)
+            .ok_or_else(|| anyhow!("ROI {} not found in structure set '{}'", roi_number, structure_set_uid))?;
+        let dose = self
+            .doses
+            .get(dose_uid)
+            .ok_or_else(|| anyhow!("RT dose with uid '{}' not found", dose_uid))?;
+        if !dose.is_absolute() {
+            return Err(anyhow!(
+                "RT dose '{}' is in {} units; a DVH needs absolute dose (GY)",
+                dose_uid,
+                dose.dose_units.trim()
+            ));
+        }
+        if dose.frame_of_reference_uid != structure_set.frame_of_reference_uid {
+            return Err(anyhow!(
+                "RT dose '{}' is in frame of reference {:?}, structure set '{}' in {:?}",
+                dose_uid,
+                dose.frame_of_reference_uid,
+                structure_set_uid,
+                structure_set.frame_of_reference_uid
+            ));
+        }
+        let series = self
+            .referenced_series_of(structure_set)
+            .ok_or_else(|| anyhow!("No image series found for structure set '{}'", structure_set_uid))?;
+        let grid = self.series_grid(&series.uid, &VolumeAssemblyOptions::default())?;
+        let mask = roi.to_mask(&grid, &RasterizeOptions::default())?;
+        Dvh::compute(&roi.name, &self.dose_volume(dose_uid)?, &mask, options)
+    }
+
+    // Series matching a query, see `RepoQuery`
+    pub fn query_series(&self, query: &RepoQuery) -> Vec<SeriesMatch<'_>> {
+        query.series(self)
+    }
+
+    // Query an image by key with its pixel data, loading it on demand for header-only images
+    pub fn load_image(&self, image_key: &str) -> Result<ImageInstance> {
+        let image = self
+            .images
+            .get(image_key)
+            .ok_or_else(|| anyhow!("Image with key '{}' not found", image_key))?;
+        self.with_pixel_data(image, |image, data| {
+            let mut loaded = image.clone();
+            if !image.has_pixel_data() {
+                loaded.set_pixel_data(data.to_vec());
+            }
+            Ok(loaded)
+        })
     }
 }
 
@@ -212,6 +1141,8 @@ impl DicomRepo {
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use crate::ct_volume::{CTVolumeHandle, FloatVolumeHandle};
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[cfg(target_arch = "wasm32")]
@@ -235,35 +1166,26 @@ impl DicomRepo {
 
     // Query studies by patient and return them as JSON
     pub fn get_studies_by_patient(&self, patient_id: &str) -> Result<String, String> {
-        let studies: Vec<&StudySet> = self
-            .study_sets
-            .values()
-            .filter(|study| study.patient_id == patient_id)
-            .collect();
+        let studies = self.studies_of(patient_id);
 
         serde_json::to_string(&studies).map_err(|err| err.to_string()) // Serialize studies to JSON
     }
 
     // Query series by study and return them as JSON
     pub fn get_series_by_study(&self, study_id: &str) -> Result<String, String> {
-        let series: Vec<&ImageSeries> = self
-            .image_series
-            .values()
-            .filter(|series| series.study_uid == study_id)
-            .collect();
+        let series = self.series_of(study_id);
 
         serde_json::to_string(&series).map_err(|err| err.to_string()) // Serialize series to JSON
     }
 
     // Query images by series and return them as JSON
     pub fn get_images_by_series(&self, series_id: &str) -> Result<String, String> {
-        let images: Vec<CTImage> = self
-            .ct_images
-            .values()
-            .filter(|image| image.series_uid == series_id)
+        let images: Vec<ImageInstance> = self
+            .images_of(series_id)
+            .into_iter()
             .map(|image| {
-                let mut cloned_image = image.clone(); // Clone the CTImage
-                cloned_image.pixel_data.clear(); // Clear the pixel_data field
+                let mut cloned_image = image.clone(); // Clone the image
+                cloned_image.clear_pixel_data(); // Clear the pixel_data field
                 cloned_image
             })
             .collect();
@@ -271,71 +1193,288 @@ impl DicomRepo {
         serde_json::to_string(&images).map_err(|err| err.to_string()) // Serialize images to JSON
     }
 
-    pub async fn generate_ct_volume(&self, image_series_id: &str) -> Result<CTVolume, JsValue> {
-        // Retrieve the ImageSeries by ID
-        let series = self.image_series.get(image_series_id).ok_or_else(|| {
-            JsValue::from_str(&format!(
-                "ImageSeries with ID '{}' not found",
-                image_series_id
-            ))
-        })?;
+    // Query the series sharing a frame of reference and return them as JSON
+    pub fn get_series_by_frame_of_reference(&self, frame_of_reference_uid: &str) -> Result<String, String> {
+        let series = self.series_in_frame_of_reference(frame_of_reference_uid);
 
-        // Collect all CTImages belonging to the ImageSeries
-        let mut ct_images: Vec<&CTImage> = self
-            .ct_images
-            .values()
-            .filter(|img| img.series_uid == series.uid)
+        serde_json::to_string(&series).map_err(|err| err.to_string())
+    }
+
+    // Distinct acquisition numbers of the images of a series as JSON
+    pub fn get_acquisitions(&self, series_id: &str) -> Result<String, String> {
+        serde_json::to_string(&self.acquisitions_of(series_id)).map_err(|err| err.to_string())
+    }
+
+    // Query an RT structure set with its ROIs and contours and return it as JSON
+    pub fn get_structure_set(&self, uid: &str) -> Result<String, String> {
+        self.structure_sets
+            .get(uid)
+            .ok_or_else(|| format!("StructureSet with uid {} not found", uid))
+            .and_then(|set| serde_json::to_string(set).map_err(|err| err.to_string()))
+    }
+
+    // Query the structure sets drawn on a series and return them as JSON
+    pub fn get_structure_sets_by_series(&self, series_id: &str) -> Result<String, String> {
+        serde_json::to_string(&self.structure_sets_of(series_id)).map_err(|err| err.to_string())
+    }
+
+    // Query an RT plan with its beams and control points and return it as JSON
+    pub fn get_plan(&self, uid: &str) -> Result<String, String> {
+        self.plans
+            .get(uid)
+            .ok_or_else(|| format!("RTPlan with uid {} not found", uid))
+            .and_then(|plan| serde_json::to_string(plan).map_err(|err| err.to_string()))
+    }
+
+    // Query the plans sharing the frame of reference of a series and return them as JSON
+    pub fn get_plans_by_series(&self, series_id: &str) -> Result<String, String> {
+        serde_json::to_string(&self.plans_of(series_id)).map_err(|err| err.to_string())
+    }
+
+    // Check the frame of reference consistency of a plan and return the report as JSON
+    pub fn validate_plan(&self, plan_uid: &str) -> Result<String, String> {
+        let report = self.plan_consistency(plan_uid).map_err(|err| err.to_string())?;
+        serde_json::to_string(&report).map_err(|err| err.to_string())
+    }
+
+    // Query the dose grids sharing the frame of reference of a series and return them as JSON
+    pub fn get_doses_by_series(&self, series_id: &str) -> Result<String, String> {
+        let doses: Vec<RTDose> = self
+            .doses_of(series_id)
+            .into_iter()
+            .map(|dose| {
+                let mut header = dose.clone();
+                header.pixel_data.clear(); // The grid is not sent as JSON
+                header
+            })
             .collect();
+        serde_json::to_string(&doses).map_err(|err| err.to_string())
+    }
 
-        if ct_images.is_empty() {
-            return Err(JsValue::from_str(&format!(
-                "No CTImages found for ImageSeries with ID '{}'",
-                image_series_id
-            )));
+    // Query series with a JSON `RepoQuery` (e.g. `{"modality": "CT", "patient_name": "DOE*"}`)
+    // and return the matches as JSON
+    pub fn query_series(&self, query_json: &str) -> Result<String, String> {
+        let query: RepoQuery = serde_json::from_str(query_json).map_err(|err| err.to_string())?;
+        serde_json::to_string(&query.series(self)).map_err(|err| err.to_string())
+    }
+
+    pub async fn generate_ct_volume(&self, image_series_id: &str) -> Result<CTVolumeHandle, JsValue> {
+        self.assemble_volume(image_series_id, &VolumeAssemblyOptions::default())
+            .map(CTVolumeHandle::from)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    // Full-precision volume of a PT (SUV or activity) or MR series
+    pub async fn generate_float_volume(&self, image_series_id: &str) -> Result<FloatVolumeHandle, JsValue> {
+        self.assemble_float_volume(image_series_id, &VolumeAssemblyOptions::default())
+            .map(FloatVolumeHandle::from)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    // Whether the float volume of a series holds SUV rather than activity concentration
+    pub fn is_suv_volume(&self, image_series_id: &str) -> bool {
+        self.is_suv_series(image_series_id)
+    }
+
+    // Report the slice geometry of a series as JSON
+    pub fn validate_series_geometry(&self, image_series_id: &str) -> Result<String, String> {
+        let report = self
+            .series_geometry_report(image_series_id)
+            .map_err(|err| err.to_string())?;
+        serde_json::to_string(&report).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::test_util::test_ct_image;
+
+    fn ct_series(uid: &str) -> ImageSeries {
+        ImageSeries::new(uid.into(), "1.2".into(), "CT".into(), None, None, None, None)
+    }
+
+    // A 1x2 CT slice of series 1.2.3 at `ipp`, both pixels holding `value`
+    fn ct_slice(uid: &str, ipp: (f32, f32, f32), iop: (f32, f32, f32, f32, f32, f32), value: i16) -> CTImage {
+        CTImage {
+            uid: uid.to_string(),
+            rows: 1,
+            columns: 2,
+            pixel_spacing: Some((0.5, 0.8)),
+            image_position_patient: Some(ipp),
+            image_orientation_patient: Some(iop),
+            pixel_data: [value; 2].iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ..test_ct_image()
         }
+    }
 
-        // Sort CTImages by their z-position (third component of ImagePositionPatient)
-        ct_images.sort_by(|a, b| {
-            let z_a = a.image_position_patient.map(|pos| pos.2).unwrap_or(0.0);
-            let z_b = b.image_position_patient.map(|pos| pos.2).unwrap_or(0.0);
-            z_a.partial_cmp(&z_b).unwrap_or(Ordering::Equal)
-        });
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
 
-        // Validate consistency of rows, columns, and retrieve metadata from the first image
-        let rows = ct_images[0].rows;
-        let columns = ct_images[0].columns;
-        let pixel_spacing = ct_images[0]
-            .pixel_spacing
-            .ok_or_else(|| JsValue::from_str("PixelSpacing is missing in the first CTImage"))?;
-        let slice_thickness = ct_images[0].slice_thickness.unwrap_or(1.0);
+    #[test]
+    fn test_sagittal_volume_is_placed_by_its_positions() {
+        // Sagittal slices 2.5 mm apart along -x, added out of order, with a 1 mm SliceThickness
+        const SAGITTAL: (f32, f32, f32, f32, f32, f32) = (0.0, 1.0, 0.0, 0.0, 0.0, -1.0);
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ct_series("1.2.3"));
+        for (uid, x) in [("1", 5.0), ("3", 10.0), ("2", 7.5)] {
+            repo.add_ct_image(CTImage {
+                slice_thickness: Some(1.0),
+                ..ct_slice(uid, (x, -20.0, 30.0), SAGITTAL, uid.parse().unwrap())
+            });
+        }
 
-        // Ensure all images have consistent dimensions
-        if !ct_images
-            .iter()
-            .all(|img| img.rows == rows && img.columns == columns)
-        {
-            return Err(JsValue::from_str(&format!(
-                "Inconsistent image dimensions in ImageSeries '{}'",
-                series.uid
-            )));
+        let volume = repo.generate_ct_volume("1.2.3").unwrap();
+        assert_eq!(volume.dimensions(), (1, 2, 3));
+        assert_eq!(volume.voxel_spacing(), (0.5, 0.8, 2.5));
+        // The first slice is the first one along the normal, at x = 10
+        assert_eq!(volume.voxel_data(), &[3, 3, 2, 2, 1, 1]);
+        assert_close(volume.origin(), [10.0, -20.0, 30.0]);
+        assert_close(volume.direction(0), [0.0, 1.0, 0.0]);
+        assert_close(volume.direction(1), [0.0, 0.0, -1.0]);
+        assert_close(volume.direction(2), [-1.0, 0.0, 0.0]);
+        assert_close(volume.voxel_to_world([1.0, 0.0, 2.0]), [5.0, -19.2, 30.0]);
+    }
+
+    #[test]
+    fn test_duplicates_are_removed_or_refused() {
+        const AXIAL: (f32, f32, f32, f32, f32, f32) = (1.0, 0.0, 0.0, 0.0, 1.0, 0.0);
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ct_series("1.2.3"));
+        for (uid, z) in [("1", 0.0), ("2", 2.0), ("3", 2.0), ("4", 4.0)] {
+            repo.add_ct_image(CTImage {
+                instance_number: Some(uid.parse().unwrap()),
+                ..ct_slice(uid, (0.0, 0.0, z), AXIAL, uid.parse().unwrap())
+            });
+        }
+
+        let volume = repo.generate_ct_volume("1.2.3").unwrap();
+        assert_eq!(volume.voxel_spacing().2, 2.0);
+        assert_eq!(volume.voxel_data(), &[1, 1, 2, 2, 4, 4]);
+
+        let options = VolumeAssemblyOptions { remove_duplicates: false, ..Default::default() };
+        let err = repo.generate_ct_volume_with_options("1.2.3", &options).unwrap_err();
+        assert!(err.to_string().contains("duplicate slice positions"));
+    }
+
+    // Three axial 4x1 slices of a stack tilted by 15°: each slice moves 1 mm along y for
+    // 3.73 mm along z, and marks the row at y = 0 of the first slice with 100
+    fn tilted_repo(gantry_tilt: f32) -> DicomRepo {
+        let step = 1.0 / 15f32.to_radians().tan();
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ct_series("1.2.3"));
+        for k in 0..3u8 {
+            let mut values = [0i16; 4];
+            values[0] = 100;
+            repo.add_ct_image(CTImage {
+                uid: k.to_string(),
+                rows: 4,
+                columns: 1,
+                pixel_spacing: Some((1.0, 1.0)),
+                gantry_detector_tilt: Some(gantry_tilt),
+                image_position_patient: Some((0.0, k as f32, k as f32 * step)),
+                image_orientation_patient: Some((1.0, 0.0, 0.0, 0.0, 1.0, 0.0)),
+                pixel_data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+                ..test_ct_image()
+            });
         }
+        repo
+    }
+
+    #[test]
+    fn test_tilted_stack_keeps_its_shear_in_the_affine() {
+        let repo = tilted_repo(15.0);
+        let report = repo.validate_series_geometry("1.2.3").unwrap();
+        assert!(report.is_sheared() && !report.tilt_mismatch);
+        assert!((report.shear_angle - 15.0).abs() < 1e-3);
+        // A tag of 0° on the same positions disagrees with them
+        assert!(tilted_repo(0.0).validate_series_geometry("1.2.3").unwrap().tilt_mismatch);
 
-        let voxel_spacing = (pixel_spacing.0, pixel_spacing.1, slice_thickness);
+        let volume = repo.generate_ct_volume("1.2.3").unwrap();
+        let tilt = 15f64.to_radians();
+        assert_eq!(volume.voxel_data(), &[100, 0, 0, 0, 100, 0, 0, 0, 100, 0, 0, 0]);
+        assert_close(volume.direction(2), [0.0, tilt.sin(), tilt.cos()]);
+        assert!((volume.voxel_spacing().2 as f64 - 1.0 / tilt.sin()).abs() < 1e-3);
+        // The slice axis runs through the first voxel of every slice
+        assert_close(volume.voxel_to_world([0.0, 0.0, 2.0]), [0.0, 2.0, 2.0 / tilt.tan()]);
+    }
+
+    #[test]
+    fn test_tilted_stack_is_shifted_onto_an_orthogonal_grid() {
+        let repo = tilted_repo(15.0);
+        let options = VolumeAssemblyOptions { correct_gantry_tilt: true, ..Default::default() };
+        let volume = repo.generate_ct_volume_with_options("1.2.3", &options).unwrap();
 
-        // Collect voxel data from each CTImage sequentially
-        let mut voxel_data = Vec::new();
-        for img in &ct_images {
-            let pixels = img
-                .get_pixel_data()
-                .map_err(|e| JsValue::from_str(&e.to_string()))?;
-            voxel_data.push(pixels);
+        // The marked row stays at y = 0: one row further down in every slice
+        assert_eq!(volume.voxel_data(), &[100, 0, 0, 0, 0, 100, 0, 0, 0, 0, 100, 0]);
+        assert_close(volume.origin(), [0.0, 0.0, 0.0]);
+        assert_close(volume.direction(2), [0.0, 0.0, 1.0]);
+        let step = 1.0 / 15f64.to_radians().tan();
+        assert!((volume.voxel_spacing().2 as f64 - step).abs() < 1e-3);
+        assert_close(volume.voxel_to_world([0.0, 2.0, 2.0]), [0.0, 2.0, 2.0 * step]);
+    }
+
+    #[test]
+    fn test_read_multi_frame_images_are_not_listed_again() {
+        // Both frames of a multi-frame image were read; its DICOMDIR record names the bare UID
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ct_series("1.2.3"));
+        for frame in [1, 2] {
+            repo.add_ct_image(CTImage { frame_number: Some(frame), ..test_ct_image() });
         }
+        repo.add_unread_image("1.2.3", "1.2.3.4", PathBuf::from("IM0"));
+        let mut listed = DicomRepo::new();
+        listed.add_unread_image("1.2.3", "1.2.3.4", PathBuf::from("IM0"));
+        repo.merge(listed);
 
-        // Return the constructed CTVolume
-        Ok(CTVolume {
-            dimensions: (rows, columns, ct_images.len()),
-            voxel_spacing,
-            voxel_data,
-        })
+        assert_eq!(repo.unread_image_count("1.2.3"), 0);
+        assert_eq!(repo.series_image_count("1.2.3"), 2);
+        assert_eq!(repo.read_series("1.2.3").unwrap(), 0);
+        assert!(!repo.pixel_sources.contains_key("1.2.3.4"));
+    }
+
+    #[test]
+    fn test_merge_drops_frames_of_a_shorter_replacement() {
+        let frames = |count: u32| {
+            let mut repo = DicomRepo::new();
+            repo.add_image_series(ct_series("1.2.3"));
+            for frame in 1..=count {
+                let image = ImageInstance::CT(CTImage { frame_number: Some(frame), ..test_ct_image() });
+                repo.add_pixel_source(image.key(), PathBuf::from("MF"));
+                repo.add_image(image);
+            }
+            repo
+        };
+        let mut repo = frames(3);
+        repo.merge(frames(2));
+
+        let mut keys: Vec<&String> = repo.images.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["1.2.3.4#1", "1.2.3.4#2"]);
+        assert!(!repo.pixel_sources.contains_key("1.2.3.4#3"));
+        assert_eq!(repo.series_image_count("1.2.3"), 2);
+    }
+
+    #[test]
+    fn test_ct_volume_rejects_values_beyond_i16() {
+        // A slope of 2 maps the signed 16-bit stored range onto twice the i16 range
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ct_series("1.2.3"));
+        repo.add_ct_image(CTImage {
+            rows: 1,
+            columns: 2,
+            pixel_spacing: Some((1.0, 1.0)),
+            rescale_slope: Some(2.0),
+            pixel_data: [20000i16, -100].iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ..test_ct_image()
+        });
+
+        let err = repo.generate_ct_volume("1.2.3").unwrap_err();
+        assert!(err.to_string().contains("float volume"));
+        let volume = repo.generate_float_volume("1.2.3").unwrap();
+        assert_eq!(volume.voxel_data(), &[40000.0, -200.0]);
     }
 }
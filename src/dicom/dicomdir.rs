@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use dicom_object::{FileDicomObject, InMemDicomObject, OpenFileOptions};
+use std::path::{Path, PathBuf};
+
+use super::dicom_helper::{get_value, get_values, MissingAttribute};
+use super::dicom_repo::DicomRepo;
+use super::image_instance::SUPPORTED_IMAGE_MODALITIES;
+use super::image_series::ImageSeries;
+use super::patient::Patient;
+use super::studyset::StudySet;
+
+// An instance referenced by an IMAGE record of a DICOMDIR
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DicomDirInstance {
+    pub uid: String,        // ReferencedSOPInstanceUIDInFile
+    pub series_uid: String, // SeriesInstanceUID of the enclosing SERIES record
+    pub path: PathBuf,      // File resolved from ReferencedFileID
+}
+
+/// The Patient/Study/Series/Instance hierarchy described by a DICOMDIR.
+///
+/// Built from the directory records alone, so browsing a CD or exported study does not
+/// require opening the referenced files.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DicomDir {
+    pub patients: Vec<Patient>,
+    pub studies: Vec<StudySet>,
+    pub series: Vec<ImageSeries>,
+    pub instances: Vec<DicomDirInstance>,
+}
+
+// Whether a file is a DICOMDIR, which indexes a file set rather than holding an image
+pub fn is_dicomdir(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.eq_ignore_ascii_case("DICOMDIR"))
+}
+
+/// Reads a DICOMDIR file; referenced files are resolved relative to its directory.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or has no directory record sequence.
+pub fn read_dicomdir(path: &Path) -> Result<DicomDir> {
+    let obj = OpenFileOptions::new()
+        .open_file(path)
+        .map_err(|err| anyhow!("Failed to read DICOMDIR {}: {}", path.display(), err))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    DicomDir::from_object(&obj, base_dir)
+}
+
+impl DicomDir {
+    /// Builds the hierarchy from the records of a parsed DICOMDIR.
+    ///
+    /// Records are expected in the depth-first order written by file-set creators, each
+    /// lower-level record following its parent. Records of other types (e.g. RT or SR
+    /// documents), series of unsupported modalities and records missing required
+    /// attributes are skipped together with their children.
+    ///
+    /// # Errors
+    /// Returns an error if the object has no directory record sequence.
+    pub fn from_object(obj: &FileDicomObject<InMemDicomObject>, base_dir: &Path) -> Result<DicomDir> {
+        let records = obj
+            .element_by_name("DirectoryRecordSequence")
+            .ok()
+            .and_then(|e| e.items())
+            .ok_or(MissingAttribute("DirectoryRecordSequence"))?;
+
+        let mut dir = DicomDir::default();
+        // The enclosing patient ID, study UID and series UID of the current record, None
+        // while inside a skipped record
+        let mut patient_id: Option<String> = None;
+        let mut study_uid: Option<String> = None;
+        let mut series_uid: Option<String> = None;
+
+        for record in records {
+            let record_type = get_value::<String>(record, "DirectoryRecordType").unwrap_or_default();
+            match record_type.trim() {
+                "PATIENT" => {
+                    let patient = Patient::from_object(record).ok();
+                    patient_id = patient.as_ref().map(|p| p.patient_id.clone());
+                    study_uid = None;
+                    series_uid = None;
+                    dir.patients.extend(patient);
+                }
+                "STUDY" => {
+                    let study = patient_id.as_ref().and_then(|id| study_from_record(record, id));
+                    study_uid = study.as_ref().map(|s| s.uid.clone());
+                    series_uid = None;
+                    dir.studies.extend(study);
+                }
+                "SERIES" => {
+                    let series = study_uid.as_ref().and_then(|uid| series_from_record(record, uid));
+                    series_uid = series.as_ref().map(|s| s.uid.clone());
+                    dir.series.extend(series);
+                }
+                "IMAGE" => {
+                    let instance = series_uid.as_ref().and_then(|uid| {
+                        Some(DicomDirInstance {
+                            uid: get_value::<String>(record, "ReferencedSOPInstanceUIDInFile")?,
+                            series_uid: uid.clone(),
+                            path: referenced_path(record, base_dir)?,
+                        })
+                    });
+                    dir.instances.extend(instance);
+                }
+                _ => {}
+            }
+        }
+        Ok(dir)
+    }
+
+    // Add the patients, studies and series to the repository, and the instances as header-less
+    // images whose files are read once their series is opened (`DicomRepo::read_series`)
+    pub fn add_to(&self, repo: &mut DicomRepo) {
+        for patient in &self.patients {
+            repo.add_patient(patient.clone());
+        }
+        for study in &self.studies {
+            repo.add_study(study.clone());
+        }
+        for series in &self.series {
+            repo.add_image_series(series.clone());
+        }
+        for instance in &self.instances {
+            repo.add_unread_image(&instance.series_uid, &instance.uid, instance.path.clone());
+        }
+    }
+}
+
+// STUDY records carry no PatientID, so it comes from the enclosing PATIENT record
+fn study_from_record(record: &InMemDicomObject, patient_id: &str) -> Option<StudySet> {
+    Some(StudySet::new(
+        get_value::<String>(record, "StudyID").filter(|v| !v.trim().is_empty()),
+        get_value::<String>(record, "StudyInstanceUID")?,
+        patient_id.to_string(),
+        get_value::<String>(record, "StudyDate").filter(|v| !v.trim().is_empty()),
+        get_value::<String>(record, "StudyDescription"),
+    ))
+}
+
+// SERIES records carry no StudyInstanceUID, so it comes from the enclosing STUDY record
+fn series_from_record(record: &InMemDicomObject, study_uid: &str) -> Option<ImageSeries> {
+    let modality = get_value::<String>(record, "Modality")?;
+    if !SUPPORTED_IMAGE_MODALITIES.contains(&modality.as_str()) {
+        return None;
+    }
+    Some(ImageSeries::new(
+        get_value::<String>(record, "SeriesInstanceUID")?,
+        study_uid.to_string(),
+        modality,
+        get_value::<i32>(record, "SeriesNumber"),
+        None, // FrameOfReferenceUID is not part of SERIES records
+        get_value::<String>(record, "BodyPartExamined"),
+        get_value::<String>(record, "SeriesDescription"),
+    ))
+}
+
+// ReferencedFileID holds the path components of the file relative to the DICOMDIR
+fn referenced_path(record: &InMemDicomObject, base_dir: &Path) -> Option<PathBuf> {
+    let components = get_values::<String>(record, "ReferencedFileID")?;
+    let mut path = base_dir.to_path_buf();
+    for component in components.iter().filter(|c| !c.is_empty()) {
+        path.push(component);
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use dicom_core::value::DataSetSequence;
+    use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+    use dicom_object::meta::FileMetaTableBuilder;
+
+    pub(crate) fn record(record_type: &str, elements: &[(Tag, VR, &str)]) -> InMemDicomObject {
+        let mut item = InMemDicomObject::new_empty();
+        item.put(DataElement::new(Tag(0x0004, 0x1430), VR::CS, PrimitiveValue::from(record_type)));
+        for &(tag, vr, value) in elements {
+            item.put(DataElement::new(tag, vr, PrimitiveValue::from(value)));
+        }
+        item
+    }
+
+    // Build a DICOMDIR holding the given directory records
+    pub(crate) fn dicomdir_object(records: Vec<InMemDicomObject>) -> FileDicomObject<InMemDicomObject> {
+        let mut obj = InMemDicomObject::new_empty();
+        obj.put(DataElement::new(Tag(0x0004, 0x1220), VR::SQ, DataSetSequence::from(records)));
+        obj.with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.1.3.10")
+                .media_storage_sop_instance_uid("1.2.3"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hierarchy_from_records() {
+        let records = vec![
+            record("PATIENT", &[(Tag(0x0010, 0x0020), VR::LO, "P1"), (Tag(0x0010, 0x0010), VR::PN, "Doe^J")]),
+            record("STUDY", &[
+                (Tag(0x0020, 0x0010), VR::SH, "S1"),
+                (Tag(0x0020, 0x000D), VR::UI, "1.1"),
+                (Tag(0x0008, 0x0020), VR::DA, "20240101"),
+            ]),
+            record("SERIES", &[(Tag(0x0020, 0x000E), VR::UI, "1.1.1"), (Tag(0x0008, 0x0060), VR::CS, "CT")]),
+            record("IMAGE", &[
+                (Tag(0x0004, 0x1511), VR::UI, "1.1.1.1"),
+                (Tag(0x0004, 0x1500), VR::CS, "DICOM\\IMG0001"),
+            ]),
+            // Unsupported modality: the series and its images are skipped
+            record("SERIES", &[(Tag(0x0020, 0x000E), VR::UI, "1.1.2"), (Tag(0x0008, 0x0060), VR::CS, "SR")]),
+            record("IMAGE", &[
+                (Tag(0x0004, 0x1511), VR::UI, "1.1.2.1"),
+                (Tag(0x0004, 0x1500), VR::CS, "DICOM\\DOC0001"),
+            ]),
+        ];
+        let dir = DicomDir::from_object(&dicomdir_object(records), Path::new("cd")).unwrap();
+        assert_eq!(dir.patients.len(), 1);
+        assert_eq!(dir.studies[0].patient_id, "P1");
+        assert_eq!(dir.series.len(), 1);
+        assert_eq!(dir.series[0].study_uid, "1.1");
+        assert_eq!(dir.instances.len(), 1);
+        assert_eq!(dir.instances[0].path, Path::new("cd").join("DICOM").join("IMG0001"));
+    }
+}
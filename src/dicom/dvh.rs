@@ -0,0 +1,368 @@
+use super::roi_mask::{MaskVolume, MASK_INSIDE};
+use super::rt_dose::DoseVolume;
+use anyhow::{anyhow, Result};
+use std::fmt::Write;
+
+// Cubic millimetres per cubic centimetre
+const MM3_PER_CM3: f64 = 1000.0;
+
+// How a dose-volume histogram is binned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DvhOptions {
+    // Width of the dose bins (Gy)
+    pub bin_width: f64,
+}
+
+impl Default for DvhOptions {
+    fn default() -> Self {
+        DvhOptions { bin_width: 0.01 }
+    }
+}
+
+/// Dose-volume histogram of a ROI.
+///
+/// `differential[i]` is the volume (cm³) receiving a dose in `[i, i + 1) * bin_width`.
+/// Partial voxels of the mask contribute their inside fraction.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Dvh {
+    pub roi_name: String,
+    pub bin_width: f64,         // Gy
+    pub volume: f64,            // Volume of the ROI (cm³)
+    pub min_dose: f64,          // Gy
+    pub max_dose: f64,          // Gy
+    pub mean_dose: f64,         // Volume-weighted mean (Gy)
+    pub differential: Vec<f64>, // cm³ per bin
+}
+
+// Dose of each voxel of a mask that lies (partly) in the ROI, with its volume (mm³)
+fn sample_roi(dose: &DoseVolume, mask: &MaskVolume) -> Result<Vec<(f64, f64)>> {
+    let to_dose_index = dose
+        .patient_to_index()
+        .ok_or_else(|| anyhow!("The dose grid has a degenerate geometry"))?;
+    // Mask indices map to dose indices through a single affine transform; on a shared grid it
+    // is the identity and the samples fall on the dose voxels
+    let transform = to_dose_index.multiply(mask.index_to_patient());
+    let (sr, sc, ss) = mask.voxel_spacing();
+    let voxel_volume = sr as f64 * sc as f64 * ss as f64;
+    Ok(mask
+        .iter()
+        .filter(|&(_, value)| value > 0)
+        .map(|([i, j, k], value)| {
+            let index = transform.apply(&[i as f64, j as f64, k as f64, 1.0]);
+            // Voxels outside the calculated grid received no dose
+            let d = dose.interpolate([index[0], index[1], index[2]]).unwrap_or(0.0);
+            (d, voxel_volume * value as f64 / MASK_INSIDE as f64)
+        })
+        .collect())
+}
+
+impl Dvh {
+    /// Computes the DVH of the ROI of `mask` in `dose`.
+    ///
+    /// The dose is sampled at the centre of every mask voxel by trilinear interpolation, so the
+    /// dose grid and the mask (CT) grid need not match. Mask voxels outside the dose grid
+    /// count as receiving no dose.
+    ///
+    /// # Errors
+    /// Returns an error if the bin width is not positive, the mask is empty or the dose grid
+    /// geometry is degenerate.
+    pub fn compute(roi_name: &str, dose: &DoseVolume, mask: &MaskVolume, options: &DvhOptions) -> Result<Dvh> {
+        if options.bin_width.is_nan() || options.bin_width <= 0.0 {
+            return Err(anyhow!("Invalid DVH bin width {}", options.bin_width));
+        }
+        let samples = sample_roi(dose, mask)?;
+        let total: f64 = samples.iter().map(|(_, volume)| volume).sum();
+        if samples.is_empty() || total <= 0.0 {
+            return Err(anyhow!("The mask of ROI '{}' is empty", roi_name));
+        }
+
+        let min_dose = samples.iter().map(|(d, _)| *d).fold(f64::INFINITY, f64::min);
+        let max_dose = samples.iter().map(|(d, _)| *d).fold(f64::NEG_INFINITY, f64::max);
+        let mean_dose = samples.iter().map(|(d, volume)| d * volume).sum::<f64>() / total;
+
+        let bins = (max_dose.max(0.0) / options.bin_width).floor() as usize + 1;
+        let mut differential = vec![0.0; bins];
+        for (d, volume) in &samples {
+            let bin = ((d.max(0.0) / options.bin_width).floor() as usize).min(bins - 1);
+            differential[bin] += volume / MM3_PER_CM3;
+        }
+
+        Ok(Dvh {
+            roi_name: roi_name.to_string(),
+            bin_width: options.bin_width,
+            volume: total / MM3_PER_CM3,
+            min_dose,
+            max_dose,
+            mean_dose,
+            differential,
+        })
+    }
+
+    // Lower dose edge of each bin (Gy)
+    pub fn doses(&self) -> Vec<f64> {
+        (0..self.differential.len()).map(|i| i as f64 * self.bin_width).collect()
+    }
+
+    // Volume (cm³) receiving at least the lower dose edge of each bin
+    pub fn cumulative(&self) -> Vec<f64> {
+        let mut cumulative = vec![0.0; self.differential.len()];
+        let mut sum = 0.0;
+        for (i, volume) in self.differential.iter().enumerate().rev() {
+            sum += volume;
+            cumulative[i] = sum;
+        }
+        cumulative
+    }
+
+    /// Volume (cm³) receiving at least `dose` Gy, e.g. V20Gy for `dose` 20.
+    ///
+    /// Interpolates linearly within the bin holding `dose`.
+    pub fn volume_at_dose(&self, dose: f64) -> f64 {
+        if dose <= 0.0 {
+            return self.volume;
+        }
+        let position = dose / self.bin_width;
+        let bin = position.floor() as usize;
+        if bin >= self.differential.len() {
+            return 0.0;
+        }
+        let above: f64 = self.differential[bin + 1..].iter().sum();
+        above + self.differential[bin] * (1.0 - (position - bin as f64))
+    }
+
+    // Percentage of the ROI volume receiving at least `dose` Gy
+    pub fn volume_percent_at_dose(&self, dose: f64) -> f64 {
+        100.0 * self.volume_at_dose(dose) / self.volume
+    }
+
+    /// Minimum dose (Gy) received by the hottest `percent` of the ROI volume, e.g. D95 for
+    /// `percent` 95.
+    ///
+    /// Interpolates linearly within the bin where the cumulative volume reaches `percent`.
+    pub fn dose_at_volume_percent(&self, percent: f64) -> f64 {
+        let target = self.volume * percent.clamp(0.0, 100.0) / 100.0;
+        if target <= 0.0 {
+            return self.max_dose;
+        }
+        let mut above = 0.0;
+        for (bin, volume) in self.differential.iter().enumerate().rev() {
+            if above + volume >= target && *volume > 0.0 {
+                let fraction = (target - above) / volume;
+                let dose = (bin as f64 + 1.0 - fraction) * self.bin_width;
+                return dose.clamp(self.min_dose, self.max_dose);
+            }
+            above += volume;
+        }
+        self.min_dose
+    }
+
+    /// Homogeneity index (D2 - D98) / D50 as defined by ICRU Report 83; 0 for a perfectly
+    /// uniform dose. None when D50 is zero.
+    pub fn homogeneity_index(&self) -> Option<f64> {
+        let d50 = self.dose_at_volume_percent(50.0);
+        (d50 > 0.0).then(|| (self.dose_at_volume_percent(2.0) - self.dose_at_volume_percent(98.0)) / d50)
+    }
+
+    // DVH curves as CSV: dose, differential and cumulative volume and cumulative percentage
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("dose_gy,differential_cm3,cumulative_cm3,cumulative_percent\n");
+        for ((dose, differential), cumulative) in self.doses().iter().zip(&self.differential).zip(self.cumulative()) {
+            let _ = writeln!(
+                csv,
+                "{:.4},{:.6},{:.6},{:.4}",
+                dose,
+                differential,
+                cumulative,
+                100.0 * cumulative / self.volume
+            );
+        }
+        csv
+    }
+
+    // DVH with its curves and the common metrics as JSON
+    pub fn to_json(&self) -> Result<String> {
+        let export = serde_json::json!({
+            "roi_name": self.roi_name,
+            "volume_cm3": self.volume,
+            "bin_width_gy": self.bin_width,
+            "metrics": {
+                "d_min": self.min_dose,
+                "d_max": self.max_dose,
+                "d_mean": self.mean_dose,
+                "d_2": self.dose_at_volume_percent(2.0),
+                "d_50": self.dose_at_volume_percent(50.0),
+                "d_95": self.dose_at_volume_percent(95.0),
+                "d_98": self.dose_at_volume_percent(98.0),
+                "homogeneity_index": self.homogeneity_index(),
+            },
+            "dose_gy": self.doses(),
+            "differential_cm3": self.differential,
+            "cumulative_cm3": self.cumulative(),
+        });
+        serde_json::to_string(&export).map_err(|err| anyhow!("Failed to serialize DVH: {}", err))
+    }
+}
+
+/// Conformity of the prescription isodose to a target.
+///
+/// Volumes are in cm³: `target_volume` (TV), `prescription_isodose_volume` (PIV, the volume
+/// of the mask grid receiving at least the prescription dose) and `covered_target_volume`
+/// (TV_PIV, the part of the target inside it).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct ConformityIndices {
+    pub target_volume: f64,
+    pub prescription_isodose_volume: f64,
+    pub covered_target_volume: f64,
+    pub coverage: f64, // TV_PIV / TV
+    pub rtog: f64,     // PIV / TV
+    pub paddick: f64,  // TV_PIV² / (TV × PIV)
+}
+
+/// Computes the conformity of the `prescription` isodose (Gy) to the target of `target_mask`.
+///
+/// The dose is sampled on the grid of the mask, and the prescription isodose volume is
+/// measured over the whole mask grid, so the grid should cover the body.
+///
+/// # Errors
+/// Returns an error if the target is empty or the dose grid geometry is degenerate.
+pub fn conformity_indices(dose: &DoseVolume, target_mask: &MaskVolume, prescription: f64) -> Result<ConformityIndices> {
+    let to_dose_index = dose
+        .patient_to_index()
+        .ok_or_else(|| anyhow!("The dose grid has a degenerate geometry"))?;
+    let transform = to_dose_index.multiply(target_mask.index_to_patient());
+    let (sr, sc, ss) = target_mask.voxel_spacing();
+    let voxel_volume = sr as f64 * sc as f64 * ss as f64 / MM3_PER_CM3;
+
+    let (mut target, mut isodose, mut covered) = (0.0, 0.0, 0.0);
+    for ([i, j, k], value) in target_mask.iter() {
+        let index = transform.apply(&[i as f64, j as f64, k as f64, 1.0]);
+        let inside_isodose = dose
+            .interpolate([index[0], index[1], index[2]])
+            .is_some_and(|d| d >= prescription);
+        let fraction = value as f64 / MASK_INSIDE as f64;
+        target += fraction * voxel_volume;
+        if inside_isodose {
+            isodose += voxel_volume;
+            covered += fraction * voxel_volume;
+        }
+    }
+    if target <= 0.0 {
+        return Err(anyhow!("The target mask is empty"));
+    }
+
+    Ok(ConformityIndices {
+        target_volume: target,
+        prescription_isodose_volume: isodose,
+        covered_target_volume: covered,
+        coverage: covered / target,
+        rtog: isodose / target,
+        paddick: if isodose > 0.0 { covered * covered / (target * isodose) } else { 0.0 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume::Volume;
+
+    #[test]
+    fn test_metrics_on_a_dose_gradient() {
+        // Ten 1 mm³ voxels along a row receiving 1..10 Gy; the ROI covers all of them
+        let dose = Volume::new((1, 10, 1), (1.0, 1.0, 1.0), (1..=10).map(|d| d as f32).collect()).unwrap();
+        let mask = Volume::new((1, 10, 1), (1.0, 1.0, 1.0), vec![MASK_INSIDE; 10]).unwrap();
+        let dvh = Dvh::compute("PTV", &dose, &mask, &DvhOptions { bin_width: 1.0 }).unwrap();
+
+        assert!((dvh.volume - 0.01).abs() < 1e-12);
+        assert_eq!((dvh.min_dose, dvh.max_dose, dvh.mean_dose), (1.0, 10.0, 5.5));
+        assert!((dvh.volume_percent_at_dose(5.0) - 60.0).abs() < 1e-9);
+        assert!((dvh.volume_percent_at_dose(20.0)).abs() < 1e-9);
+        assert!((dvh.dose_at_volume_percent(50.0) - 6.0).abs() < 1e-9);
+        assert!((dvh.cumulative()[0] - dvh.volume).abs() < 1e-12);
+        assert!(dvh.to_csv().lines().nth(2).unwrap().starts_with("1.0000,0.001000,0.010000,100.0000"));
+        assert!(dvh.to_json().unwrap().contains("\"d_95\""));
+    }
+
+    #[test]
+    fn test_trilinear_sampling_and_conformity() {
+        // A 2 mm dose grid rising along x, sampled at the 1 mm voxels of the mask grid
+        let dose = Volume::new((1, 3, 1), (1.0, 2.0, 1.0), vec![0.0f32, 20.0, 40.0]).unwrap();
+        let mask = Volume::new((1, 5, 1), (1.0, 1.0, 1.0), vec![0, 0, MASK_INSIDE, MASK_INSIDE, 0]).unwrap();
+        let dvh = Dvh::compute("GTV", &dose, &mask, &DvhOptions::default()).unwrap();
+        assert!((dvh.min_dose - 20.0).abs() < 1e-6 && (dvh.max_dose - 30.0).abs() < 1e-6);
+
+        // The 25 Gy isodose covers x >= 2.5 mm: voxels 3 and 4, half of the target
+        let indices = conformity_indices(&dose, &mask, 25.0).unwrap();
+        assert!((indices.coverage - 0.5).abs() < 1e-9);
+        assert!((indices.rtog - 1.0).abs() < 1e-9);
+        assert!((indices.paddick - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_repo_dvh_needs_absolute_dose_in_the_same_frame() {
+        use crate::dicom::ct_image::CTImage;
+        use crate::dicom::rt_dose::RTDose;
+        use crate::dicom::rt_struct::{Contour, ContourGeometry, Roi, StructureSet};
+        use crate::dicom::test_util::{test_ct_image, test_rt_dose};
+        use crate::dicom::{DicomRepo, ImageSeries};
+
+        // A header-only 2x2 CT slice without pixel source: stacking its pixels would fail
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ImageSeries::new(
+            "1.2.3".into(), "1.2".into(), "CT".into(), None, Some("1.2.9".into()), None, None,
+        ));
+        repo.add_ct_image(CTImage {
+            rows: 2,
+            columns: 2,
+            pixel_spacing: Some((1.0, 1.0)),
+            image_position_patient: Some((0.0, 0.0, 0.0)),
+            image_orientation_patient: Some((1.0, 0.0, 0.0, 0.0, 1.0, 0.0)),
+            pixel_data: Vec::new(),
+            ..test_ct_image()
+        });
+        repo.add_structure_set(StructureSet {
+            uid: "9.1".into(),
+            series_uid: "9.2".into(),
+            study_uid: "1.2".into(),
+            label: "RS".into(),
+            name: None,
+            date: None,
+            frame_of_reference_uid: Some("1.2.9".into()),
+            referenced_series_uid: Some("1.2.3".into()),
+            rois: vec![Roi {
+                number: 1,
+                name: "PTV".into(),
+                color: None,
+                roi_type: None,
+                observation_label: None,
+                frame_of_reference_uid: Some("1.2.9".into()),
+                generation_algorithm: None,
+                contours: vec![Contour {
+                    geometry: ContourGeometry::ClosedPlanar,
+                    points: vec![[-0.5, -0.5, 0.0], [1.5, -0.5, 0.0], [1.5, 1.5, 0.0], [-0.5, 1.5, 0.0]],
+                }],
+            }],
+        });
+        // 2 Gy over the slice
+        let dose = RTDose {
+            rows: 2,
+            columns: 2,
+            pixel_spacing: (1.0, 1.0),
+            image_position_patient: (0.0, 0.0, 0.0),
+            pixel_data: [4u16; 4].iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ..test_rt_dose()
+        };
+        repo.add_dose(RTDose { uid: "9.5".into(), dose_units: "RELATIVE".into(), ..dose.clone() });
+        repo.add_dose(RTDose { uid: "9.6".into(), frame_of_reference_uid: Some("1.2.8".into()), ..dose.clone() });
+        repo.add_dose(dose);
+
+        let options = DvhOptions::default();
+        let relative = repo.compute_dvh("9.1", 1, "9.5", &options).unwrap_err();
+        assert!(relative.to_string().contains("absolute dose"));
+        let elsewhere = repo.compute_dvh("9.1", 1, "9.6", &options).unwrap_err();
+        assert!(elsewhere.to_string().contains("frame of reference"));
+
+        let dvh = repo.compute_dvh("9.1", 1, "9.3", &options).unwrap();
+        assert!((dvh.mean_dose - 2.0).abs() < 1e-6);
+        assert!((dvh.volume - 0.004).abs() < 1e-9);
+    }
+}
@@ -1,155 +1,620 @@
 use anyhow::Result;
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
-};
+use std::sync::Arc;
 
 #[cfg(not(target_arch = "wasm32"))]
-use tokio::fs::{self, File};
+use tokio::fs::File;
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::io::AsyncReadExt;
 #[cfg(not(target_arch = "wasm32"))]
-use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::BTreeSet;
 
 use super::*;
+use super::dicom_helper::{get_value, is_missing_attribute};
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use std::path::{Path, PathBuf};
+
+/// Options controlling how DICOM files are read into a `DicomRepo`.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    // Read only the attributes before the pixel data and load pixels on demand
+    pub header_only: bool,
+    // Memory budget (bytes) of the cache holding pixel data loaded on demand
+    pub pixel_cache_budget: usize,
+    // Maximum number of files read and parsed at the same time
+    pub max_concurrency: usize,
+    // Which files of the given directories are parsed
+    #[cfg(not(target_arch = "wasm32"))]
+    pub scan: ScanOptions,
+    // Called with a progress snapshot after every discovered or completed file
+    pub progress: Option<ProgressCallback>,
+    // Aborts the scan and skips the files not yet parsed once cancelled
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            header_only: false,
+            pixel_cache_budget: DEFAULT_PIXEL_CACHE_BUDGET,
+            max_concurrency: default_max_concurrency(),
+            #[cfg(not(target_arch = "wasm32"))]
+            scan: ScanOptions::default(),
+            progress: None,
+            cancel: None,
+        }
+    }
+}
+
+// Twice the number of cores, so file reads overlap with parsing
+fn default_max_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|cores| cores.get() * 2)
+        .unwrap_or(8)
+}
+
+/// Entities built from a single DICOM file.
+///
+/// The file is parsed once and the same object feeds every entity builder. Entities the
+/// file does not describe (e.g. images of an unsupported modality) are left empty, and the
+/// reason is kept for the ingest report.
+pub(crate) struct ParsedFile {
+    pub(crate) patient: Option<Patient>,
+    pub(crate) study: Option<StudySet>,
+    pub(crate) series: Option<ImageSeries>,
+    pub(crate) images: Vec<ImageInstance>,
+    pub(crate) rt_object: Option<RtObject>,
+    pub(crate) source: Option<PathBuf>, // File the entities were read from, if known
+    pub(crate) status: IngestStatus,
+    pub(crate) reason: Option<String>, // Why entities were rejected, if any
+}
+
+// A radiotherapy object, stored in the repository next to the image hierarchy
+pub(crate) enum RtObject {
+    StructureSet(StructureSet),
+    Dose(RTDose),
+    Plan(RTPlan),
+}
+
+impl ParsedFile {
+    // Parse the DICOM data once and build all entities from it
+    pub(crate) fn from_bytes(dicom_data: &[u8]) -> Result<ParsedFile> {
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Ok(Self::from_object(&obj))
+    }
+
+    // Parse a file read whole, remembering where it came from
+    pub(crate) fn from_file_bytes(path: PathBuf, dicom_data: &[u8]) -> Result<ParsedFile> {
+        Ok(ParsedFile {
+            source: Some(path),
+            ..Self::from_bytes(dicom_data)?
+        })
+    }
+
+    // Read only the attributes before the pixel data, remembering the file for later loading;
+    // also returns the number of bytes read
+    pub(crate) fn from_header_file(path: PathBuf) -> Result<(ParsedFile, u64)> {
+        let (obj, bytes_read) = read_header_counted(&path)?;
+        let parsed = ParsedFile {
+            source: Some(path),
+            ..Self::from_object(&obj)
+        };
+        Ok((parsed, bytes_read))
+    }
+
+    pub(crate) fn from_object(obj: &FileDicomObject<InMemDicomObject>) -> ParsedFile {
+        let patient = Patient::from_object(obj);
+        let study = StudySet::from_object(obj);
+        let modality = get_value::<String>(obj, "Modality");
+        match modality.as_deref() {
+            Some(RTSTRUCT_MODALITY) => {
+                return Self::from_rt_object(patient, study, StructureSet::from_object(obj).map(RtObject::StructureSet))
+            }
+            Some(RTDOSE_MODALITY) => return Self::from_rt_object(patient, study, RTDose::from_object(obj).map(RtObject::Dose)),
+            Some(RTPLAN_MODALITY) => return Self::from_rt_object(patient, study, RTPlan::from_object(obj).map(RtObject::Plan)),
+            _ => {}
+        }
+        let series = ImageSeries::from_object(obj);
+        let images = ImageInstance::frames_from_object(obj);
+
+        // An unsupported modality explains the missing series and images on its own
+        let modality = modality.filter(|modality| !SUPPORTED_IMAGE_MODALITIES.contains(&modality.as_str()));
+        let (status, reason) = if let Some(modality) = modality {
+            (
+                IngestStatus::UnsupportedModality,
+                Some(format!("Unsupported modality {}", modality)),
+            )
+        } else {
+            let mut errors: Vec<String> = [
+                patient.as_ref().err(),
+                study.as_ref().err(),
+                series.as_ref().err(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|err| err.to_string())
+            .collect();
+            // Entity builders report absent required attributes as `MissingAttribute`
+            let status = match &images {
+                Err(err) if !is_missing_attribute(err) => IngestStatus::InvalidImage,
+                _ if errors.is_empty() && images.is_ok() => IngestStatus::Ingested,
+                _ => IngestStatus::MissingAttributes,
+            };
+            if let Err(err) = &images {
+                errors.push(format!("{:#}", err));
+            }
+            (status, (!errors.is_empty()).then(|| errors.join("; ")))
+        };
+
+        ParsedFile {
+            patient: patient.ok(),
+            study: study.ok(),
+            series: series.ok(),
+            images: images.unwrap_or_default(),
+            rt_object: None,
+            source: None,
+            status,
+            reason,
+        }
+    }
+
+    // RT objects hold no image series of their own; they link to the images through the
+    // frame of reference
+    fn from_rt_object(patient: Result<Patient>, study: Result<StudySet>, rt_object: Result<RtObject>) -> ParsedFile {
+        let status = match &rt_object {
+            Err(err) if !is_missing_attribute(err) => IngestStatus::InvalidImage,
+            _ if patient.is_ok() && study.is_ok() && rt_object.is_ok() => IngestStatus::Ingested,
+            _ => IngestStatus::MissingAttributes,
+        };
+        let errors: Vec<String> = [patient.as_ref().err(), study.as_ref().err(), rt_object.as_ref().err()]
+            .into_iter()
+            .flatten()
+            .map(|err| format!("{:#}", err))
+            .collect();
+
+        ParsedFile {
+            patient: patient.ok(),
+            study: study.ok(),
+            series: None,
+            images: Vec::new(),
+            rt_object: rt_object.ok(),
+            source: None,
+            status,
+            reason: (!errors.is_empty()).then(|| errors.join("; ")),
+        }
+    }
+
+    // Outcome of the file for the ingest report
+    pub(crate) fn outcome(&self, path: &Path) -> FileOutcome {
+        FileOutcome {
+            path: path.to_path_buf(),
+            status: self.status,
+            reason: self.reason.clone(),
+            images: self.images.len(),
+        }
+    }
+
+    // Add the parsed entities to the repository
+    pub(crate) fn add_to(self, repo: &mut DicomRepo) {
+        if let Some(patient) = self.patient {
+            repo.add_patient(patient);
+        }
+        if let Some(study) = self.study {
+            repo.add_study(study);
+        }
+        if let Some(series) = self.series {
+            repo.add_image_series(series);
+        }
+        match self.rt_object {
+            Some(RtObject::StructureSet(structure_set)) => repo.add_structure_set(structure_set),
+            Some(RtObject::Dose(dose)) => {
+                // Header-only doses load their grid from the file when a volume is built
+                if let Some(path) = &self.source {
+                    repo.add_pixel_source(dose.uid.clone(), path.clone());
+                }
+                repo.add_dose(dose);
+            }
+            Some(RtObject::Plan(plan)) => repo.add_plan(plan),
+            None => {}
+        }
+        for image in self.images {
+            if let Some(path) = &self.source {
+                repo.add_pixel_source(image.key(), path.clone());
+            }
+            repo.add_image(image);
+        }
+    }
+}
 
 /// Parses DICOM files from a list of directories and constructs a `DicomRepo`.
 ///
-/// This function traverses each provided directory asynchronously, collects file paths,
+/// This function traverses each provided directory recursively, collects file paths,
 /// and delegates the parsing of files to `parse_dcm_files`. It ensures that only files
-/// (not directories) are added to the list of file paths for processing.
+/// (not directories) are added to the list of file paths for processing. The patients,
+/// studies and series of any DICOMDIR found are added from its directory records; the files
+/// it lists are not parsed but registered as header-less images, read by
+/// `DicomRepo::read_series` once their series is opened.
 ///
 /// # Arguments
 /// - `directories`: A vector of directory paths containing DICOM files to process.
 ///
 /// # Returns
-/// A `Result` containing the constructed `DicomRepo` on success, or an error if any
-/// directory cannot be read.
+/// A `Result` containing the constructed `DicomRepo` and the `IngestReport` of every file
+/// found on success, or an error if any directory cannot be read.
 ///
 /// # Errors
-/// - If a directory cannot be opened or traversed, the function logs the error and returns it.
+/// - If a directory cannot be opened or traversed, the function returns the error.
 /// - Non-existent directories or permission issues may result in an error.
 ///
 /// # Example
-/// ```rust
+/// ```no_run
+/// use kepler_wgpu::dicom::fileio::parse_dcm_directories;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
 /// let directories = vec!["/path/to/dir1", "/path/to/dir2"];
-/// let repo = parse_dcm_directories(directories).await?;
+/// let (repo, report) = parse_dcm_directories(directories).await?;
+/// # Ok(())
+/// # }
 /// ```
 #[cfg(not(target_arch = "wasm32"))]
-pub async fn parse_dcm_directories(directories: Vec<&str>) -> Result<DicomRepo> {
-    let mut file_paths = Vec::new();
-
-    // Collect all files from the provided directories
-    for dir_path in directories {
-        let mut entries = fs::read_dir(dir_path).await.map_err(|err| {
-            eprintln!("Error reading directory {}: {}", dir_path, err);
-            anyhow::Error::new(err)
-        })?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            if path.is_file() {
-                file_paths.push(path);
+pub async fn parse_dcm_directories(directories: Vec<&str>) -> Result<(DicomRepo, IngestReport)> {
+    parse_dcm_directories_with_options(directories, ParseOptions::default()).await
+}
+
+/// Parses DICOM files from a list of directories as `parse_dcm_directories` does, with
+/// `options` controlling which files are scanned (`options.scan`) and how they are read
+/// (e.g. header-only indexing).
+///
+/// Progress is reported to `options.progress` as files are discovered and parsed. Once
+/// `options.cancel` is cancelled the scan stops, the remaining files are reported as
+/// `Cancelled` and the entities parsed so far are returned.
+///
+/// # Errors
+/// Same as `parse_dcm_directories`; invalid scan globs are reported as errors too.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn parse_dcm_directories_with_options(
+    directories: Vec<&str>,
+    options: ParseOptions,
+) -> Result<(DicomRepo, IngestReport)> {
+    let progress = Arc::new(ProgressTracker::new(options.progress.clone()));
+    let (repo, report, files) = scan_directories(directories, &options, &progress).await?;
+    parse_dcm_files_into(repo, report, files, options, progress).await
+}
+
+/// Parses DICOM files from a list of directories as `parse_dcm_directories_with_options`
+/// does, re-parsing only the files that changed since the index at `index_path` was written.
+///
+/// Files whose size and modification time match the index are restored from it as
+/// header-only images (pixel data is loaded on demand) and reported as `Indexed`; new and
+/// changed files are parsed, and files no longer found are dropped. The index is then
+/// rewritten, unless the ingest was cancelled. A missing, outdated or unreadable index
+/// only means that every file is parsed.
+///
+/// # Errors
+/// Same as `parse_dcm_directories`, or if the updated index cannot be written.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn parse_dcm_directories_indexed(
+    directories: Vec<&str>,
+    index_path: &Path,
+    options: ParseOptions,
+) -> Result<(DicomRepo, IngestReport)> {
+    let progress = Arc::new(ProgressTracker::new(options.progress.clone()));
+    let (repo, mut report, files) = scan_directories(directories, &options, &progress).await?;
+
+    // Validating the index stats every file, so it runs off the async workers
+    let path = index_path.to_path_buf();
+    let (repo, outcomes, changed) = tokio::task::spawn_blocking(move || {
+        let mut repo = repo;
+        let index = match RepoIndex::load(&path) {
+            Ok(index) => Some(index),
+            Err(err) => {
+                if path.exists() {
+                    log::warn!("Ignoring index {}: {}", path.display(), err);
+                }
+                None
             }
-        }
+        };
+        let (outcomes, changed) = match index {
+            Some(index) => index.restore_unchanged(&mut repo, files),
+            None => (Vec::new(), files),
+        };
+        (repo, outcomes, changed)
+    })
+    .await?;
+    for outcome in outcomes {
+        progress.completed(&outcome);
+        report.record(outcome);
+    }
+
+    let (repo, report) = parse_dcm_files_into(repo, report, changed, options, progress).await?;
+    if report.cancelled {
+        return Ok((repo, report));
+    }
+    let path = index_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        repo.save_index(&path)?;
+        Ok((repo, report))
+    })
+    .await?
+}
+
+// Collect the files below the directories and read the DICOMDIRs among them, returning
+// the repository holding the DICOMDIR records and the files to parse
+#[cfg(not(target_arch = "wasm32"))]
+async fn scan_directories(
+    directories: Vec<&str>,
+    options: &ParseOptions,
+    progress: &Arc<ProgressTracker>,
+) -> Result<(DicomRepo, IngestReport, Vec<PathBuf>)> {
+    let cancel = options.cancel.clone().unwrap_or_default();
+
+    // Collect all files from the provided directories, off the async workers
+    let roots: Vec<String> = directories.iter().map(|dir| dir.to_string()).collect();
+    let scan_options = options.scan.clone();
+    let scan_progress = Arc::clone(progress);
+    let scan_cancel = cancel.clone();
+    let scan = tokio::task::spawn_blocking(move || {
+        let roots: Vec<&str> = roots.iter().map(String::as_str).collect();
+        collect_dicom_files_with(&roots, &scan_options, &scan_cancel, |_| scan_progress.discovered(1))
+    })
+    .await??;
+
+    let mut report = IngestReport::new();
+    for (path, reason) in scan.errors {
+        report.record(FileOutcome {
+            path,
+            status: IngestStatus::ReadError,
+            reason: Some(reason),
+            images: 0,
+        });
+    }
+
+    // DICOMDIR records describe the hierarchy up front; the files they list are not parsed
+    // but read once their series is opened, and entries parsed from other files take precedence
+    let mut repo = DicomRepo::new();
+    let mut files: BTreeSet<PathBuf> = scan.files.into_iter().collect();
+    for dicomdir_path in scan.dicomdirs {
+        let (status, reason) = if cancel.is_cancelled() {
+            (IngestStatus::Cancelled, None)
+        } else {
+            match read_dicomdir(&dicomdir_path) {
+                Ok(mut dicomdir) => {
+                    // Files outside the scan (missing, or filtered out) stay unknown
+                    dicomdir.instances.retain(|instance| files.contains(&instance.path));
+                    dicomdir.add_to(&mut repo);
+                    for instance in &dicomdir.instances {
+                        if files.remove(&instance.path) {
+                            let outcome = FileOutcome {
+                                path: instance.path.clone(),
+                                status: IngestStatus::Listed,
+                                reason: None,
+                                images: 0,
+                            };
+                            progress.completed(&outcome);
+                            report.record(outcome);
+                        }
+                    }
+                    (IngestStatus::DirectoryIndex, None)
+                }
+                Err(err) => (IngestStatus::NotDicom, Some(err.to_string())),
+            }
+        };
+        let outcome = FileOutcome {
+            path: dicomdir_path,
+            status,
+            reason,
+            images: 0,
+        };
+        progress.completed(&outcome);
+        report.record(outcome);
     }
-    parse_dcm_files(file_paths).await
+    Ok((repo, report, files.into_iter().collect()))
 }
 
 /// Parses a list of DICOM files concurrently and constructs a `DicomRepo`.
 ///
-/// This function reads the contents of each file asynchronously, parses DICOM data,
-/// and updates a shared `DicomRepo`. File processing is performed concurrently using
-/// Tokio's task spawning, and the shared repository is updated in a thread-safe manner.
+/// This function reads the contents of each file asynchronously, parses the DICOM data on
+/// Tokio's blocking pool, and merges the parsed entities into a `DicomRepo` once every
+/// file is done.
 ///
 /// # Arguments
 /// - `file_paths`: A vector of file paths to DICOM files to process.
 ///
 /// # Returns
-/// A `Result` containing the constructed `DicomRepo` on success, or an error if any
-/// critical operation (e.g., file I/O) fails.
+/// A `Result` containing the constructed `DicomRepo` and an `IngestReport` listing the
+/// outcome of every file, sorted by path.
 ///
 /// # Errors
-/// - If a file cannot be opened, read, or parsed, an error will be logged, and the function
-///   will continue processing the remaining files. Non-fatal errors will not stop the process.
+/// - If a file cannot be opened, read, or parsed, its outcome is recorded in the report
+///   and the function continues processing the remaining files.
 ///
 /// # Concurrency
-/// - Parsing of DICOM data is done outside of the critical section to minimize the time spent
-///   holding the `Mutex` lock on the shared repository.
-/// - Each file is processed in a separate Tokio task, enabling high concurrency.
+/// - At most `ParseOptions::max_concurrency` files (twice the number of cores by default)
+///   are open or being parsed at a time, bounding file descriptors and memory.
+/// - Parsing runs on the blocking pool, keeping the async workers free for file reads.
+/// - Tasks return their entities instead of locking a shared repository; the results
+///   are merged at the end, in path order.
 ///
 /// # Example
-/// ```rust
+/// ```no_run
+/// use kepler_wgpu::dicom::fileio::parse_dcm_files;
+/// use std::path::PathBuf;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
 /// let files = vec![PathBuf::from("file1.dcm"), PathBuf::from("file2.dcm")];
-/// let repo = parse_dcm_files(files).await?;
+/// let (repo, report) = parse_dcm_files(files).await?;
+/// println!("{}", report.to_json()?);
+/// # Ok(())
+/// # }
 /// ```
 #[cfg(not(target_arch = "wasm32"))]
-pub async fn parse_dcm_files(file_paths: Vec<std::path::PathBuf>) -> Result<DicomRepo> {
-    // Shared repository and counter tracker
-    let repo = Arc::new(Mutex::new(DicomRepo::new()));
-    let count = Arc::new(AtomicUsize::new(0));
+pub async fn parse_dcm_files(file_paths: Vec<std::path::PathBuf>) -> Result<(DicomRepo, IngestReport)> {
+    parse_dcm_files_with_options(file_paths, ParseOptions::default()).await
+}
+
+/// Parses a list of DICOM files as `parse_dcm_files` does, with `options` controlling how
+/// the files are read.
+///
+/// With `header_only` set, each file is read up to its pixel data only; the pixel data is
+/// loaded on demand (e.g. by `generate_ct_volume`) and kept in an LRU cache bounded by
+/// `pixel_cache_budget`. Progress and cancellation work as in
+/// `parse_dcm_directories_with_options`, every given file counting as discovered up front.
+///
+/// # Errors
+/// Same as `parse_dcm_files`.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn parse_dcm_files_with_options(
+    file_paths: Vec<std::path::PathBuf>,
+    options: ParseOptions,
+) -> Result<(DicomRepo, IngestReport)> {
+    let progress = Arc::new(ProgressTracker::new(options.progress.clone()));
+    progress.discovered(file_paths.len());
+    parse_dcm_files_into(DicomRepo::new(), IngestReport::new(), file_paths, options, progress).await
+}
+
+/// Parses additional DICOM files and merges them into an existing `DicomRepo`.
+///
+/// The files are parsed as by `parse_dcm_files_with_options`; entities already in `repo`
+/// are replaced by those parsed from the new files.
+///
+/// # Errors
+/// Same as `parse_dcm_files`.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn merge_dcm_files(
+    repo: &mut DicomRepo,
+    file_paths: Vec<std::path::PathBuf>,
+    options: ParseOptions,
+) -> Result<IngestReport> {
+    let (parsed, report) = parse_dcm_files_with_options(file_paths, options).await?;
+    repo.merge(parsed);
+    Ok(report)
+}
 
-    // Process files concurrently
+// Read and parse one file; the entities are returned for merging into the repository
+#[cfg(not(target_arch = "wasm32"))]
+async fn parse_dcm_file(
+    file_path: PathBuf,
+    header_only: bool,
+    progress: &ProgressTracker,
+) -> (FileOutcome, Option<ParsedFile>) {
+    let failure = |status, reason: String| FileOutcome {
+        path: file_path.clone(),
+        status,
+        reason: Some(reason),
+        images: 0,
+    };
+
+    // Parsing is CPU-bound (and header-only reads block), so it runs on the blocking pool
+    let parsed = if header_only {
+        // Header-only reads stop before the pixel data, so the file is never read whole
+        let path = file_path.clone();
+        let parsed = tokio::task::spawn_blocking(move || ParsedFile::from_header_file(path)).await;
+        parsed.map(|parsed| {
+            parsed.map(|(parsed, bytes_read)| {
+                progress.read(bytes_read);
+                parsed
+            })
+        })
+    } else {
+        // Read the file contents into a buffer asynchronously
+        let mut buffer = vec![];
+        let read = match File::open(&file_path).await {
+            Ok(mut file) => file.read_to_end(&mut buffer).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = read {
+            return (failure(IngestStatus::ReadError, err.to_string()), None);
+        }
+        progress.read(buffer.len() as u64);
+
+        // Parse the DICOM data once
+        let path = file_path.clone();
+        tokio::task::spawn_blocking(move || ParsedFile::from_file_bytes(path, &buffer)).await
+    };
+
+    match parsed {
+        Ok(Ok(parsed)) => (parsed.outcome(&file_path), Some(parsed)),
+        Ok(Err(err)) => (failure(IngestStatus::NotDicom, err.to_string()), None),
+        Err(join_err) => (
+            failure(IngestStatus::Failed, format!("Parsing panicked: {}", join_err)),
+            None,
+        ),
+    }
+}
+
+// Parse the files into an existing repository, adding their outcomes to the report
+#[cfg(not(target_arch = "wasm32"))]
+async fn parse_dcm_files_into(
+    mut repo: DicomRepo,
+    mut report: IngestReport,
+    file_paths: Vec<std::path::PathBuf>,
+    options: ParseOptions,
+    progress: Arc<ProgressTracker>,
+) -> Result<(DicomRepo, IngestReport)> {
+    repo.set_pixel_cache_budget(options.pixel_cache_budget);
+    let cancel = options.cancel.unwrap_or_default();
+    // At most `max_concurrency` files are open or being parsed at any time
+    let permits = Arc::new(Semaphore::new(options.max_concurrency.max(1)));
+
+    // Process files concurrently; each task returns its entities instead of sharing the
+    // repository, so no lock is taken while parsing
     let mut tasks = vec![];
     for file_path in file_paths {
-        let repo_clone = Arc::clone(&repo);
-        let count_clone = Arc::clone(&count);
-
-        let task: tokio::task::JoinHandle<Result<(), anyhow::Error>> = tokio::spawn(async move {
-            // Open the file asynchronously
-            let mut file = File::open(&file_path).await.map_err(|err| {
-                eprintln!("Error opening file {}: {}", file_path.display(), err);
-                anyhow::Error::new(err)
-            })?;
-
-            // Read the file contents into a buffer
-            let mut buffer = vec![];
-            file.read_to_end(&mut buffer).await.map_err(|err| {
-                eprintln!("Error reading file {}: {}", file_path.display(), err);
-                anyhow::Error::new(err)
-            })?;
-
-            // Parse the DICOM data outside of the lock
-            let parsed_patient = Patient::from_bytes(&buffer);
-            let parsed_study = StudySet::from_bytes(&buffer);
-            let parsed_series = ImageSeries::from_bytes(&buffer);
-            let parsed_ct_image = CTImage::from_bytes(&buffer);
-
-            // Update the repository with parsed data
-            let mut repo = repo_clone.lock().await;
-
-            if let Ok(patient) = parsed_patient {
-                repo.add_patient(patient);
-            }
-            if let Ok(study) = parsed_study {
-                repo.add_study(study);
-            }
-            if let Ok(series) = parsed_series {
-                repo.add_image_series(series);
-            }
-            if let Ok(ct_image) = parsed_ct_image {
-                repo.add_ct_image(ct_image);
-            }
-
-            count_clone.fetch_add(1, Ordering::SeqCst);
-            Ok(())
+        let permits_clone = Arc::clone(&permits);
+        let progress_clone = Arc::clone(&progress);
+        let cancel_clone = cancel.clone();
+        let path = file_path.clone();
+        let task = tokio::spawn(async move {
+            let _permit = permits_clone.acquire_owned().await;
+            // Files still waiting for a permit are skipped once the ingest is cancelled
+            let (outcome, parsed) = if cancel_clone.is_cancelled() {
+                let outcome = FileOutcome {
+                    path: file_path,
+                    status: IngestStatus::Cancelled,
+                    reason: None,
+                    images: 0,
+                };
+                (outcome, None)
+            } else {
+                parse_dcm_file(file_path, options.header_only, &progress_clone).await
+            };
+            progress_clone.completed(&outcome);
+            (outcome, parsed)
         });
-
-        tasks.push(task);
+        tasks.push((path, task));
     }
 
     // Wait for all tasks to complete
-    for task in tasks {
-        match task.await {
-            Ok(Ok(())) => { /* Task succeeded */ }
-            Ok(Err(err)) => eprintln!("Task error: {}", err),
-            Err(join_err) => eprintln!("Task panicked or was cancelled: {:?}", join_err),
+    let mut parsed_files = Vec::with_capacity(tasks.len());
+    for (path, task) in tasks {
+        let (outcome, parsed) = task.await.unwrap_or_else(|join_err| {
+            let outcome = FileOutcome {
+                path,
+                status: IngestStatus::Failed,
+                reason: Some(format!("Task panicked or was cancelled: {}", join_err)),
+                images: 0,
+            };
+            progress.completed(&outcome);
+            (outcome, None)
+        });
+        if let Some(parsed) = parsed {
+            parsed_files.push((outcome.path.clone(), parsed));
         }
+        report.record(outcome);
     }
+    report.sort();
+    report.cancelled = cancel.is_cancelled();
 
-    // Extract the final repository and return it
-    let repo = repo.lock().await;
-    Ok((*repo).clone())
+    // Merge in path order, so entities described by several files resolve the same way
+    // whatever order the tasks finished in
+    parsed_files.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, parsed) in parsed_files {
+        parsed.add_to(&mut repo);
+    }
+    Ok((repo, report))
 }
 
 //------------------------------ WASM Code -------------------------------------
@@ -160,9 +625,34 @@ use js_sys::{Array, Promise, Uint8Array};
 use wasm_bindgen::prelude::*;
 
 
+// Pass a progress snapshot to the JavaScript callback as a plain object
+#[cfg(target_arch = "wasm32")]
+fn emit_progress_js(on_progress: &Option<js_sys::Function>, progress: &IngestProgress) {
+    let Some(callback) = on_progress else {
+        return;
+    };
+    let snapshot = serde_json::to_string(progress)
+        .ok()
+        .and_then(|json| js_sys::JSON::parse(&json).ok());
+    if let Some(snapshot) = snapshot {
+        if let Err(err) = callback.call1(&JsValue::NULL, &snapshot) {
+            log::error!("Progress callback failed: {:?}", err);
+        }
+    }
+}
+
+/// Parses the given `File` objects into a `DicomRepo`.
+///
+/// `on_progress` is called with `{ discovered, parsed, failed, bytes_read }` after the files
+/// are listed and after each file completes. Files read after `cancel` (a handle obtained
+/// from `CancellationToken.handle()`) is cancelled are skipped.
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[cfg(target_arch = "wasm32")]
-pub async fn parse_dcm_files_wasm(files: Array) -> Result<DicomRepo, JsValue> {
+pub async fn parse_dcm_files_wasm(
+    files: Array,
+    on_progress: Option<js_sys::Function>,
+    cancel: Option<CancellationToken>,
+) -> Result<DicomRepo, JsValue> {
     // use futures::channel::oneshot;
     // use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::{Arc, Mutex};
@@ -170,9 +660,14 @@ pub async fn parse_dcm_files_wasm(files: Array) -> Result<DicomRepo, JsValue> {
     use web_sys::{File, FileReader, ProgressEvent, console};
     use log::error;
 
-    // Shared repository and counter
+    // Shared repository and ingest report
     let repo = Arc::new(Mutex::new(DicomRepo::new()));
+    let report = Arc::new(Mutex::new(IngestReport::new()));
     let len = files.length() as usize;
+    let progress = Arc::new(ProgressTracker::new(None));
+    let cancel = cancel.unwrap_or_default();
+    progress.discovered(len);
+    emit_progress_js(&on_progress, &progress.snapshot());
 
     // Processing each file asynchronously
     let tasks: Vec<Promise> = (0..len)
@@ -184,6 +679,11 @@ pub async fn parse_dcm_files_wasm(files: Array) -> Result<DicomRepo, JsValue> {
             // Create a promise for each file
             let promise = Promise::new(&mut |resolve, reject| {
                 let repo_clone = Arc::clone(&repo);
+                let report_clone = Arc::clone(&report);
+                let progress_clone = Arc::clone(&progress);
+                let cancel_clone = cancel.clone();
+                let on_progress_clone = on_progress.clone();
+                let path = PathBuf::from(file.name());
                 // The closure now correctly accepts the `ProgressEvent`
                 let closure = Closure::once_into_js(move |event: ProgressEvent| {
                     let result: Result<(), String> = {
@@ -195,21 +695,37 @@ pub async fn parse_dcm_files_wasm(files: Array) -> Result<DicomRepo, JsValue> {
                             // .map_err(|| JsValue::from("Failed to retrieve file result"))?;
 
                         let buffer = Uint8Array::new(&buffer).to_vec();
+                        progress_clone.read(buffer.len() as u64);
 
-                        // Parse the DICOM and update repository
-                        let mut repo = repo_clone.lock().unwrap();
-                        if let Ok(patient) = Patient::from_bytes(&buffer) {
-                            repo.add_patient(patient);
-                        }
-                        if let Ok(study) = StudySet::from_bytes(&buffer) {
-                            repo.add_study(study);
-                        }
-                        if let Ok(series) = ImageSeries::from_bytes(&buffer) {
-                            repo.add_image_series(series);
-                        }
-                        if let Ok(ct_image) = CTImage::from_bytes(&buffer) {
-                            repo.add_ct_image(ct_image);
-                        }
+                        // Parse the DICOM once and update repository
+                        let parsed = if cancel_clone.is_cancelled() {
+                            None
+                        } else {
+                            Some(ParsedFile::from_bytes(&buffer))
+                        };
+                        let outcome = match parsed {
+                            None => FileOutcome {
+                                path,
+                                status: IngestStatus::Cancelled,
+                                reason: None,
+                                images: 0,
+                            },
+                            Some(Ok(parsed)) => {
+                                let outcome = parsed.outcome(&path);
+                                let mut repo = repo_clone.lock().unwrap();
+                                parsed.add_to(&mut repo);
+                                outcome
+                            }
+                            Some(Err(err)) => FileOutcome {
+                                path,
+                                status: IngestStatus::NotDicom,
+                                reason: Some(err.to_string()),
+                                images: 0,
+                            },
+                        };
+                        progress_clone.completed(&outcome);
+                        emit_progress_js(&on_progress_clone, &progress_clone.snapshot());
+                        report_clone.lock().unwrap().record(outcome);
 
                         Ok(())
                     };
@@ -233,7 +749,164 @@ pub async fn parse_dcm_files_wasm(files: Array) -> Result<DicomRepo, JsValue> {
     let all_promise = js_sys::Promise::all(&tasks.into_iter().collect::<Array>());
     wasm_bindgen_futures::JsFuture::from(all_promise).await?;
 
+    // Log the ingest report for the pipeline
+    let mut report = report.lock().map_err(|e| JsValue::from(e.to_string()))?;
+    report.sort();
+    report.cancelled = cancel.is_cancelled();
+    for failure in report.failures() {
+        error!("{}: {:?} {}", failure.path.display(), failure.status, failure.reason.as_deref().unwrap_or(""));
+    }
+    if let Ok(json) = report.to_json() {
+        log::info!("Ingest report: {}", json);
+    }
+
     // Return the DicomRepo directly as a JsValue
     let repo = repo.lock().map_err(|e| JsValue::from(e.to_string()))?;
     Ok(repo.clone())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::dicomdir::tests::{dicomdir_object, record};
+    use crate::dicom::pixel_cache::tests::{ct_object, write_ct_file};
+    use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+    use std::fs;
+
+    #[test]
+    fn test_single_parse_matches_per_entity_builders() {
+        let mut obj = ct_object("1.2.3.4");
+        let text = |tag, vr, value: &str| DataElement::new(tag, vr, PrimitiveValue::from(value));
+        obj.put(text(Tag(0x0010, 0x0010), VR::PN, "Doe^Jane"));
+        obj.put(text(Tag(0x0010, 0x0020), VR::LO, "P1"));
+        obj.put(text(Tag(0x0020, 0x000D), VR::UI, "1.2"));
+        let mut bytes = Vec::new();
+        obj.write_all(&mut bytes).unwrap();
+
+        let parsed = ParsedFile::from_object(&obj);
+        assert_eq!(parsed.status, IngestStatus::Ingested);
+        assert!(parsed.reason.is_none() && parsed.rt_object.is_none());
+        let debug = |entity: &dyn std::fmt::Debug| format!("{:?}", entity);
+        assert_eq!(debug(&parsed.patient.unwrap()), debug(&Patient::from_bytes(&bytes).unwrap()));
+        assert_eq!(debug(&parsed.study.unwrap()), debug(&StudySet::from_bytes(&bytes).unwrap()));
+        assert_eq!(debug(&parsed.series.unwrap()), debug(&ImageSeries::from_bytes(&bytes).unwrap()));
+        assert_eq!(debug(&parsed.images), debug(&vec![ImageInstance::from_bytes(&bytes).unwrap()]));
+    }
+
+    #[test]
+    fn test_missing_attributes_are_told_from_invalid_images() {
+        let mut obj = ct_object("1.2.3.4");
+        obj.remove_element(Tag(0x0028, 0x0010));
+        let parsed = ParsedFile::from_object(&obj);
+        assert_eq!(parsed.status, IngestStatus::MissingAttributes);
+        assert!(parsed.reason.unwrap().contains("Missing Rows"));
+
+        // Three frames announced, but pixel data for one
+        let mut obj = ct_object("1.2.3.4");
+        obj.put(DataElement::new(Tag(0x0028, 0x0008), VR::IS, PrimitiveValue::from("3")));
+        assert_eq!(ParsedFile::from_object(&obj).status, IngestStatus::InvalidImage);
+    }
+
+    #[tokio::test]
+    async fn test_dicomdir_listed_files_are_not_opened() {
+        let root = std::env::temp_dir().join(format!("kepler-dicomdir-{}", std::process::id()));
+        fs::create_dir_all(root.join("DICOM")).unwrap();
+        let records = vec![
+            record("PATIENT", &[(Tag(0x0010, 0x0020), VR::LO, "P1"), (Tag(0x0010, 0x0010), VR::PN, "Doe^J")]),
+            record("STUDY", &[(Tag(0x0020, 0x000D), VR::UI, "1.2")]),
+            record("SERIES", &[(Tag(0x0020, 0x000E), VR::UI, "1.2.3"), (Tag(0x0008, 0x0060), VR::CS, "CT")]),
+            record("IMAGE", &[
+                (Tag(0x0004, 0x1511), VR::UI, "1.2.3.4"),
+                (Tag(0x0004, 0x1500), VR::CS, "DICOM\\CT1"),
+            ]),
+        ];
+        dicomdir_object(records).write_to_file(root.join("DICOMDIR")).unwrap();
+        // Not DICOM at all, so parsing it would reject it
+        let listed = root.join("DICOM").join("CT1");
+        fs::write(&listed, b"not opened").unwrap();
+
+        let (mut repo, report) = parse_dcm_directories(vec![root.to_str().unwrap()]).await.unwrap();
+        assert_eq!(report.count(IngestStatus::NotDicom), 0);
+        assert_eq!(report.count(IngestStatus::Listed), 1);
+        assert_eq!(report.files.iter().find(|outcome| outcome.path == listed).unwrap().status, IngestStatus::Listed);
+        assert_eq!((repo.series_image_count("1.2.3"), repo.unread_image_count("1.2.3")), (1, 1));
+        assert!(repo.get_images_by_series("1.2.3").is_empty());
+        assert!(repo.generate_ct_volume_with_options("1.2.3", &Default::default()).is_err());
+
+        // Opening the series reads the listed file
+        write_ct_file(&listed);
+        assert_eq!(repo.read_series("1.2.3").unwrap(), 1);
+        assert_eq!(repo.unread_image_count("1.2.3"), 0);
+        assert_eq!(repo.load_image("1.2.3.4").unwrap().get_pixel_data().unwrap(), vec![7, 9]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_header_only_ingest_reports_bytes_read() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        // A 256x256 slice: its 128 KiB of pixel data are not read
+        let file = std::env::temp_dir().join(format!("kepler-header-bytes-{}.dcm", std::process::id()));
+        let mut obj = ct_object("1.2.3.4");
+        obj.put(DataElement::new(Tag(0x0028, 0x0010), VR::US, PrimitiveValue::from(256u16)));
+        obj.put(DataElement::new(Tag(0x0028, 0x0011), VR::US, PrimitiveValue::from(256u16)));
+        obj.put(DataElement::new(PIXEL_DATA_TAG, VR::OW, PrimitiveValue::U16(vec![0; 256 * 256].into())));
+        obj.write_to_file(&file).unwrap();
+        let file_len = fs::metadata(&file).unwrap().len();
+
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        let last = bytes_read.clone();
+        let options = ParseOptions {
+            header_only: true,
+            progress: Some(ProgressCallback::new(move |progress| {
+                last.store(progress.bytes_read, Ordering::SeqCst)
+            })),
+            ..ParseOptions::default()
+        };
+        let (repo, _) = parse_dcm_files_with_options(vec![file.clone()], options).await.unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(repo.series_image_count("1.2.3"), 1);
+        let bytes_read = bytes_read.load(Ordering::SeqCst);
+        assert!(bytes_read > 0 && bytes_read < file_len / 2, "{} of {} bytes", bytes_read, file_len);
+    }
+
+    #[tokio::test]
+    async fn test_result_does_not_depend_on_concurrency() {
+        let dir = std::env::temp_dir().join(format!("kepler-concurrency-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut files = Vec::new();
+        for i in 0..6 {
+            let file = dir.join(format!("ct{}.dcm", i));
+            ct_object(&format!("1.2.3.4.{}", i)).write_to_file(&file).unwrap();
+            files.push(file);
+        }
+        let not_dicom = dir.join("notes.dcm");
+        fs::write(&not_dicom, b"not dicom").unwrap();
+        files.push(not_dicom);
+        // Handed over out of order, so the result has to be sorted
+        files.reverse();
+
+        let parse = |max_concurrency| {
+            let options = ParseOptions {
+                max_concurrency,
+                ..ParseOptions::default()
+            };
+            parse_dcm_files_with_options(files.clone(), options)
+        };
+        let (serial_repo, serial_report) = parse(1).await.unwrap();
+        let (parallel_repo, parallel_report) = parse(8).await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let outcomes = |report: &IngestReport| -> Vec<(PathBuf, IngestStatus, usize)> {
+            report.files.iter().map(|outcome| (outcome.path.clone(), outcome.status, outcome.images)).collect()
+        };
+        assert_eq!(outcomes(&serial_report), outcomes(&parallel_report));
+        assert!(serial_report.files.windows(2).all(|pair| pair[0].path < pair[1].path));
+        assert_eq!(serial_report.count(IngestStatus::NotDicom), 1);
+
+        let images = |repo: &DicomRepo| -> Vec<String> {
+            repo.get_images_by_series("1.2.3").iter().map(|image| format!("{:?}", image)).collect()
+        };
+        assert_eq!(images(&serial_repo).len(), 6);
+        assert_eq!(images(&serial_repo), images(&parallel_repo));
+    }
+}
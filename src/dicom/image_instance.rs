@@ -0,0 +1,312 @@
+use super::ct_image::CTImage;
+use super::dicom_helper::{get_value, MissingAttribute};
+use super::mr_image::MRImage;
+use super::pt_image::PTImage;
+use anyhow::{anyhow, Result};
+use dicom_object::{FileDicomObject, InMemDicomObject};
+
+// Modalities whose images can be stored in a `DicomRepo` and stacked into a volume
+pub const SUPPORTED_IMAGE_MODALITIES: &[&str] = &["CT", "MR", "PT"];
+
+// Common view of a single image plane, whatever modality it was acquired with
+pub trait DicomImage {
+    fn uid(&self) -> &str;
+    fn series_uid(&self) -> &str;
+    fn modality(&self) -> &str;
+
+    // 1-based frame number for images split out of an Enhanced multi-frame object
+    fn frame_number(&self) -> Option<u32>;
+    fn instance_number(&self) -> Option<i32>;
+    // Acquisition the image belongs to, separating e.g. repeated scans sharing a series
+    fn acquisition_number(&self) -> Option<i32>;
+
+    fn rows(&self) -> u16;
+    fn columns(&self) -> u16;
+    fn pixel_spacing(&self) -> Option<(f32, f32)>;
+    fn slice_thickness(&self) -> Option<f32>;
+    fn spacing_between_slices(&self) -> Option<f32>;
+    fn gantry_detector_tilt(&self) -> Option<f32>;
+    fn image_position_patient(&self) -> Option<(f32, f32, f32)>;
+    fn image_orientation_patient(&self) -> Option<(f32, f32, f32, f32, f32, f32)>;
+
+    // Modality values (HU for CT, signal for MR, activity for PT) clamped to `i16`
+    fn get_pixel_data(&self) -> Result<Vec<i16>>;
+
+    // Modality values in full precision; PT images return SUV when it can be computed
+    fn get_pixel_data_f32(&self) -> Result<Vec<f32>>;
+
+    // Same as the getters above, but decoding native samples held outside the image (e.g. in
+    // the pixel cache of a header-only repository) instead of its own pixel data
+    fn get_pixel_data_from(&self, pixel_data: &[u8]) -> Result<Vec<i16>>;
+    fn get_pixel_data_f32_from(&self, pixel_data: &[u8]) -> Result<Vec<f32>>;
+}
+
+macro_rules! impl_dicom_image {
+    ($name:ident, $modality:expr) => {
+        impl DicomImage for $name {
+            fn uid(&self) -> &str {
+                &self.uid
+            }
+            fn series_uid(&self) -> &str {
+                &self.series_uid
+            }
+            fn modality(&self) -> &str {
+                $modality
+            }
+            fn frame_number(&self) -> Option<u32> {
+                impl_dicom_image!(@frame $name, self)
+            }
+            fn instance_number(&self) -> Option<i32> {
+                self.instance_number
+            }
+            fn acquisition_number(&self) -> Option<i32> {
+                impl_dicom_image!(@acquisition $name, self)
+            }
+            fn rows(&self) -> u16 {
+                self.rows
+            }
+            fn columns(&self) -> u16 {
+                self.columns
+            }
+            fn pixel_spacing(&self) -> Option<(f32, f32)> {
+                self.pixel_spacing
+            }
+            fn slice_thickness(&self) -> Option<f32> {
+                self.slice_thickness
+            }
+            fn spacing_between_slices(&self) -> Option<f32> {
+                impl_dicom_image!(@spacing $name, self)
+            }
+            fn gantry_detector_tilt(&self) -> Option<f32> {
+                impl_dicom_image!(@tilt $name, self)
+            }
+            fn image_position_patient(&self) -> Option<(f32, f32, f32)> {
+                self.image_position_patient
+            }
+            fn image_orientation_patient(&self) -> Option<(f32, f32, f32, f32, f32, f32)> {
+                self.image_orientation_patient
+            }
+            fn get_pixel_data(&self) -> Result<Vec<i16>> {
+                $name::get_pixel_data(self)
+            }
+            fn get_pixel_data_f32(&self) -> Result<Vec<f32>> {
+                DicomImage::get_pixel_data_f32_from(self, &self.pixel_data)
+            }
+            fn get_pixel_data_from(&self, pixel_data: &[u8]) -> Result<Vec<i16>> {
+                $name::get_pixel_data_from(self, pixel_data)
+            }
+            fn get_pixel_data_f32_from(&self, pixel_data: &[u8]) -> Result<Vec<f32>> {
+                impl_dicom_image!(@f32 $name, self, pixel_data)
+            }
+        }
+    };
+    (@tilt CTImage, $self:ident) => {
+        $self.gantry_detector_tilt
+    };
+    (@tilt $name:ident, $self:ident) => {
+        None
+    };
+    (@acquisition CTImage, $self:ident) => {
+        $self.acquisition_number
+    };
+    (@acquisition $name:ident, $self:ident) => {
+        None
+    };
+    (@spacing PTImage, $self:ident) => {
+        None
+    };
+    (@spacing $name:ident, $self:ident) => {
+        $self.spacing_between_slices
+    };
+    (@frame PTImage, $self:ident) => {
+        None
+    };
+    (@frame $name:ident, $self:ident) => {
+        $self.frame_number
+    };
+    (@f32 CTImage, $self:ident, $pixel_data:ident) => {
+        Ok($self.get_pixel_data_i32_from($pixel_data)?.into_iter().map(|v| v as f32).collect())
+    };
+    (@f32 PTImage, $self:ident, $pixel_data:ident) => {
+        match $self.get_suv_values_from($pixel_data) {
+            Ok(suv) => Ok(suv),
+            Err(_) => $self.get_pixel_data_f32_from($pixel_data),
+        }
+    };
+    (@f32 $name:ident, $self:ident, $pixel_data:ident) => {
+        $self.get_pixel_data_f32_from($pixel_data)
+    };
+}
+
+impl_dicom_image!(CTImage, "CT");
+impl_dicom_image!(MRImage, "MR");
+impl_dicom_image!(PTImage, "PT");
+
+// An image of any supported modality, as stored in `DicomRepo`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum ImageInstance {
+    CT(CTImage),
+    MR(MRImage),
+    PT(PTImage),
+}
+
+impl ImageInstance {
+    // Function to parse the DICOM file and generate the image of the matching modality
+    pub fn from_bytes(dicom_data: &[u8]) -> Result<ImageInstance> {
+        // Parse the DICOM file into a `FileDicomObject`
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
+
+    // Function to generate the image of the matching modality from an already parsed DICOM file
+    pub fn from_object(obj: &FileDicomObject<InMemDicomObject>) -> Result<ImageInstance> {
+        let modality = get_value::<String>(obj, "Modality")
+            .ok_or(MissingAttribute("Modality"))?;
+        match modality.as_str() {
+            "CT" => Ok(ImageInstance::CT(CTImage::from_object(obj)?)),
+            "MR" => Ok(ImageInstance::MR(MRImage::from_object(obj)?)),
+            "PT" => Ok(ImageInstance::PT(PTImage::from_object(obj)?)),
+            _ => Err(anyhow!("Unsupported image modality {}", modality)),
+        }
+    }
+
+    // Function to parse the DICOM file and generate one image per frame
+    pub fn frames_from_bytes(dicom_data: &[u8]) -> Result<Vec<ImageInstance>> {
+        // Parse the DICOM file into a `FileDicomObject`
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::frames_from_object(&obj)
+    }
+
+    // Function to generate one image per frame from an already parsed DICOM file, so that
+    // Enhanced CT/MR objects contribute every frame as a separate slice
+    pub fn frames_from_object(obj: &FileDicomObject<InMemDicomObject>) -> Result<Vec<ImageInstance>> {
+        let modality = get_value::<String>(obj, "Modality")
+            .ok_or(MissingAttribute("Modality"))?;
+        match modality.as_str() {
+            "CT" => Ok(CTImage::frames_from_object(obj)?.into_iter().map(ImageInstance::CT).collect()),
+            "MR" => Ok(MRImage::frames_from_object(obj)?.into_iter().map(ImageInstance::MR).collect()),
+            "PT" => Ok(vec![ImageInstance::PT(PTImage::from_object(obj)?)]),
+            _ => Err(anyhow!("Unsupported image modality {}", modality)),
+        }
+    }
+
+    // Key identifying the image in a `DicomRepo`: the SOPInstanceUID, suffixed with the frame
+    // number for frames of a multi-frame object
+    pub fn key(&self) -> String {
+        match self.frame_number() {
+            Some(frame) => format!("{}#{}", self.uid(), frame),
+            None => self.uid().to_string(),
+        }
+    }
+
+    // Access the image through its modality-independent interface
+    pub fn as_image(&self) -> &dyn DicomImage {
+        match self {
+            ImageInstance::CT(image) => image,
+            ImageInstance::MR(image) => image,
+            ImageInstance::PT(image) => image,
+        }
+    }
+
+    // Whether the image holds its pixel data, as opposed to a header-only image
+    pub fn has_pixel_data(&self) -> bool {
+        match self {
+            ImageInstance::CT(image) => !image.pixel_data.is_empty(),
+            ImageInstance::MR(image) => !image.pixel_data.is_empty(),
+            ImageInstance::PT(image) => !image.pixel_data.is_empty(),
+        }
+    }
+
+    // Native samples held by the image, empty for a header-only image
+    pub fn pixel_data(&self) -> &[u8] {
+        match self {
+            ImageInstance::CT(image) => &image.pixel_data,
+            ImageInstance::MR(image) => &image.pixel_data,
+            ImageInstance::PT(image) => &image.pixel_data,
+        }
+    }
+
+    // Release the pixel data, keeping only the header attributes
+    pub fn clear_pixel_data(&mut self) {
+        match self {
+            ImageInstance::CT(image) => image.pixel_data.clear(),
+            ImageInstance::MR(image) => image.pixel_data.clear(),
+            ImageInstance::PT(image) => image.pixel_data.clear(),
+        }
+    }
+
+    // Move the stored pixel data out of the image, leaving it empty
+    pub fn take_pixel_data(&mut self) -> Vec<u8> {
+        match self {
+            ImageInstance::CT(image) => std::mem::take(&mut image.pixel_data),
+            ImageInstance::MR(image) => std::mem::take(&mut image.pixel_data),
+            ImageInstance::PT(image) => std::mem::take(&mut image.pixel_data),
+        }
+    }
+
+    // Replace the stored pixel data, e.g. with data loaded on demand
+    pub fn set_pixel_data(&mut self, pixel_data: Vec<u8>) {
+        match self {
+            ImageInstance::CT(image) => image.pixel_data = pixel_data,
+            ImageInstance::MR(image) => image.pixel_data = pixel_data,
+            ImageInstance::PT(image) => image.pixel_data = pixel_data,
+        }
+    }
+}
+
+impl DicomImage for ImageInstance {
+    fn uid(&self) -> &str {
+        self.as_image().uid()
+    }
+    fn series_uid(&self) -> &str {
+        self.as_image().series_uid()
+    }
+    fn modality(&self) -> &str {
+        self.as_image().modality()
+    }
+    fn frame_number(&self) -> Option<u32> {
+        self.as_image().frame_number()
+    }
+    fn instance_number(&self) -> Option<i32> {
+        self.as_image().instance_number()
+    }
+    fn acquisition_number(&self) -> Option<i32> {
+        self.as_image().acquisition_number()
+    }
+    fn rows(&self) -> u16 {
+        self.as_image().rows()
+    }
+    fn columns(&self) -> u16 {
+        self.as_image().columns()
+    }
+    fn pixel_spacing(&self) -> Option<(f32, f32)> {
+        self.as_image().pixel_spacing()
+    }
+    fn slice_thickness(&self) -> Option<f32> {
+        self.as_image().slice_thickness()
+    }
+    fn spacing_between_slices(&self) -> Option<f32> {
+        self.as_image().spacing_between_slices()
+    }
+    fn gantry_detector_tilt(&self) -> Option<f32> {
+        self.as_image().gantry_detector_tilt()
+    }
+    fn image_position_patient(&self) -> Option<(f32, f32, f32)> {
+        self.as_image().image_position_patient()
+    }
+    fn image_orientation_patient(&self) -> Option<(f32, f32, f32, f32, f32, f32)> {
+        self.as_image().image_orientation_patient()
+    }
+    fn get_pixel_data(&self) -> Result<Vec<i16>> {
+        self.as_image().get_pixel_data()
+    }
+    fn get_pixel_data_f32(&self) -> Result<Vec<f32>> {
+        self.as_image().get_pixel_data_f32()
+    }
+    fn get_pixel_data_from(&self, pixel_data: &[u8]) -> Result<Vec<i16>> {
+        self.as_image().get_pixel_data_from(pixel_data)
+    }
+    fn get_pixel_data_f32_from(&self, pixel_data: &[u8]) -> Result<Vec<f32>> {
+        self.as_image().get_pixel_data_f32_from(pixel_data)
+    }
+}
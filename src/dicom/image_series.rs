@@ -1,7 +1,8 @@
 use anyhow::{Result, anyhow};
 use dicom_object::{FileDicomObject, InMemDicomObject};
 use crate::define_dicom_struct;
-use super::dicom_helper::get_value;
+use super::dicom_helper::{get_value, MissingAttribute};
+use super::image_instance::SUPPORTED_IMAGE_MODALITIES;
 
 
 // Use the macro to define the ImageSeries struct
@@ -9,6 +10,9 @@ define_dicom_struct!(ImageSeries, {
     (uid, String, "(0020,000E) SeriesInstanceUID", false),  // SeriesID is required
     (study_uid, String, "(0020,000D) StudyInstanceUID", false),     // StudyInstanceUID is required
     (modality, String, "(0008,0060) Modality", false),     // Modality is required
+    (series_number, i32, "(0020,0011) SeriesNumber", true), // SeriesNumber is optional
+    (frame_of_reference_uid, String, "(0020,0052) FrameOfReferenceUID", true), // Shared by spatially registered series (Optional)
+    (body_part_examined, String, "(0018,0015) BodyPartExamined", true), // BodyPartExamined is optional
     (description, String, "(0008,103E) SeriesDescription", true) // SeriesDescription is optional
 });
 
@@ -18,28 +22,38 @@ impl ImageSeries {
         // Parse the DICOM file into a `FileDicomObject`
         let dicom_obj: FileDicomObject<InMemDicomObject> =
             FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&dicom_obj)
+    }
 
+    // Function to generate the ImageSeries structure from an already parsed DICOM file or data set
+    pub fn from_object(dicom_obj: &InMemDicomObject) -> Result<ImageSeries> {
         // Retrieve required fields using `get_value`
-        let series_uid = get_value::<String>(&dicom_obj, "SeriesInstanceUID")
-            .ok_or_else(|| anyhow!("Missing SeriesInstanceUID"))?;
-        let studyset_uid = get_value::<String>(&dicom_obj, "StudyInstanceUID")
-            .ok_or_else(|| anyhow!("Missing StudyInstanceUID"))?;
-        let modality = get_value::<String>(&dicom_obj, "Modality")
-            .ok_or_else(|| anyhow!("Missing Modality"))?;
+        let series_uid = get_value::<String>(dicom_obj, "SeriesInstanceUID")
+            .ok_or(MissingAttribute("SeriesInstanceUID"))?;
+        let studyset_uid = get_value::<String>(dicom_obj, "StudyInstanceUID")
+            .ok_or(MissingAttribute("StudyInstanceUID"))?;
+        let modality = get_value::<String>(dicom_obj, "Modality")
+            .ok_or(MissingAttribute("Modality"))?;
 
-        // Ensure the modality is "CT"
-        if modality != "CT" {
-            return Err(anyhow!("Expected CT image, but got {} image", modality).into());
+        // Ensure the modality is one we can build images for
+        if !SUPPORTED_IMAGE_MODALITIES.contains(&modality.as_str()) {
+            return Err(anyhow!("Unsupported modality {}, expected one of {:?}", modality, SUPPORTED_IMAGE_MODALITIES));
         }
 
         // Optional fields
-        let description = get_value::<String>(&dicom_obj, "SeriesDescription");
+        let series_number = get_value::<i32>(dicom_obj, "SeriesNumber");
+        let frame_of_reference_uid = get_value::<String>(dicom_obj, "FrameOfReferenceUID");
+        let body_part_examined = get_value::<String>(dicom_obj, "BodyPartExamined");
+        let description = get_value::<String>(dicom_obj, "SeriesDescription");
 
         // Return the populated struct
         Ok(ImageSeries {
             uid: series_uid,
             study_uid: studyset_uid,
             modality,
+            series_number,
+            frame_of_reference_uid,
+            body_part_examined,
             description,
         })
     }
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+// Outcome category of a file handed to the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestStatus {
+    Ingested,            // All entities and images were added to the repository
+    DirectoryIndex,      // A DICOMDIR whose records were added to the repository
+    Indexed,             // Unchanged since the on-disk index was written and restored from it
+    Listed,              // Listed by a DICOMDIR and left unread until its series is opened
+    ReadError,           // The file could not be opened or read
+    NotDicom,            // The content is not a DICOM file
+    MissingAttributes,   // A required attribute of an entity is missing
+    UnsupportedModality, // The modality has no model (e.g. SR, RTIMAGE)
+    InvalidImage,        // The image attributes or pixel data, or the RT object content, could not be used
+    Failed,              // The parsing task itself failed
+    Cancelled,           // The file was skipped because the ingest was cancelled
+}
+
+impl IngestStatus {
+    // Whether the file contributed everything it holds to the repository
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            IngestStatus::Ingested | IngestStatus::DirectoryIndex | IngestStatus::Indexed | IngestStatus::Listed
+        )
+    }
+}
+
+// What happened to a single file
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileOutcome {
+    pub path: PathBuf,
+    pub status: IngestStatus,
+    pub reason: Option<String>, // Why the file was (partly) rejected
+    pub images: usize,          // Number of images (frames) added from the file
+}
+
+/// Per-file outcome of parsing a set of DICOM files, with counts per category.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct IngestReport {
+    pub files: Vec<FileOutcome>,
+    pub counts: BTreeMap<IngestStatus, usize>,
+    pub images: usize, // Total number of images (frames) added
+    pub cancelled: bool, // The ingest was cancelled before every file was parsed
+}
+
+impl IngestReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, outcome: FileOutcome) {
+        *self.counts.entry(outcome.status).or_insert(0) += 1;
+        self.images += outcome.images;
+        self.files.push(outcome);
+    }
+
+    // Number of files with the given outcome
+    pub fn count(&self, status: IngestStatus) -> usize {
+        self.counts.get(&status).copied().unwrap_or(0)
+    }
+
+    // Files that were rejected or only partly ingested
+    pub fn failures(&self) -> impl Iterator<Item = &FileOutcome> {
+        self.files.iter().filter(|outcome| !outcome.status.is_success())
+    }
+
+    // Order the outcomes by path, so reports of concurrent parses are reproducible
+    pub fn sort(&mut self) {
+        self.files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
+
+// Snapshot of the progress of an ingest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct IngestProgress {
+    pub discovered: usize, // Files found so far
+    pub parsed: usize,     // Files fully ingested
+    pub failed: usize,     // Files rejected, partly ingested or cancelled
+    pub bytes_read: u64,   // Bytes read from files: whole files, or up to the pixel data for header-only reads
+}
+
+impl IngestProgress {
+    // Files handled so far, successfully or not
+    pub fn completed(&self) -> usize {
+        self.parsed + self.failed
+    }
+}
+
+/// Callback receiving a progress snapshot after every discovered or completed file.
+///
+/// Callbacks run on the parsing tasks, possibly concurrently, and should return quickly; to
+/// drive a UI from another thread, send the snapshots over a channel from the callback.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&IngestProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&IngestProgress) + Send + Sync + 'static) -> Self {
+        ProgressCallback(Arc::new(callback))
+    }
+
+    pub fn call(&self, progress: &IngestProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Flag shared between an ingest and the UI or CLI that may abort it.
+///
+/// Clones share the flag. Files not yet parsed when the token is cancelled are reported as
+/// `Cancelled` and the parsers return what was ingested so far.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl CancellationToken {
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // A token sharing this token's flag; JavaScript hands a handle to the parser so that it
+    // keeps its own token to cancel with
+    pub fn handle(&self) -> CancellationToken {
+        self.clone()
+    }
+}
+
+// Progress counters updated concurrently by the parsing tasks
+#[derive(Debug, Default)]
+pub(crate) struct ProgressTracker {
+    discovered: AtomicUsize,
+    parsed: AtomicUsize,
+    failed: AtomicUsize,
+    bytes_read: AtomicU64,
+    callback: Option<ProgressCallback>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(callback: Option<ProgressCallback>) -> Self {
+        ProgressTracker {
+            callback,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> IngestProgress {
+        IngestProgress {
+            discovered: self.discovered.load(Ordering::SeqCst),
+            parsed: self.parsed.load(Ordering::SeqCst),
+            failed: self.failed.load(Ordering::SeqCst),
+            bytes_read: self.bytes_read.load(Ordering::SeqCst),
+        }
+    }
+
+    fn emit(&self) {
+        if let Some(callback) = &self.callback {
+            callback.call(&self.snapshot());
+        }
+    }
+
+    pub(crate) fn discovered(&self, count: usize) {
+        self.discovered.fetch_add(count, Ordering::SeqCst);
+        self.emit();
+    }
+
+    pub(crate) fn read(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    pub(crate) fn completed(&self, outcome: &FileOutcome) {
+        if outcome.status.is_success() {
+            self.parsed.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.failed.fetch_add(1, Ordering::SeqCst);
+        }
+        self.emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_json() {
+        let mut report = IngestReport::new();
+        let outcome = |path: &str, status, images| FileOutcome {
+            path: PathBuf::from(path),
+            status,
+            reason: None,
+            images,
+        };
+        report.record(outcome("b.dcm", IngestStatus::Ingested, 2));
+        report.record(outcome("a.txt", IngestStatus::NotDicom, 0));
+        report.record(outcome("c.dcm", IngestStatus::Ingested, 1));
+        report.sort();
+
+        assert_eq!(report.count(IngestStatus::Ingested), 2);
+        assert_eq!(report.images, 3);
+        assert_eq!(report.failures().count(), 1);
+
+        let json = report.to_json().unwrap();
+        assert!(json.starts_with(r#"{"files":[{"path":"a.txt","status":"not_dicom""#));
+        assert!(json.contains(r#""counts":{"ingested":2,"not_dicom":1}"#));
+    }
+
+    #[test]
+    fn test_progress_tracker_emits_snapshots() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tracker = ProgressTracker::new(Some(ProgressCallback::new(move |p| {
+            tx.send(*p).unwrap();
+        })));
+        tracker.discovered(2);
+        tracker.read(128);
+        tracker.completed(&FileOutcome {
+            path: PathBuf::from("a.dcm"),
+            status: IngestStatus::Ingested,
+            reason: None,
+            images: 1,
+        });
+
+        let events: Vec<IngestProgress> = rx.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            IngestProgress { discovered: 2, parsed: 1, failed: 0, bytes_read: 128 }
+        );
+    }
+}
@@ -10,6 +10,10 @@ pub mod fileio;
 
 // mod dicom_ai;
 mod dicom_helper;
+mod pixel_data;
+mod multi_frame;
+#[cfg(test)]
+mod test_util;
 
 mod patient;
 pub use patient::*;
@@ -23,5 +27,59 @@ pub use image_series::*;
 mod ct_image;
 pub use ct_image::*;
 
+mod mr_image;
+pub use mr_image::*;
+
+mod pt_image;
+pub use pt_image::*;
+
+mod image_instance;
+pub use image_instance::*;
+
+mod series_geometry;
+pub use series_geometry::*;
+
+mod rt_struct;
+pub use rt_struct::*;
+
+mod roi_mask;
+pub use roi_mask::*;
+
+mod rt_dose;
+pub use rt_dose::*;
+
+mod dvh;
+pub use dvh::*;
+
+mod rt_plan;
+pub use rt_plan::*;
+
+mod pixel_cache;
+pub use pixel_cache::*;
+
+mod dicomdir;
+pub use dicomdir::*;
+
+mod ingest;
+pub use ingest::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod scan;
+#[cfg(not(target_arch = "wasm32"))]
+pub use scan::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod watch;
+#[cfg(not(target_arch = "wasm32"))]
+pub use watch::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod repo_index;
+#[cfg(not(target_arch = "wasm32"))]
+pub use repo_index::*;
+
+mod query;
+pub use query::*;
+
 mod dicom_repo;
 pub use dicom_repo::*;
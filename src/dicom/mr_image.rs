@@ -0,0 +1,128 @@
+use super::dicom_helper::{get_value, get_values, MissingAttribute};
+use super::multi_frame::impl_frames_from_object;
+use super::pixel_data::{is_big_endian, read_pixel_data, stored_values, transfer_syntax_uid};
+use crate::define_dicom_struct;
+use anyhow::Result;
+use dicom_object::{FileDicomObject, InMemDicomObject};
+
+define_dicom_struct!(MRImage, {
+    (uid, String, "(0008,0018) SOPInstanceUID", false),              // Unique identifier for the image
+    (series_uid, String, "(0020,000E) SeriesInstanceUID", false),  // SeriesID is required
+    (frame_number, u32, derived, true),                            // 1-based frame of an Enhanced multi-frame object (Optional)
+    (instance_number, i32, "(0020,0013) InstanceNumber", true),   // Position of the image in its series (Optional)
+    (rows, u16, "(0028,0010) Rows", false),                         // Rows (Mandatory)
+    (columns, u16, "(0028,0011) Columns", false),                    // Columns (Mandatory)
+    (pixel_spacing, (f32, f32), "(0028,0030) PixelSpacing", true),   // PixelSpacing (Optional)
+    (slice_thickness, f32, "(0018,0050) SliceThickness", true),      // SliceThickness (Optional)
+    (spacing_between_slices, f32, "(0018,0088) SpacingBetweenSlices", true), // SpacingBetweenSlices (Optional)
+    (image_position_patient, (f32, f32, f32), "(0020,0032) ImagePositionPatient", true), // ImagePositionPatient (Optional)
+    (image_orientation_patient, (f32, f32, f32, f32, f32, f32), "(0020,0037) ImageOrientationPatient", true), // ImageOrientationPatient (Optional)
+    (repetition_time, f32, "(0018,0080) RepetitionTime", true),     // RepetitionTime in ms (Optional)
+    (echo_time, f32, "(0018,0081) EchoTime", true),                 // EchoTime in ms (Optional)
+    (magnetic_field_strength, f32, "(0018,0087) MagneticFieldStrength", true), // Field strength in T (Optional)
+    (rescale_slope, f32, "(0028,1053) RescaleSlope", true),          // RescaleSlope (Not part of the MR IOD, used when present)
+    (rescale_intercept, f32, "(0028,1052) RescaleIntercept", true),  // RescaleIntercept (Not part of the MR IOD, used when present)
+    (window_center, f32, "(0028,1050) WindowCenter", true),          // WindowCenter (Optional)
+    (window_width, f32, "(0028,1051) WindowWidth", true),            // WindowWidth (Optional)
+    (bits_allocated, u16, "(0028,0100) BitsAllocated", false),       // BitsAllocated (Mandatory)
+    (bits_stored, u16, "(0028,0101) BitsStored", false),             // BitsStored (Mandatory)
+    (high_bit, u16, "(0028,0102) HighBit", false),                   // HighBit (Mandatory)
+    (pixel_representation, u16, "(0028,0103) PixelRepresentation", false), // Pixel Representation (Mandatory)
+    (transfer_syntax_uid, String, "(0002,0010) TransferSyntaxUID", false), // Transfer syntax the file was encoded with
+    (pixel_data, Vec<u8>, "(7FE0,0010) PixelData", false)            // PixelData (Mandatory)
+});
+
+impl_frames_from_object!(MRImage);
+
+impl MRImage {
+    // Function to parse the DICOM file and generate the MRImage structure
+    pub fn from_bytes(dicom_data: &[u8]) -> Result<MRImage> {
+        // Parse the DICOM file into a `FileDicomObject`
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
+
+    // Function to generate the MRImage structure from an already parsed DICOM file
+    pub fn from_object(obj: &FileDicomObject<InMemDicomObject>) -> Result<MRImage> {
+        Ok(MRImage {
+            uid: get_value::<String>(obj, "SOPInstanceUID")
+                .ok_or(MissingAttribute("SOPInstanceUID"))?,
+            series_uid: get_value::<String>(obj, "SeriesInstanceUID")
+                .ok_or(MissingAttribute("SeriesInstanceUID"))?,
+            frame_number: None,
+            instance_number: get_value::<i32>(obj, "InstanceNumber"),
+            rows: get_value::<u16>(obj, "Rows").ok_or(MissingAttribute("Rows"))?,
+            columns: get_value::<u16>(obj, "Columns").ok_or(MissingAttribute("Columns"))?,
+            pixel_spacing: get_values::<f32>(obj, "PixelSpacing")
+                .and_then(|v| match v[..] { [r, c] => Some((r, c)), _ => None }),
+            slice_thickness: get_value::<f32>(obj, "SliceThickness"),
+            spacing_between_slices: get_value::<f32>(obj, "SpacingBetweenSlices"),
+            image_position_patient: get_values::<f32>(obj, "ImagePositionPatient")
+                .and_then(|v| match v[..] { [x, y, z] => Some((x, y, z)), _ => None }),
+            image_orientation_patient: get_values::<f32>(obj, "ImageOrientationPatient")
+                .and_then(|v| match v[..] { [a, b, c, d, e, f] => Some((a, b, c, d, e, f)), _ => None }),
+            repetition_time: get_value::<f32>(obj, "RepetitionTime"),
+            echo_time: get_value::<f32>(obj, "EchoTime"),
+            magnetic_field_strength: get_value::<f32>(obj, "MagneticFieldStrength"),
+            rescale_slope: get_value::<f32>(obj, "RescaleSlope"),
+            rescale_intercept: get_value::<f32>(obj, "RescaleIntercept"),
+            window_center: get_value::<f32>(obj, "WindowCenter"),
+            window_width: get_value::<f32>(obj, "WindowWidth"),
+            bits_allocated: get_value::<u16>(obj, "BitsAllocated")
+                .ok_or(MissingAttribute("BitsAllocated"))?,
+            bits_stored: get_value::<u16>(obj, "BitsStored")
+                .ok_or(MissingAttribute("BitsStored"))?,
+            high_bit: get_value::<u16>(obj, "HighBit").ok_or(MissingAttribute("HighBit"))?,
+            pixel_representation: get_value::<u16>(obj, "PixelRepresentation")
+                .ok_or(MissingAttribute("PixelRepresentation"))?,
+            transfer_syntax_uid: transfer_syntax_uid(obj),
+            pixel_data: read_pixel_data(obj)?, // Decoded to native samples; empty for header-only reads
+        })
+    }
+
+    // Stored pixel values, masked to BitsStored at HighBit and sign-extended for PixelRepresentation 1
+    pub fn get_stored_values(&self) -> Result<Vec<i32>> {
+        self.get_stored_values_from(&self.pixel_data)
+    }
+
+    // Stored pixel values of native samples kept apart from the image, e.g. in the pixel cache
+    pub fn get_stored_values_from(&self, pixel_data: &[u8]) -> Result<Vec<i32>> {
+        stored_values(
+            pixel_data,
+            self.bits_allocated,
+            self.bits_stored,
+            self.high_bit,
+            self.pixel_representation,
+            is_big_endian(&self.transfer_syntax_uid),
+        )
+    }
+
+    // MR signal intensities. MR images usually carry no rescale tags, in which case the stored
+    // values are returned unchanged.
+    pub fn get_pixel_data_f32(&self) -> Result<Vec<f32>> {
+        self.get_pixel_data_f32_from(&self.pixel_data)
+    }
+
+    pub fn get_pixel_data_f32_from(&self, pixel_data: &[u8]) -> Result<Vec<f32>> {
+        let rescale_slope = self.rescale_slope.unwrap_or(1.0);
+        let rescale_intercept = self.rescale_intercept.unwrap_or(0.0);
+        Ok(self
+            .get_stored_values_from(pixel_data)?
+            .into_iter()
+            .map(|v| v as f32 * rescale_slope + rescale_intercept)
+            .collect())
+    }
+
+    // MR signal intensities as `i16`, clamped to the `i16` range
+    pub fn get_pixel_data(&self) -> Result<Vec<i16>> {
+        self.get_pixel_data_from(&self.pixel_data)
+    }
+
+    pub fn get_pixel_data_from(&self, pixel_data: &[u8]) -> Result<Vec<i16>> {
+        Ok(self
+            .get_pixel_data_f32_from(pixel_data)?
+            .into_iter()
+            .map(|v| v.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+            .collect())
+    }
+}
@@ -0,0 +1,246 @@
+use super::dicom_helper::{get_first_item, get_value, get_values};
+use anyhow::{anyhow, Result};
+use dicom_object::InMemDicomObject;
+
+// Frame-level attributes of an Enhanced (multi-frame) image, resolved from the
+// Per-frame Functional Groups with the Shared Functional Groups as fallback
+#[derive(Debug, Clone, Default)]
+pub struct FrameAttributes {
+    pub pixel_spacing: Option<(f32, f32)>,
+    pub slice_thickness: Option<f32>,
+    pub spacing_between_slices: Option<f32>,
+    pub image_position_patient: Option<(f32, f32, f32)>,
+    pub image_orientation_patient: Option<(f32, f32, f32, f32, f32, f32)>,
+    pub rescale_slope: Option<f32>,
+    pub rescale_intercept: Option<f32>,
+    pub window_center: Option<f32>,
+    pub window_width: Option<f32>,
+}
+
+// Number of frames in the object, 1 when NumberOfFrames is absent
+pub fn number_of_frames(obj: &InMemDicomObject) -> u32 {
+    get_value::<u32>(obj, "NumberOfFrames").unwrap_or(1).max(1)
+}
+
+// Whether the object holds several frames or describes its frames through functional groups
+pub fn is_multi_frame(obj: &InMemDicomObject) -> bool {
+    number_of_frames(obj) > 1 || obj.element_by_name("PerFrameFunctionalGroupsSequence").is_ok()
+}
+
+// Look up a functional group macro (e.g. PlanePositionSequence) for one frame,
+// preferring the per-frame item over the shared one
+fn functional_group<'a>(
+    per_frame: Option<&'a InMemDicomObject>,
+    shared: Option<&'a InMemDicomObject>,
+    group: &str,
+) -> Option<&'a InMemDicomObject> {
+    per_frame
+        .and_then(|item| get_first_item(item, group))
+        .or_else(|| shared.and_then(|item| get_first_item(item, group)))
+}
+
+/// Resolves the attributes of every frame of a multi-frame object.
+///
+/// Pixel Measures, Plane Position, Plane Orientation, Pixel Value Transformation and
+/// Frame VOI LUT functional groups are read from the Per-frame Functional Groups Sequence,
+/// falling back to the Shared Functional Groups Sequence when a frame does not carry them.
+///
+/// # Errors
+/// - If the Per-frame Functional Groups Sequence is present but its item count does not
+///   match NumberOfFrames.
+pub fn frame_attributes(obj: &InMemDicomObject) -> Result<Vec<FrameAttributes>> {
+    let frames = number_of_frames(obj) as usize;
+    let shared = get_first_item(obj, "SharedFunctionalGroupsSequence");
+    let per_frame_items: Option<&[InMemDicomObject]> = obj
+        .element_by_name("PerFrameFunctionalGroupsSequence")
+        .ok()
+        .and_then(|e| e.items());
+
+    if let Some(items) = per_frame_items {
+        if items.len() != frames {
+            return Err(anyhow!(
+                "PerFrameFunctionalGroupsSequence has {} items, but NumberOfFrames is {}",
+                items.len(),
+                frames
+            ));
+        }
+    }
+
+    let attributes = (0..frames)
+        .map(|i| {
+            let per_frame = per_frame_items.map(|items| &items[i]);
+            let measures = functional_group(per_frame, shared, "PixelMeasuresSequence");
+            let position = functional_group(per_frame, shared, "PlanePositionSequence");
+            let orientation = functional_group(per_frame, shared, "PlaneOrientationSequence");
+            let transformation = functional_group(per_frame, shared, "PixelValueTransformationSequence");
+            let voi_lut = functional_group(per_frame, shared, "FrameVOILUTSequence");
+
+            FrameAttributes {
+                pixel_spacing: measures
+                    .and_then(|item| get_values::<f32>(item, "PixelSpacing"))
+                    .and_then(|v| match v[..] { [r, c] => Some((r, c)), _ => None }),
+                slice_thickness: measures.and_then(|item| get_value::<f32>(item, "SliceThickness")),
+                spacing_between_slices: measures
+                    .and_then(|item| get_value::<f32>(item, "SpacingBetweenSlices")),
+                image_position_patient: position
+                    .and_then(|item| get_values::<f32>(item, "ImagePositionPatient"))
+                    .and_then(|v| match v[..] { [x, y, z] => Some((x, y, z)), _ => None }),
+                image_orientation_patient: orientation
+                    .and_then(|item| get_values::<f32>(item, "ImageOrientationPatient"))
+                    .and_then(|v| match v[..] { [a, b, c, d, e, f] => Some((a, b, c, d, e, f)), _ => None }),
+                rescale_slope: transformation.and_then(|item| get_value::<f32>(item, "RescaleSlope")),
+                rescale_intercept: transformation
+                    .and_then(|item| get_value::<f32>(item, "RescaleIntercept")),
+                window_center: voi_lut.and_then(|item| get_value::<f32>(item, "WindowCenter")),
+                window_width: voi_lut.and_then(|item| get_value::<f32>(item, "WindowWidth")),
+            }
+        })
+        .collect();
+
+    Ok(attributes)
+}
+
+/// Splits a multi-frame image into one image per frame.
+///
+/// `base` is the image parsed from the top-level attributes, with its pixel data taken out as
+/// `pixel_data` (all frames back to back); `apply` receives a copy of it for each frame together
+/// with the frame's 1-based number, its resolved attributes and its own slice of the pixel data.
+///
+/// # Errors
+/// - If the functional groups are inconsistent with NumberOfFrames.
+/// - If the pixel data is shorter than NumberOfFrames frames of `frame_len` bytes.
+pub fn split_frames<T, F>(
+    obj: &InMemDicomObject,
+    base: T,
+    pixel_data: Vec<u8>,
+    frame_len: usize,
+    apply: F,
+) -> Result<Vec<T>>
+where
+    T: Clone,
+    F: Fn(&mut T, u32, &FrameAttributes, Vec<u8>),
+{
+    let attributes = frame_attributes(obj)?;
+
+    // Objects read without pixel data (header-only) yield frames without pixel data
+    if pixel_data.is_empty() {
+        return Ok(attributes
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let mut image = base.clone();
+                apply(&mut image, i as u32 + 1, frame, Vec::new());
+                image
+            })
+            .collect());
+    }
+
+    if frame_len == 0 || pixel_data.len() < frame_len * attributes.len() {
+        return Err(anyhow!(
+            "Pixel data holds {} bytes, expected {} frames of {} bytes",
+            pixel_data.len(),
+            attributes.len(),
+            frame_len
+        ));
+    }
+
+    let mut frames = Vec::with_capacity(attributes.len());
+    for (i, (frame, data)) in attributes.iter().zip(pixel_data.chunks_exact(frame_len)).enumerate() {
+        let mut image = base.clone();
+        apply(&mut image, i as u32 + 1, frame, data.to_vec());
+        frames.push(image);
+    }
+    Ok(frames)
+}
+
+// Implements `frames_from_object` for an image type whose frame-level fields match
+// `FrameAttributes` field for field (CTImage and MRImage), overriding each of them with the
+// value of the frame when it has one
+macro_rules! impl_frames_from_object {
+    ($name:ident) => {
+        impl $name {
+            // Function to generate one image per frame, splitting Enhanced multi-frame objects along
+            // their functional groups; single-frame objects yield a single image
+            pub fn frames_from_object(
+                obj: &dicom_object::FileDicomObject<dicom_object::InMemDicomObject>,
+            ) -> anyhow::Result<Vec<$name>> {
+                let mut image = Self::from_object(obj)?;
+                if !$crate::dicom::multi_frame::is_multi_frame(obj) {
+                    return Ok(vec![image]);
+                }
+
+                let pixel_data = std::mem::take(&mut image.pixel_data);
+                let frame_len = image.rows as usize * image.columns as usize * (image.bits_allocated as usize / 8);
+                $crate::dicom::multi_frame::split_frames(obj, image, pixel_data, frame_len, |img, number, frame, data| {
+                    img.frame_number = Some(number);
+                    img.pixel_spacing = frame.pixel_spacing.or(img.pixel_spacing);
+                    img.slice_thickness = frame.slice_thickness.or(img.slice_thickness);
+                    img.spacing_between_slices = frame.spacing_between_slices.or(img.spacing_between_slices);
+                    img.image_position_patient = frame.image_position_patient.or(img.image_position_patient);
+                    img.image_orientation_patient = frame.image_orientation_patient.or(img.image_orientation_patient);
+                    img.rescale_slope = frame.rescale_slope.or(img.rescale_slope);
+                    img.rescale_intercept = frame.rescale_intercept.or(img.rescale_intercept);
+                    img.window_center = frame.window_center.or(img.window_center);
+                    img.window_width = frame.window_width.or(img.window_width);
+                    img.pixel_data = data;
+                })
+            }
+        }
+    };
+}
+pub(crate) use impl_frames_from_object;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::value::DataSetSequence;
+    use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+
+    fn sequence(tag: Tag, items: Vec<InMemDicomObject>) -> DataElement<InMemDicomObject> {
+        DataElement::new(tag, VR::SQ, DataSetSequence::from(items))
+    }
+
+    fn plane_position(ipp: &str) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([sequence(
+            Tag(0x0020, 0x9113), // PlanePositionSequence
+            vec![InMemDicomObject::from_element_iter([DataElement::new(
+                Tag(0x0020, 0x0032), // ImagePositionPatient
+                VR::DS,
+                PrimitiveValue::from(ipp),
+            )])],
+        )])
+    }
+
+    #[test]
+    fn test_per_frame_overrides_shared_groups() {
+        let shared = InMemDicomObject::from_element_iter([sequence(
+            Tag(0x0028, 0x9110), // PixelMeasuresSequence
+            vec![InMemDicomObject::from_element_iter([
+                DataElement::new(Tag(0x0028, 0x0030), VR::DS, PrimitiveValue::from("0.5\\0.5")),
+                DataElement::new(Tag(0x0018, 0x0050), VR::DS, PrimitiveValue::from("2")),
+            ])],
+        )]);
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(Tag(0x0028, 0x0008), VR::IS, PrimitiveValue::from("2")),
+            sequence(Tag(0x5200, 0x9229), vec![shared]),
+            sequence(
+                Tag(0x5200, 0x9230),
+                vec![plane_position("0\\0\\-10"), plane_position("0\\0\\-8")],
+            ),
+        ]);
+
+        assert!(is_multi_frame(&obj));
+        let frames = frame_attributes(&obj).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].image_position_patient, Some((0.0, 0.0, -10.0)));
+        assert_eq!(frames[1].image_position_patient, Some((0.0, 0.0, -8.0)));
+        assert_eq!(frames[1].pixel_spacing, Some((0.5, 0.5)));
+        assert_eq!(frames[1].slice_thickness, Some(2.0));
+
+        let split = split_frames(&obj, 0u32, vec![1, 2, 3, 4], 2, |img, number, _, data| {
+            *img = number * 100 + data[0] as u32;
+        })
+        .unwrap();
+        assert_eq!(split, vec![101, 203]);
+    }
+}
@@ -1,7 +1,7 @@
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 use dicom_object::{FileDicomObject, InMemDicomObject};
 use crate::define_dicom_struct;
-use super::dicom_helper::get_value;
+use super::dicom_helper::{get_value, MissingAttribute};
 
 
 // Use the macro to define the Patient struct
@@ -19,16 +19,20 @@ impl Patient {
         // Parse the DICOM file into a `FileDicomObject`
         let dicom_obj: FileDicomObject<InMemDicomObject> =
             FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&dicom_obj)
+    }
 
+    // Function to generate the Patient structure from an already parsed DICOM file or data set
+    pub fn from_object(dicom_obj: &InMemDicomObject) -> Result<Patient> {
         // Retrieve required fields using `get_value`
-        let id = get_value::<String>(&dicom_obj, "PatientID")
-            .ok_or_else(|| anyhow!("Missing PatientID"))?;
-        let name = get_value::<String>(&dicom_obj, "PatientName")
-            .ok_or_else(|| anyhow!("Missing PatientName"))?;
+        let id = get_value::<String>(dicom_obj, "PatientID")
+            .ok_or(MissingAttribute("PatientID"))?;
+        let name = get_value::<String>(dicom_obj, "PatientName")
+            .ok_or(MissingAttribute("PatientName"))?;
 
         // Optional fields
-        let birthdate = get_value::<String>(&dicom_obj, "PatientBirthDate");
-        let sex = get_value::<String>(&dicom_obj, "PatientSex");
+        let birthdate = get_value::<String>(dicom_obj, "PatientBirthDate");
+        let sex = get_value::<String>(dicom_obj, "PatientSex");
 
         // Return the populated struct
         Ok(Patient {
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use dicom_core::Tag;
+use dicom_object::{FileDicomObject, InMemDicomObject, OpenFileOptions};
+use lru::LruCache;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::image_instance::ImageInstance;
+
+// Pixel Data (7FE0,0010); header-only reads stop before this element
+pub const PIXEL_DATA_TAG: Tag = Tag(0x7FE0, 0x0010);
+
+// Default memory budget of the pixel cache: 512 MiB
+pub const DEFAULT_PIXEL_CACHE_BUDGET: usize = 512 * 1024 * 1024;
+
+// Read the attributes of a DICOM file, stopping before its pixel data
+pub fn read_header(path: &Path) -> Result<FileDicomObject<InMemDicomObject>> {
+    read_header_counted(path).map(|(obj, _)| obj)
+}
+
+// Same as `read_header`, also returning the number of bytes read from the file, e.g. to report
+// the progress of a header-only ingest
+pub fn read_header_counted(path: &Path) -> Result<(FileDicomObject<InMemDicomObject>, u64)> {
+    let file = File::open(path).map_err(|err| anyhow!("Failed to open {}: {}", path.display(), err))?;
+    let mut reader = CountingReader { inner: file, count: 0 };
+    let obj = OpenFileOptions::new()
+        .read_until(PIXEL_DATA_TAG)
+        .from_reader(&mut reader)
+        .map_err(|err| anyhow!("Failed to read header of {}: {}", path.display(), err))?;
+    Ok((obj, reader.count))
+}
+
+// Reader counting the bytes read through it
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Loads the pixel data of every image (frame) stored in a DICOM file.
+///
+/// Returns the stored pixel bytes keyed by image key, in the same form as the
+/// `pixel_data` of an eagerly parsed image.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or its pixel data cannot be decoded.
+pub fn load_pixel_data(path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let obj = OpenFileOptions::new()
+        .open_file(path)
+        .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+    let images = ImageInstance::frames_from_object(&obj)?;
+    Ok(images
+        .into_iter()
+        .map(|mut image| {
+            let key = image.key();
+            (key, image.take_pixel_data())
+        })
+        .collect())
+}
+
+/// Least-recently-used cache of image pixel data bounded by a memory budget in bytes.
+///
+/// Entries larger than the whole budget are not cached.
+pub struct PixelCache {
+    entries: LruCache<String, Arc<Vec<u8>>>,
+    budget: usize,    // Maximum number of cached pixel bytes
+    used: usize,      // Number of cached pixel bytes
+}
+
+impl fmt::Debug for PixelCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PixelCache")
+            .field("entries", &self.entries.len())
+            .field("budget", &self.budget)
+            .field("used", &self.used)
+            .finish()
+    }
+}
+
+impl PixelCache {
+    pub fn new(budget: usize) -> Self {
+        PixelCache {
+            entries: LruCache::unbounded(),
+            budget,
+            used: 0,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Change the budget, evicting entries until the cache fits
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    // Look up an entry, marking it as most recently used
+    pub fn get(&mut self, key: &str) -> Option<Arc<Vec<u8>>> {
+        self.entries.get(key).cloned()
+    }
+
+    // Insert an entry, evicting the least recently used ones to stay within the budget
+    pub fn insert(&mut self, key: String, data: Arc<Vec<u8>>) {
+        // Data too large to ever fit still replaces the stale entry of the same key
+        if data.len() > self.budget {
+            self.remove(&key);
+            return;
+        }
+        self.used += data.len();
+        if let Some(old) = self.entries.put(key, data) {
+            self.used -= old.len();
+        }
+        self.evict();
+    }
+
+    // Drop an entry, e.g. when its image is replaced or removed
+    pub fn remove(&mut self, key: &str) {
+        if let Some(data) = self.entries.pop(key) {
+            self.used -= data.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            match self.entries.pop_lru() {
+                Some((_, data)) => self.used -= data.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use dicom_core::{DataElement, PrimitiveValue, VR};
+    use dicom_object::meta::FileMetaTableBuilder;
+    use crate::dicom::DicomImage;
+
+    // Write a 1x2 single-frame CT file holding the stored values 7 and 9
+    pub(crate) fn write_ct_file(path: &Path) {
+        ct_object("1.2.3.4").write_to_file(path).unwrap();
+    }
+
+    // Build the 1x2 CT object of `write_ct_file` with the given SOP instance UID
+    pub(crate) fn ct_object(sop_uid: &str) -> FileDicomObject<InMemDicomObject> {
+        let mut obj = InMemDicomObject::new_empty();
+        let text = |tag, vr, value: &str| DataElement::new(tag, vr, PrimitiveValue::from(value));
+        let short = |tag, value: u16| DataElement::new(tag, VR::US, PrimitiveValue::from(value));
+        obj.put(text(Tag(0x0008, 0x0018), VR::UI, sop_uid));
+        obj.put(text(Tag(0x0008, 0x0060), VR::CS, "CT"));
+        obj.put(text(Tag(0x0020, 0x000E), VR::UI, "1.2.3"));
+        obj.put(short(Tag(0x0028, 0x0010), 1));
+        obj.put(short(Tag(0x0028, 0x0011), 2));
+        obj.put(short(Tag(0x0028, 0x0100), 16));
+        obj.put(short(Tag(0x0028, 0x0101), 16));
+        obj.put(short(Tag(0x0028, 0x0102), 15));
+        obj.put(short(Tag(0x0028, 0x0103), 0));
+        obj.put(DataElement::new(PIXEL_DATA_TAG, VR::OW, PrimitiveValue::U16(vec![7, 9].into())));
+        obj.with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+                .media_storage_sop_instance_uid(sop_uid),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_header_only_read_and_lazy_load() {
+        let path = std::env::temp_dir().join(format!("kepler-pixel-cache-{}.dcm", std::process::id()));
+        write_ct_file(&path);
+
+        // The header yields an image without pixel data
+        let header = read_header(&path).unwrap();
+        assert!(header.element(PIXEL_DATA_TAG).is_err());
+        let image = ImageInstance::from_object(&header).unwrap();
+        assert!(image.get_pixel_data().unwrap().is_empty());
+
+        let frames = load_pixel_data(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(frames, vec![(image.key(), vec![7, 0, 9, 0])]);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_within_budget() {
+        let mut cache = PixelCache::new(10);
+        cache.insert("a".to_string(), Arc::new(vec![0; 4]));
+        cache.insert("b".to_string(), Arc::new(vec![0; 4]));
+        // Touch "a" so that "b" becomes the eviction candidate
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), Arc::new(vec![0; 4]));
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some() && cache.get("c").is_some());
+        assert_eq!(cache.used(), 8);
+
+        // Too large to ever fit, and drops the entry it would replace
+        cache.insert("d".to_string(), Arc::new(vec![0; 11]));
+        assert!(cache.get("d").is_none());
+        cache.insert("a".to_string(), Arc::new(vec![0; 11]));
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.used(), 4);
+
+        cache.set_budget(4);
+        assert_eq!((cache.len(), cache.used()), (1, 4));
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use dicom_core::{DicomValue, PrimitiveValue};
+use dicom_encoding::{Codec, Endianness, TransferSyntaxIndex};
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+
+// Retrieve the transfer syntax UID from the file meta group, without the trailing padding
+pub fn transfer_syntax_uid(obj: &FileDicomObject<InMemDicomObject>) -> String {
+    obj.meta()
+        .transfer_syntax()
+        .trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+// Whether samples encoded with the given transfer syntax are stored big-endian
+pub fn is_big_endian(ts_uid: &str) -> bool {
+    TransferSyntaxRegistry
+        .get(ts_uid)
+        .map(|ts| ts.endianness() == Endianness::Big)
+        .unwrap_or(false)
+}
+
+/// Extracts the PixelData element of a DICOM object as native (unencapsulated) samples.
+///
+/// Native pixel data is returned in the byte order of the object's transfer syntax, even when
+/// the parser has already decoded the words into host order. Encapsulated pixel data (JPEG Baseline/Extended,
+/// JPEG Lossless, RLE Lossless, ...) is decoded frame by frame through the pixel data reader
+/// registered for the object's transfer syntax, yielding little-endian samples.
+///
+/// # Errors
+/// - If the object has no PixelData element.
+/// - If the transfer syntax is unknown, or no decoder is available for it
+///   (e.g. JPEG-LS or JPEG 2000 in this build).
+/// - If the encapsulated fragments cannot be decoded.
+pub fn decode_pixel_data(obj: &FileDicomObject<InMemDicomObject>) -> Result<Vec<u8>> {
+    let element = obj.element_by_name("PixelData")?;
+
+    let ts_uid = transfer_syntax_uid(obj);
+
+    // Native pixel data needs no decoding, only a consistent byte order
+    match element.value() {
+        DicomValue::Primitive(PrimitiveValue::U16(words)) => {
+            let big_endian = is_big_endian(&ts_uid);
+            return Ok(words
+                .iter()
+                .flat_map(|w| if big_endian { w.to_be_bytes() } else { w.to_le_bytes() })
+                .collect());
+        }
+        DicomValue::Primitive(PrimitiveValue::I16(words)) => {
+            let big_endian = is_big_endian(&ts_uid);
+            return Ok(words
+                .iter()
+                .flat_map(|w| if big_endian { w.to_be_bytes() } else { w.to_le_bytes() })
+                .collect());
+        }
+        DicomValue::PixelSequence(_) => {}
+        _ => return Ok(element.to_bytes()?.to_vec()),
+    }
+
+    let ts = TransferSyntaxRegistry
+        .get(&ts_uid)
+        .ok_or_else(|| anyhow!("Unknown transfer syntax {}", ts_uid))?;
+
+    match ts.codec() {
+        Codec::EncapsulatedPixelData(Some(reader), _) => {
+            let mut data = Vec::new();
+            reader.decode(obj, &mut data).map_err(|err| {
+                anyhow!(
+                    "Failed to decode {} ({}) pixel data: {}",
+                    ts.name(),
+                    ts_uid,
+                    err
+                )
+            })?;
+            Ok(data)
+        }
+        _ => Err(anyhow!(
+            "Pixel data in transfer syntax {} ({}) is not supported",
+            ts.name(),
+            ts_uid
+        )),
+    }
+}
+
+// Like `decode_pixel_data`, but yields an empty buffer for objects read without their pixel
+// data (header-only indexing) instead of an error
+pub fn read_pixel_data(obj: &FileDicomObject<InMemDicomObject>) -> Result<Vec<u8>> {
+    if obj.element_by_name("PixelData").is_err() {
+        return Ok(Vec::new());
+    }
+    decode_pixel_data(obj)
+}
+
+/// Extracts stored pixel values from native samples.
+///
+/// Each sample is read as a `bits_allocated`-wide word in the given byte order, shifted down so
+/// that `high_bit` becomes the top of a `bits_stored`-wide value, masked, and sign-extended when
+/// `pixel_representation` is 1 (two's complement).
+pub fn stored_values(
+    pixel_data: &[u8],
+    bits_allocated: u16,
+    bits_stored: u16,
+    high_bit: u16,
+    pixel_representation: u16,
+    big_endian: bool,
+) -> Result<Vec<i32>> {
+    if !matches!(bits_allocated, 8 | 16 | 32) {
+        anyhow::bail!("Unsupported BitsAllocated: {}", bits_allocated);
+    }
+    if bits_stored == 0 || bits_stored > bits_allocated || high_bit >= bits_allocated || high_bit + 1 < bits_stored {
+        anyhow::bail!(
+            "Inconsistent pixel layout: BitsAllocated {}, BitsStored {}, HighBit {}",
+            bits_allocated, bits_stored, high_bit
+        );
+    }
+    if pixel_representation > 1 {
+        anyhow::bail!("Unsupported pixel representation: {}", pixel_representation);
+    }
+
+    let signed = pixel_representation == 1;
+    let shift = (high_bit + 1 - bits_stored) as u32;
+    let mask: u32 = if bits_stored == 32 { u32::MAX } else { (1u32 << bits_stored) - 1 };
+    let sign_bit: u32 = 1u32 << (bits_stored - 1);
+
+    let bytes_per_sample = (bits_allocated / 8) as usize;
+    if !pixel_data.len().is_multiple_of(bytes_per_sample) {
+        anyhow::bail!(
+            "Invalid pixel data length {} for BitsAllocated {}",
+            pixel_data.len(),
+            bits_allocated
+        );
+    }
+
+    let values = pixel_data
+        .chunks_exact(bytes_per_sample)
+        .map(|chunk| {
+            let word: u32 = match (bytes_per_sample, big_endian) {
+                (1, _) => chunk[0] as u32,
+                (2, false) => u16::from_le_bytes([chunk[0], chunk[1]]) as u32,
+                (2, true) => u16::from_be_bytes([chunk[0], chunk[1]]) as u32,
+                (_, false) => u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+                (_, true) => u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+            };
+            let value = (word >> shift) & mask;
+            if signed && value & sign_bit != 0 {
+                // Sign-extend the stored value to 32 bits
+                (value | !mask) as i32
+            } else {
+                value as i32
+            }
+        })
+        .collect();
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::value::PixelFragmentSequence;
+    use dicom_core::{DataElement, Tag, VR};
+    use dicom_object::meta::FileMetaTableBuilder;
+
+    // A 1x2 16-bit image whose pixel data is the given fragments, in the given transfer syntax
+    fn encapsulated(ts_uid: &str, fragments: Vec<Vec<u8>>) -> FileDicomObject<InMemDicomObject> {
+        let short = |tag, value: u16| DataElement::new(tag, VR::US, PrimitiveValue::from(value));
+        InMemDicomObject::from_element_iter([
+            short(Tag(0x0028, 0x0002), 1),
+            short(Tag(0x0028, 0x0010), 1),
+            short(Tag(0x0028, 0x0011), 2),
+            short(Tag(0x0028, 0x0100), 16),
+            short(Tag(0x0028, 0x0101), 16),
+            short(Tag(0x0028, 0x0102), 15),
+            short(Tag(0x0028, 0x0103), 0),
+            DataElement::new(Tag(0x7FE0, 0x0010), VR::OB, PixelFragmentSequence::new(vec![], fragments)),
+        ])
+        .with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax(ts_uid)
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.2")
+                .media_storage_sop_instance_uid("1.2.3.4"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decodes_rle_lossless() {
+        // Two segments, most significant bytes first, each a PackBits literal run of 2 bytes
+        let mut fragment = vec![0u8; 64];
+        fragment[0] = 2; // Number of segments
+        fragment[4] = 64; // Offset of the first segment
+        fragment[8] = 68; // Offset of the second segment
+        fragment.extend([0x01, 0x00, 0x01, 0x00]); // MSB of 7 and 0x0102, padded to even length
+        fragment.extend([0x01, 0x07, 0x02, 0x00]); // LSB of 7 and 0x0102
+        let obj = encapsulated("1.2.840.10008.1.2.5", vec![fragment]);
+
+        let data = decode_pixel_data(&obj).unwrap();
+        assert_eq!(data, vec![0x07, 0x00, 0x02, 0x01]);
+        assert_eq!(stored_values(&data, 16, 16, 15, 0, false).unwrap(), vec![7, 0x0102]);
+    }
+
+    #[test]
+    fn test_unsupported_syntax_is_a_clear_error() {
+        let obj = encapsulated("1.2.840.10008.1.2.4.90", vec![vec![0xFF, 0x4F, 0xFF, 0x51]]);
+        let err = decode_pixel_data(&obj).unwrap_err().to_string();
+        assert!(err.contains("JPEG 2000") && err.contains("not supported"), "{}", err);
+    }
+}
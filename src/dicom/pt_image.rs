@@ -0,0 +1,290 @@
+use super::dicom_helper::{get_first_item, get_value, get_values, parse_time, MissingAttribute};
+use super::pixel_data::{is_big_endian, read_pixel_data, stored_values, transfer_syntax_uid};
+use crate::define_dicom_struct;
+use anyhow::{anyhow, Result};
+use dicom_object::{FileDicomObject, InMemDicomObject};
+
+define_dicom_struct!(PTImage, {
+    (uid, String, "(0008,0018) SOPInstanceUID", false),              // Unique identifier for the image
+    (series_uid, String, "(0020,000E) SeriesInstanceUID", false),  // SeriesID is required
+    (instance_number, i32, "(0020,0013) InstanceNumber", true),   // Position of the image in its series (Optional)
+    (rows, u16, "(0028,0010) Rows", false),                         // Rows (Mandatory)
+    (columns, u16, "(0028,0011) Columns", false),                    // Columns (Mandatory)
+    (pixel_spacing, (f32, f32), "(0028,0030) PixelSpacing", true),   // PixelSpacing (Optional)
+    (slice_thickness, f32, "(0018,0050) SliceThickness", true),      // SliceThickness (Optional)
+    (image_position_patient, (f32, f32, f32), "(0020,0032) ImagePositionPatient", true), // ImagePositionPatient (Optional)
+    (image_orientation_patient, (f32, f32, f32, f32, f32, f32), "(0020,0037) ImageOrientationPatient", true), // ImageOrientationPatient (Optional)
+    (rescale_slope, f32, "(0028,1053) RescaleSlope", true),          // RescaleSlope (Mandatory in the PET IOD, tolerated if missing)
+    (rescale_intercept, f32, "(0028,1052) RescaleIntercept", true),  // RescaleIntercept (Mandatory in the PET IOD, tolerated if missing)
+    (units, String, "(0054,1001) Units", true),                      // Units of the rescaled values, e.g. BQML (Optional)
+    (decay_correction, String, "(0054,1102) DecayCorrection", true), // NONE, START or ADMIN (Optional)
+    (patient_weight, f32, "(0010,1030) PatientWeight", true),        // Patient weight in kg (Optional)
+    (series_time, String, "(0008,0031) SeriesTime", true),           // SeriesTime (Optional)
+    (acquisition_time, String, "(0008,0032) AcquisitionTime", true), // AcquisitionTime (Optional)
+    (radiopharmaceutical_start_time, String, "(0054,0016) > (0018,1072) RadiopharmaceuticalStartTime", true), // Injection time (Optional)
+    (radionuclide_total_dose, f32, "(0054,0016) > (0018,1074) RadionuclideTotalDose", true), // Injected activity in Bq (Optional)
+    (radionuclide_half_life, f32, "(0054,0016) > (0018,1075) RadionuclideHalfLife", true),   // Half life in s (Optional)
+    (bits_allocated, u16, "(0028,0100) BitsAllocated", false),       // BitsAllocated (Mandatory)
+    (bits_stored, u16, "(0028,0101) BitsStored", false),             // BitsStored (Mandatory)
+    (high_bit, u16, "(0028,0102) HighBit", false),                   // HighBit (Mandatory)
+    (pixel_representation, u16, "(0028,0103) PixelRepresentation", false), // Pixel Representation (Mandatory)
+    (transfer_syntax_uid, String, "(0002,0010) TransferSyntaxUID", false), // Transfer syntax the file was encoded with
+    (pixel_data, Vec<u8>, "(7FE0,0010) PixelData", false)            // PixelData (Mandatory)
+});
+
+impl PTImage {
+    // Function to parse the DICOM file and generate the PTImage structure
+    pub fn from_bytes(dicom_data: &[u8]) -> Result<PTImage> {
+        // Parse the DICOM file into a `FileDicomObject`
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
+
+    // Function to generate the PTImage structure from an already parsed DICOM file
+    pub fn from_object(obj: &FileDicomObject<InMemDicomObject>) -> Result<PTImage> {
+        // Injection details live in the first item of the RadiopharmaceuticalInformationSequence
+        let radiopharmaceutical = get_first_item(obj, "RadiopharmaceuticalInformationSequence");
+
+        Ok(PTImage {
+            uid: get_value::<String>(obj, "SOPInstanceUID")
+                .ok_or(MissingAttribute("SOPInstanceUID"))?,
+            series_uid: get_value::<String>(obj, "SeriesInstanceUID")
+                .ok_or(MissingAttribute("SeriesInstanceUID"))?,
+            instance_number: get_value::<i32>(obj, "InstanceNumber"),
+            rows: get_value::<u16>(obj, "Rows").ok_or(MissingAttribute("Rows"))?,
+            columns: get_value::<u16>(obj, "Columns").ok_or(MissingAttribute("Columns"))?,
+            pixel_spacing: get_values::<f32>(obj, "PixelSpacing")
+                .and_then(|v| match v[..] { [r, c] => Some((r, c)), _ => None }),
+            slice_thickness: get_value::<f32>(obj, "SliceThickness"),
+            image_position_patient: get_values::<f32>(obj, "ImagePositionPatient")
+                .and_then(|v| match v[..] { [x, y, z] => Some((x, y, z)), _ => None }),
+            image_orientation_patient: get_values::<f32>(obj, "ImageOrientationPatient")
+                .and_then(|v| match v[..] { [a, b, c, d, e, f] => Some((a, b, c, d, e, f)), _ => None }),
+            rescale_slope: get_value::<f32>(obj, "RescaleSlope"),
+            rescale_intercept: get_value::<f32>(obj, "RescaleIntercept"),
+            units: get_value::<String>(obj, "Units"),
+            decay_correction: get_value::<String>(obj, "DecayCorrection"),
+            patient_weight: get_value::<f32>(obj, "PatientWeight"),
+            series_time: get_value::<String>(obj, "SeriesTime"),
+            acquisition_time: get_value::<String>(obj, "AcquisitionTime"),
+            radiopharmaceutical_start_time: radiopharmaceutical
+                .and_then(|item| get_value::<String>(item, "RadiopharmaceuticalStartTime")),
+            radionuclide_total_dose: radiopharmaceutical
+                .and_then(|item| get_value::<f32>(item, "RadionuclideTotalDose")),
+            radionuclide_half_life: radiopharmaceutical
+                .and_then(|item| get_value::<f32>(item, "RadionuclideHalfLife")),
+            bits_allocated: get_value::<u16>(obj, "BitsAllocated")
+                .ok_or(MissingAttribute("BitsAllocated"))?,
+            bits_stored: get_value::<u16>(obj, "BitsStored")
+                .ok_or(MissingAttribute("BitsStored"))?,
+            high_bit: get_value::<u16>(obj, "HighBit").ok_or(MissingAttribute("HighBit"))?,
+            pixel_representation: get_value::<u16>(obj, "PixelRepresentation")
+                .ok_or(MissingAttribute("PixelRepresentation"))?,
+            transfer_syntax_uid: transfer_syntax_uid(obj),
+            pixel_data: read_pixel_data(obj)?, // Decoded to native samples; empty for header-only reads
+        })
+    }
+
+    // Stored pixel values, masked to BitsStored at HighBit and sign-extended for PixelRepresentation 1
+    pub fn get_stored_values(&self) -> Result<Vec<i32>> {
+        self.get_stored_values_from(&self.pixel_data)
+    }
+
+    // Stored pixel values of native samples kept apart from the image, e.g. in the pixel cache
+    pub fn get_stored_values_from(&self, pixel_data: &[u8]) -> Result<Vec<i32>> {
+        stored_values(
+            pixel_data,
+            self.bits_allocated,
+            self.bits_stored,
+            self.high_bit,
+            self.pixel_representation,
+            is_big_endian(&self.transfer_syntax_uid),
+        )
+    }
+
+    // Activity concentration in `units` (typically Bq/ml) after applying rescale slope and intercept
+    pub fn get_pixel_data_f32(&self) -> Result<Vec<f32>> {
+        self.get_pixel_data_f32_from(&self.pixel_data)
+    }
+
+    pub fn get_pixel_data_f32_from(&self, pixel_data: &[u8]) -> Result<Vec<f32>> {
+        let rescale_slope = self.rescale_slope.unwrap_or(1.0);
+        let rescale_intercept = self.rescale_intercept.unwrap_or(0.0);
+        Ok(self
+            .get_stored_values_from(pixel_data)?
+            .into_iter()
+            .map(|v| v as f32 * rescale_slope + rescale_intercept)
+            .collect())
+    }
+
+    // Activity values as `i16`, clamped to the `i16` range
+    pub fn get_pixel_data(&self) -> Result<Vec<i16>> {
+        self.get_pixel_data_from(&self.pixel_data)
+    }
+
+    pub fn get_pixel_data_from(&self, pixel_data: &[u8]) -> Result<Vec<i16>> {
+        Ok(self
+            .get_pixel_data_f32_from(pixel_data)?
+            .into_iter()
+            .map(|v| v.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+            .collect())
+    }
+
+    /// Computes the factor converting activity concentration to body-weight SUV (g/ml).
+    ///
+    /// SUVbw = C * weight / D, where the injected dose D is decay-corrected from the
+    /// radiopharmaceutical start time to the time the pixel values refer to: the series start
+    /// for DecayCorrection `START`, the acquisition time for `NONE`, and no correction for `ADMIN`.
+    ///
+    /// # Errors
+    /// - If Units is neither `BQML` nor already `GML`.
+    /// - If the patient weight, total dose, half life or any required time is missing.
+    pub fn suv_factor(&self) -> Result<f32> {
+        match self.units.as_deref().map(str::trim) {
+            Some("GML") => return Ok(1.0),
+            Some("BQML") => {}
+            other => return Err(anyhow!("SUV requires BQML units, but got {:?}", other)),
+        }
+
+        let weight_g = self
+            .patient_weight
+            .filter(|w| *w > 0.0)
+            .ok_or(MissingAttribute("PatientWeight"))? as f64
+            * 1000.0;
+        let dose = self
+            .radionuclide_total_dose
+            .filter(|d| *d > 0.0)
+            .ok_or(MissingAttribute("RadionuclideTotalDose"))? as f64;
+        let half_life = self
+            .radionuclide_half_life
+            .filter(|h| *h > 0.0)
+            .ok_or(MissingAttribute("RadionuclideHalfLife"))? as f64;
+
+        let reference_time = match self.decay_correction.as_deref().map(str::trim).unwrap_or("START") {
+            "ADMIN" => None,
+            "NONE" => Some(self.acquisition_time.as_ref().or(self.series_time.as_ref())),
+            _ => Some(self.series_time.as_ref()),
+        };
+
+        let decayed_dose = match reference_time {
+            None => dose,
+            Some(time) => {
+                let scan = time
+                    .and_then(|t| parse_time(t))
+                    .ok_or_else(|| anyhow!("Missing or invalid SeriesTime/AcquisitionTime"))?;
+                let injection = self
+                    .radiopharmaceutical_start_time
+                    .as_deref()
+                    .and_then(parse_time)
+                    .ok_or_else(|| anyhow!("Missing or invalid RadiopharmaceuticalStartTime"))?;
+                // Scans crossing midnight have an earlier clock time than the injection
+                let mut elapsed = scan - injection;
+                if elapsed < 0.0 {
+                    elapsed += 24.0 * 3600.0;
+                }
+                dose * (-elapsed * std::f64::consts::LN_2 / half_life).exp()
+            }
+        };
+
+        Ok((weight_g / decayed_dose) as f32)
+    }
+
+    // Body-weight SUV values
+    pub fn get_suv_values(&self) -> Result<Vec<f32>> {
+        self.get_suv_values_from(&self.pixel_data)
+    }
+
+    pub fn get_suv_values_from(&self, pixel_data: &[u8]) -> Result<Vec<f32>> {
+        let factor = self.suv_factor()?;
+        Ok(self
+            .get_pixel_data_f32_from(pixel_data)?
+            .into_iter()
+            .map(|v| v * factor)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One 16-bit pixel of 10000 with rescale slope 2 and all SUV attributes present
+    fn test_image() -> PTImage {
+        PTImage {
+            uid: "1.2.3.4".to_string(),
+            series_uid: "1.2.3".to_string(),
+            instance_number: None,
+            rows: 1,
+            columns: 1,
+            pixel_spacing: None,
+            slice_thickness: None,
+            image_position_patient: None,
+            image_orientation_patient: None,
+            rescale_slope: Some(2.0),
+            rescale_intercept: None,
+            units: Some("BQML".to_string()),
+            decay_correction: Some("START".to_string()),
+            patient_weight: Some(70.0),
+            series_time: Some("110000".to_string()),
+            acquisition_time: None,
+            radiopharmaceutical_start_time: Some("095000.00".to_string()),
+            radionuclide_total_dose: Some(370e6),
+            radionuclide_half_life: Some(6588.0),
+            bits_allocated: 16,
+            bits_stored: 16,
+            high_bit: 15,
+            pixel_representation: 0,
+            transfer_syntax_uid: "1.2.840.10008.1.2.1".to_string(),
+            pixel_data: vec![0x10, 0x27], // 10000
+        }
+    }
+
+    #[test]
+    fn test_suv_factor_decay_corrected_to_series_start() {
+        let image = test_image();
+
+        // 70 minutes of F-18 decay: 370 MBq * 2^(-4200/6588)
+        let decayed_dose = 370e6 * (-4200.0 * std::f64::consts::LN_2 / 6588.0).exp();
+        let expected = (70000.0 / decayed_dose) as f32;
+        assert!((image.suv_factor().unwrap() - expected).abs() < 1e-9);
+
+        let suv = image.get_suv_values().unwrap();
+        assert!((suv[0] - 20000.0 * expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_float_volume_keeps_activity_above_i16() {
+        use crate::dicom::{DicomRepo, ImageInstance, ImageSeries};
+
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ImageSeries::new("1.2.3".into(), "1.2".into(), "PT".into(), None, None, None, None));
+        for (i, z) in [0.0, 2.0].into_iter().enumerate() {
+            repo.add_image(ImageInstance::PT(PTImage {
+                uid: format!("1.2.3.{}", i),
+                pixel_spacing: Some((1.0, 1.0)),
+                image_position_patient: Some((0.0, 0.0, z)),
+                image_orientation_patient: Some((1.0, 0.0, 0.0, 0.0, 1.0, 0.0)),
+                pixel_data: vec![0x30, 0x75], // 30000, i.e. 60000 Bq/ml
+                ..test_image()
+            }));
+        }
+        assert!(repo.generate_ct_volume_with_options("1.2.3", &Default::default()).is_err());
+
+        // Every image carries the SUV attributes, so the whole volume is in SUV
+        let factor = test_image().suv_factor().unwrap();
+        assert!(repo.is_suv_volume("1.2.3"));
+        let volume = repo.generate_float_volume("1.2.3").unwrap();
+        assert_eq!(volume.dimensions(), (1, 1, 2));
+        assert!(volume.voxel_data().iter().all(|v| (v - 60000.0 * factor).abs() < 1e-3));
+
+        // Without a patient weight the activity concentration is kept, unclamped
+        let mut image = repo.load_image("1.2.3.1").unwrap();
+        if let ImageInstance::PT(pt) = &mut image {
+            pt.patient_weight = None;
+        }
+        repo.add_image(image);
+        assert!(!repo.is_suv_volume("1.2.3"));
+        let volume = repo.generate_float_volume("1.2.3").unwrap();
+        assert_eq!(volume.voxel_data(), &[60000.0, 60000.0]);
+    }
+}
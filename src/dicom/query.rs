@@ -0,0 +1,402 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use super::dicom_repo::DicomRepo;
+use super::image_instance::{DicomImage, ImageInstance};
+use super::image_series::ImageSeries;
+use super::patient::Patient;
+use super::studyset::StudySet;
+
+// One level of the patient -> study -> series -> image hierarchy: the children of each parent
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Links {
+    children: HashMap<String, HashSet<String>>,
+}
+
+impl Links {
+    pub(crate) fn link(&mut self, parent: &str, child: &str) {
+        self.children
+            .entry(parent.to_string())
+            .or_default()
+            .insert(child.to_string());
+    }
+
+    pub(crate) fn unlink(&mut self, parent: &str, child: &str) {
+        if let Some(children) = self.children.get_mut(parent) {
+            children.remove(child);
+            if children.is_empty() {
+                self.children.remove(parent);
+            }
+        }
+    }
+
+    pub(crate) fn children(&self, parent: &str) -> impl Iterator<Item = &String> {
+        self.children.get(parent).into_iter().flatten()
+    }
+
+    pub(crate) fn count(&self, parent: &str) -> usize {
+        self.children.get(parent).map_or(0, HashSet::len)
+    }
+
+    pub(crate) fn parents(&self) -> impl Iterator<Item = &String> {
+        self.children.keys()
+    }
+}
+
+// Order values with missing ones last
+pub(crate) fn none_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// Studies by date (undated last), then UID
+pub(crate) fn study_order(a: &StudySet, b: &StudySet) -> Ordering {
+    none_last(a.date.as_ref(), b.date.as_ref()).then_with(|| a.uid.cmp(&b.uid))
+}
+
+// Series by series number (unnumbered last), then UID
+pub(crate) fn series_order(a: &ImageSeries, b: &ImageSeries) -> Ordering {
+    none_last(a.series_number, b.series_number).then_with(|| a.uid.cmp(&b.uid))
+}
+
+// Images by instance number (unnumbered last), then frame number and key
+pub(crate) fn image_order(a: &ImageInstance, b: &ImageInstance) -> Ordering {
+    none_last(a.instance_number(), b.instance_number())
+        .then_with(|| a.frame_number().cmp(&b.frame_number()))
+        .then_with(|| a.key().cmp(&b.key()))
+}
+
+// Case-insensitive match of DICOM-style wildcards: `*` matches any run of characters, `?`
+// a single character
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last `*` absorb one more character
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Filter over the series of a `DicomRepo`, built with chained calls:
+///
+/// ```ignore
+/// let query = RepoQuery::new().patient_name("DOE^*").modality("CT").study_date_from("20240101");
+/// for m in query.series(&repo) { println!("{} {}", m.series.uid, m.instance_count); }
+/// ```
+///
+/// Dates are DICOM DA values (`YYYYMMDD`) and ranges include their bounds. Entities lacking
+/// a filtered attribute do not match. Results come sorted by study date, series number
+/// and, for images, instance number.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct RepoQuery {
+    pub patient_id: Option<String>,
+    pub patient_name: Option<String>,   // Wildcard pattern, e.g. `DOE^J*`
+    pub birthdate_from: Option<String>,
+    pub birthdate_to: Option<String>,
+    pub study_date_from: Option<String>,
+    pub study_date_to: Option<String>,
+    pub modality: Option<String>,
+    pub description: Option<String>,    // Substring of the series or study description, ignoring case
+    pub min_instances: Option<usize>,
+    pub max_instances: Option<usize>,
+}
+
+// A series matching a `RepoQuery`, with the study and patient it belongs to
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SeriesMatch<'a> {
+    pub patient: Option<&'a Patient>,
+    pub study: Option<&'a StudySet>,
+    pub series: &'a ImageSeries,
+    pub instance_count: usize,
+}
+
+fn in_range(value: Option<&str>, from: &Option<String>, to: &Option<String>) -> bool {
+    if from.is_none() && to.is_none() {
+        return true;
+    }
+    let Some(value) = value.map(str::trim) else {
+        return false;
+    };
+    from.as_deref().is_none_or(|from| value >= from.trim()) && to.as_deref().is_none_or(|to| value <= to.trim())
+}
+
+impl RepoQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn patient_id(mut self, patient_id: &str) -> Self {
+        self.patient_id = Some(patient_id.to_string());
+        self
+    }
+
+    pub fn patient_name(mut self, pattern: &str) -> Self {
+        self.patient_name = Some(pattern.to_string());
+        self
+    }
+
+    pub fn birthdate_from(mut self, date: &str) -> Self {
+        self.birthdate_from = Some(date.to_string());
+        self
+    }
+
+    pub fn birthdate_to(mut self, date: &str) -> Self {
+        self.birthdate_to = Some(date.to_string());
+        self
+    }
+
+    pub fn study_date_from(mut self, date: &str) -> Self {
+        self.study_date_from = Some(date.to_string());
+        self
+    }
+
+    pub fn study_date_to(mut self, date: &str) -> Self {
+        self.study_date_to = Some(date.to_string());
+        self
+    }
+
+    pub fn modality(mut self, modality: &str) -> Self {
+        self.modality = Some(modality.to_string());
+        self
+    }
+
+    pub fn description(mut self, text: &str) -> Self {
+        self.description = Some(text.to_string());
+        self
+    }
+
+    pub fn min_instances(mut self, count: usize) -> Self {
+        self.min_instances = Some(count);
+        self
+    }
+
+    pub fn max_instances(mut self, count: usize) -> Self {
+        self.max_instances = Some(count);
+        self
+    }
+
+    fn has_patient_filter(&self) -> bool {
+        self.patient_id.is_some()
+            || self.patient_name.is_some()
+            || self.birthdate_from.is_some()
+            || self.birthdate_to.is_some()
+    }
+
+    fn matches_patient(&self, patient: Option<&Patient>) -> bool {
+        if !self.has_patient_filter() {
+            return true;
+        }
+        let Some(patient) = patient else {
+            return false;
+        };
+        self.patient_id.as_ref().is_none_or(|id| *id == patient.patient_id)
+            && self
+                .patient_name
+                .as_ref()
+                .is_none_or(|pattern| wildcard_match(pattern, &patient.name))
+            && in_range(patient.birthdate.as_deref(), &self.birthdate_from, &self.birthdate_to)
+    }
+
+    fn matches_series(&self, study: Option<&StudySet>, series: &ImageSeries, instance_count: usize) -> bool {
+        let description = self.description.as_ref().map(|text| text.to_lowercase());
+        let contains = |value: Option<&String>| {
+            value.is_some_and(|value| value.to_lowercase().contains(description.as_deref().unwrap_or("")))
+        };
+        self.modality
+            .as_ref()
+            .is_none_or(|modality| modality.eq_ignore_ascii_case(&series.modality))
+            && (description.is_none()
+                || contains(series.description.as_ref())
+                || contains(study.and_then(|s| s.description.as_ref())))
+            && self.min_instances.is_none_or(|min| instance_count >= min)
+            && self.max_instances.is_none_or(|max| instance_count <= max)
+    }
+
+    /// Series matching the query, sorted by study date, study, series number.
+    pub fn series<'a>(&self, repo: &'a DicomRepo) -> Vec<SeriesMatch<'a>> {
+        let mut matches = Vec::new();
+        // Walk the studies through the secondary indexes, so each filter level prunes the next
+        let study_uids: Vec<&String> = match &self.patient_id {
+            Some(patient_id) => repo.studies_by_patient.children(patient_id).collect(),
+            None => repo.series_by_study.parents().collect(),
+        };
+        for study_uid in study_uids {
+            let study = repo.study_sets.get(study_uid);
+            if !in_range(study.and_then(|s| s.date.as_deref()), &self.study_date_from, &self.study_date_to) {
+                continue;
+            }
+            let patient = study.and_then(|s| repo.patients.get(&s.patient_id));
+            if !self.matches_patient(patient) {
+                continue;
+            }
+            for series in repo.series_of(study_uid) {
+                let instance_count = repo.series_image_count(&series.uid);
+                if self.matches_series(study, series, instance_count) {
+                    matches.push(SeriesMatch {
+                        patient,
+                        study,
+                        series,
+                        instance_count,
+                    });
+                }
+            }
+        }
+        matches.sort_by(|a, b| {
+            none_last(a.study.and_then(|s| s.date.as_ref()), b.study.and_then(|s| s.date.as_ref()))
+                .then_with(|| a.series.study_uid.cmp(&b.series.study_uid))
+                .then_with(|| series_order(a.series, b.series))
+        });
+        matches
+    }
+
+    // Studies with at least one matching series, in study order
+    pub fn studies<'a>(&self, repo: &'a DicomRepo) -> Vec<&'a StudySet> {
+        let mut seen = HashSet::new();
+        self.series(repo)
+            .into_iter()
+            .filter_map(|m| m.study)
+            .filter(|study| seen.insert(&study.uid))
+            .collect()
+    }
+
+    // Images of the matching series, in series order and then instance order
+    pub fn images<'a>(&self, repo: &'a DicomRepo) -> Vec<&'a ImageInstance> {
+        self.series(repo)
+            .into_iter()
+            .flat_map(|m| repo.images_of(&m.series.uid))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::ct_image::CTImage;
+    use crate::dicom::test_util::test_ct_image;
+    use std::path::PathBuf;
+
+    fn ct_image(uid: &str, series_uid: &str, instance_number: i32) -> ImageInstance {
+        ImageInstance::CT(CTImage {
+            uid: uid.to_string(),
+            series_uid: series_uid.to_string(),
+            instance_number: Some(instance_number),
+            ..test_ct_image()
+        })
+    }
+
+    fn test_repo() -> DicomRepo {
+        let mut repo = DicomRepo::new();
+        repo.add_patient(Patient::new("P1".into(), "DOE^JOHN".into(), Some("19600101".into()), None));
+        repo.add_patient(Patient::new("P2".into(), "ROE^JANE".into(), Some("19900101".into()), None));
+        repo.add_study(StudySet::new(Some("1".into()), "S2".into(), "P1".into(), Some("20240301".into()), None));
+        repo.add_study(StudySet::new(Some("2".into()), "S1".into(), "P1".into(), Some("20240101".into()), Some("Thorax".into())));
+        repo.add_study(StudySet::new(Some("3".into()), "S3".into(), "P2".into(), Some("20240201".into()), None));
+        repo.add_image_series(ImageSeries::new("A".into(), "S1".into(), "CT".into(), Some(2), None, None, None));
+        repo.add_image_series(ImageSeries::new("B".into(), "S1".into(), "CT".into(), Some(1), None, None, None));
+        repo.add_image_series(ImageSeries::new("C".into(), "S2".into(), "MR".into(), Some(1), None, None, None));
+        repo.add_image_series(ImageSeries::new("D".into(), "S3".into(), "CT".into(), None, None, None, None));
+        for (uid, series, number) in [("a2", "A", 2), ("a1", "A", 1), ("b1", "B", 1), ("c1", "C", 1)] {
+            repo.add_image(ct_image(uid, series, number));
+        }
+        repo
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(wildcard_match("doe^*", "DOE^JOHN"));
+        assert!(wildcard_match("*^J?HN", "DOE^JOHN"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("DOE", "DOE^JOHN"));
+        assert!(!wildcard_match("*X*", "DOE^JOHN"));
+    }
+
+    #[test]
+    fn test_filters_and_ordering() {
+        let repo = test_repo();
+        let uids = |query: RepoQuery| -> Vec<String> {
+            query.series(&repo).iter().map(|m| m.series.uid.clone()).collect()
+        };
+
+        // Study date first, then series number
+        assert_eq!(uids(RepoQuery::new()), vec!["B", "A", "D", "C"]);
+        assert_eq!(uids(RepoQuery::new().patient_name("doe*")), vec!["B", "A", "C"]);
+        assert_eq!(uids(RepoQuery::new().birthdate_from("19700101")), vec!["D"]);
+        assert_eq!(uids(RepoQuery::new().study_date_from("20240115").study_date_to("20240301")), vec!["D", "C"]);
+        assert_eq!(uids(RepoQuery::new().modality("ct").description("thor")), vec!["B", "A"]);
+        assert_eq!(uids(RepoQuery::new().min_instances(2)), vec!["A"]);
+        assert_eq!(uids(RepoQuery::new().max_instances(0)), vec!["D"]);
+
+        let keys: Vec<String> = RepoQuery::new().patient_id("P1").images(&repo).iter().map(|i| i.key()).collect();
+        assert_eq!(keys, vec!["b1", "a1", "a2", "c1"]);
+        assert_eq!(RepoQuery::new().studies(&repo).len(), 3);
+    }
+
+    #[test]
+    fn test_indexes_follow_updates() {
+        let mut repo = test_repo();
+        // Moving an image to another series relinks it
+        repo.add_image(ct_image("a1", "B", 5));
+        assert_eq!(repo.series_image_count("A"), 1);
+        assert_eq!(repo.series_image_count("B"), 2);
+
+        repo.remove_series("B");
+        assert!(repo.series_of("S1").iter().all(|s| s.uid != "B"));
+        assert_eq!(repo.images_of("B").len(), 0);
+    }
+
+    #[test]
+    fn test_instance_counts_include_unread_images() {
+        let mut repo = test_repo();
+        // Series D is browsed from a DICOMDIR: its two images are listed, not read
+        repo.add_unread_image("D", "d1", PathBuf::from("IM1"));
+        repo.add_unread_image("D", "d2", PathBuf::from("IM2"));
+        let matches = RepoQuery::new().min_instances(2).series(&repo);
+        let counts: Vec<(&str, usize)> = matches.iter().map(|m| (m.series.uid.as_str(), m.instance_count)).collect();
+        assert_eq!(counts, vec![("A", 2), ("D", 2)]);
+        assert!(RepoQuery::new().max_instances(0).series(&repo).is_empty());
+    }
+
+    #[test]
+    fn test_undated_studies_and_grouping() {
+        let mut repo = test_repo();
+        // StudyID and StudyDate are Type 2: a study without them is kept and sorted last
+        repo.add_study(StudySet::new(None, "S4".into(), "P2".into(), None, None));
+        repo.add_image_series(ImageSeries::new("E".into(), "S4".into(), "CT".into(), None, Some("F1".into()), None, None));
+        repo.add_image_series(ImageSeries::new("B".into(), "S1".into(), "CT".into(), Some(1), Some("F1".into()), None, None));
+        let studies: Vec<&str> = repo.studies_of("P2").iter().map(|s| s.uid.as_str()).collect();
+        assert_eq!(studies, vec!["S3", "S4"]);
+
+        let registered: Vec<&str> = repo.series_in_frame_of_reference("F1").iter().map(|s| s.uid.as_str()).collect();
+        assert_eq!(registered, vec!["B", "E"]);
+
+        for (uid, acquisition) in [("e1", 2), ("e2", 1), ("e3", 2)] {
+            let mut image = ct_image(uid, "E", 1);
+            if let ImageInstance::CT(ct) = &mut image {
+                ct.acquisition_number = Some(acquisition);
+            }
+            repo.add_image(image);
+        }
+        assert_eq!(repo.acquisitions_of("E"), vec![1, 2]);
+    }
+}
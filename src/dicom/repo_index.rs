@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::dicom_repo::DicomRepo;
+use super::image_instance::{DicomImage, ImageInstance};
+use super::image_series::ImageSeries;
+use super::ingest::{FileOutcome, IngestStatus};
+use super::patient::Patient;
+use super::studyset::StudySet;
+
+// Leading bytes of an index file, followed by the format version (u32, little endian)
+const INDEX_MAGIC: &[u8; 8] = b"KPLRIDX\0";
+
+// Version of the index layout; bump it whenever an indexed entity changes shape, so that
+// indexes written by older versions are rebuilt instead of misread
+pub const INDEX_VERSION: u32 = 3;
+
+/// On-disk index of a `DicomRepo`: the entities and, per file, the size and modification
+/// time it had when indexed together with the headers of its images.
+///
+/// Pixel data is not indexed; images restored from an index load it from their file.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct RepoIndex {
+    pub patients: Vec<Patient>,
+    pub studies: Vec<StudySet>,
+    pub series: Vec<ImageSeries>,
+    pub files: Vec<IndexedFile>,
+}
+
+// A file of the index with the images read from it
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct IndexedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+    pub images: Vec<ImageInstance>, // Without pixel data
+}
+
+// Borrowed counterparts of `RepoIndex` and `IndexedFile` used for writing, so that saving
+// does not copy the repository; the field order must match
+#[derive(Serialize)]
+struct RepoIndexRef<'a> {
+    patients: Vec<&'a Patient>,
+    studies: Vec<&'a StudySet>,
+    series: Vec<&'a ImageSeries>,
+    files: Vec<IndexedFileRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct IndexedFileRef<'a> {
+    path: &'a Path,
+    size: u64,
+    modified: SystemTime,
+    images: Vec<HeaderOnly<'a>>,
+}
+
+// Serializes an image without its pixel data
+struct HeaderOnly<'a>(&'a ImageInstance);
+
+impl Serialize for HeaderOnly<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !self.0.has_pixel_data() {
+            return self.0.serialize(serializer);
+        }
+        let mut header = self.0.clone();
+        header.clear_pixel_data();
+        header.serialize(serializer)
+    }
+}
+
+// Size and modification time of a file, used to detect changes
+fn file_stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+impl RepoIndex {
+    /// Reads an index written by `DicomRepo::save_index`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, is not an index, was written with
+    /// another `INDEX_VERSION` or is corrupt.
+    pub fn load(path: &Path) -> Result<RepoIndex> {
+        let bytes = fs::read(path)
+            .map_err(|err| anyhow!("Failed to read index {}: {}", path.display(), err))?;
+        let payload = bytes
+            .strip_prefix(INDEX_MAGIC.as_slice())
+            .ok_or_else(|| anyhow!("{} is not a repository index", path.display()))?;
+        if payload.len() < 4 {
+            return Err(anyhow!("Truncated index {}", path.display()));
+        }
+        let (version, payload) = payload.split_at(4);
+        let version = u32::from_le_bytes([version[0], version[1], version[2], version[3]]);
+        if version != INDEX_VERSION {
+            return Err(anyhow!(
+                "Index {} has version {}, expected {}",
+                path.display(),
+                version,
+                INDEX_VERSION
+            ));
+        }
+        bincode::deserialize(payload)
+            .map_err(|err| anyhow!("Corrupt index {}: {}", path.display(), err))
+    }
+
+    /// Adds the indexed entities of the files that did not change since indexing.
+    ///
+    /// Of `files`, those with the indexed size and modification time have their images
+    /// restored as header-only images, together with the series, studies and patients they
+    /// belong to. Returns the outcomes of the restored files and the files left to parse.
+    pub fn restore_unchanged(self, repo: &mut DicomRepo, files: Vec<PathBuf>) -> (Vec<FileOutcome>, Vec<PathBuf>) {
+        let mut indexed: HashMap<PathBuf, IndexedFile> = self
+            .files
+            .into_iter()
+            .map(|file| (file.path.clone(), file))
+            .collect();
+
+        let mut outcomes = Vec::new();
+        let mut changed = Vec::new();
+        let mut series_uids = HashSet::new();
+        for path in files {
+            let entry = indexed
+                .remove(&path)
+                .filter(|entry| file_stamp(&path) == Some((entry.size, entry.modified)));
+            let Some(entry) = entry else {
+                changed.push(path);
+                continue;
+            };
+            outcomes.push(FileOutcome {
+                path: path.clone(),
+                status: IngestStatus::Indexed,
+                reason: None,
+                images: entry.images.len(),
+            });
+            for image in entry.images {
+                series_uids.insert(image.series_uid().to_string());
+                repo.add_pixel_source(image.key(), path.clone());
+                repo.add_image(image);
+            }
+        }
+
+        // Only the hierarchy above restored images is kept; changed files bring their own
+        let series: Vec<ImageSeries> = self
+            .series
+            .into_iter()
+            .filter(|series| series_uids.contains(&series.uid))
+            .collect();
+        let study_uids: HashSet<&str> = series.iter().map(|s| s.study_uid.as_str()).collect();
+        let studies: Vec<StudySet> = self
+            .studies
+            .into_iter()
+            .filter(|study| study_uids.contains(study.uid.as_str()))
+            .collect();
+        let patient_ids: HashSet<&str> = studies.iter().map(|s| s.patient_id.as_str()).collect();
+        for patient in self.patients {
+            if patient_ids.contains(patient.patient_id.as_str()) {
+                repo.add_patient(patient);
+            }
+        }
+        for study in studies {
+            repo.add_study(study);
+        }
+        for series in series {
+            repo.add_image_series(series);
+        }
+        (outcomes, changed)
+    }
+}
+
+impl DicomRepo {
+    /// Writes the index of the repository to `path`, replacing any previous index.
+    ///
+    /// Files are recorded with their current size and modification time. Images not read
+    /// from a file, or whose file no longer exists, are left out. Files without images, such
+    /// as RT structure sets, are not indexed and are parsed again on the next ingest.
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be serialized or written.
+    pub fn save_index(&self, path: &Path) -> Result<()> {
+        // Group the images by file, in a stable order
+        let mut by_file: BTreeMap<&Path, Vec<HeaderOnly>> = BTreeMap::new();
+        for (key, image) in &self.images {
+            if let Some(source) = self.pixel_sources.get(key) {
+                by_file.entry(source.as_path()).or_default().push(HeaderOnly(image));
+            }
+        }
+        let files = by_file
+            .into_iter()
+            .filter_map(|(path, images)| {
+                let (size, modified) = file_stamp(path)?;
+                Some(IndexedFileRef {
+                    path,
+                    size,
+                    modified,
+                    images,
+                })
+            })
+            .collect();
+        let index = RepoIndexRef {
+            patients: self.patients.values().collect(),
+            studies: self.study_sets.values().collect(),
+            series: self.image_series.values().collect(),
+            files,
+        };
+
+        let mut bytes = INDEX_MAGIC.to_vec();
+        bytes.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, &index)
+            .map_err(|err| anyhow!("Failed to serialize index: {}", err))?;
+
+        // Write next to the target and rename, so an interrupted save keeps the old index
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &bytes)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|err| anyhow!("Failed to write index {}: {}", path.display(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::fileio::ParsedFile;
+    use crate::dicom::pixel_cache::tests::write_ct_file;
+
+    #[test]
+    fn test_index_round_trip_restores_unchanged_files() {
+        let dir = std::env::temp_dir().join(format!("kepler-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("ct.dcm");
+        write_ct_file(&file);
+
+        let mut repo = DicomRepo::new();
+        ParsedFile::from_file_bytes(file.clone(), &fs::read(&file).unwrap())
+            .unwrap()
+            .add_to(&mut repo);
+        let index_path = dir.join("repo.idx");
+        repo.save_index(&index_path).unwrap();
+
+        let index = RepoIndex::load(&index_path).unwrap();
+        assert_eq!(index.files.len(), 1);
+        assert!(!index.files[0].images[0].has_pixel_data());
+
+        // Unchanged: restored as a header-only image that loads its pixels from the file
+        let mut restored = DicomRepo::new();
+        let (outcomes, changed) = index.clone().restore_unchanged(&mut restored, vec![file.clone()]);
+        assert!(changed.is_empty());
+        assert_eq!(outcomes[0].status, IngestStatus::Indexed);
+        assert_eq!(restored.load_image("1.2.3.4").unwrap().get_pixel_data().unwrap(), vec![7, 9]);
+
+        // A different size marks the file as changed
+        let mut stale = index;
+        stale.files[0].size += 1;
+        let (outcomes, changed) = stale.restore_unchanged(&mut DicomRepo::new(), vec![file.clone()]);
+        assert!(outcomes.is_empty());
+        assert_eq!(changed, vec![file]);
+
+        // Indexes of another version are rejected
+        let mut bytes = fs::read(&index_path).unwrap();
+        bytes[INDEX_MAGIC.len()] = 0xFF;
+        fs::write(&index_path, bytes).unwrap();
+        assert!(RepoIndex::load(&index_path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+use super::rt_struct::{Contour, ContourGeometry, Roi};
+use super::series_geometry::Vec3;
+use crate::volume::{Voxel, Volume};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+// Mask value of a voxel entirely inside a ROI; partial voxels hold their inside fraction scaled
+// to this value, so an R8Unorm texture of the mask samples as the fraction
+pub const MASK_INSIDE: u8 = 255;
+
+// Largest distance (in slices) between the points of a contour and its slice
+const PLANE_TOLERANCE: f64 = 0.5;
+
+pub type MaskVolume = Volume<u8>;
+
+// How contours are turned into a mask
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterizeOptions {
+    // Estimate the fraction of each voxel inside the contours instead of testing its centre only
+    pub partial_volume: bool,
+    // Samples per voxel side used to estimate the fraction when `partial_volume` is set
+    pub subsamples: usize,
+}
+
+impl Default for RasterizeOptions {
+    fn default() -> Self {
+        RasterizeOptions {
+            partial_volume: false,
+            subsamples: 4,
+        }
+    }
+}
+
+impl Roi {
+    // Rasterize the closed contours of the ROI onto the grid of `reference`
+    pub fn to_mask<T: Voxel>(&self, reference: &Volume<T>, options: &RasterizeOptions) -> Result<MaskVolume> {
+        rasterize_contours(self.closed_contours(), reference, options)
+    }
+}
+
+/// Rasterizes closed planar contours into a mask sharing the geometry of `reference`.
+///
+/// Each contour is assigned to the slice it lies in. A voxel is inside when it is enclosed by
+/// an odd number of the contours of its slice, so contours inside other contours cut holes
+/// and contours inside holes are islands again. Voxels inside hold `MASK_INSIDE`; with
+/// `options.partial_volume` boundary voxels hold their inside fraction scaled to it. Contours
+/// lying outside the volume are ignored.
+///
+/// # Errors
+/// Returns an error if a contour is not parallel to the slices of `reference`, or if the
+/// geometry of `reference` is degenerate.
+pub fn rasterize_contours<'a, T: Voxel>(
+    contours: impl IntoIterator<Item = &'a Contour>,
+    reference: &Volume<T>,
+    options: &RasterizeOptions,
+) -> Result<MaskVolume> {
+    let (rows, columns, slices) = reference.dimensions();
+    let to_index = reference
+        .patient_to_index()
+        .ok_or_else(|| anyhow!("The reference volume has a degenerate geometry"))?;
+
+    // Polygons per slice, in (column, row) index coordinates
+    let mut polygons: HashMap<usize, Vec<Vec<[f64; 2]>>> = HashMap::new();
+    for contour in contours {
+        if !contour.is_closed_planar() {
+            continue;
+        }
+        let points: Vec<[f64; 4]> = contour
+            .points
+            .iter()
+            .map(|p| to_index.apply(&[p[0], p[1], p[2], 1.0]))
+            .collect();
+        let k = points.iter().map(|p| p[2]).sum::<f64>() / points.len() as f64;
+        if points.iter().any(|p| (p[2] - k).abs() > PLANE_TOLERANCE) {
+            return Err(anyhow!("A contour is not parallel to the slices of the reference volume"));
+        }
+        let k = k.round();
+        if k < 0.0 || k >= slices as f64 {
+            continue;
+        }
+        polygons
+            .entry(k as usize)
+            .or_default()
+            .push(points.iter().map(|p| [p[0], p[1]]).collect());
+    }
+
+    let subsamples = if options.partial_volume { options.subsamples.max(1) } else { 1 };
+    let full = (subsamples * subsamples) as f64;
+    let mut voxel_data = vec![0u8; rows * columns * slices];
+    let mut counts = vec![0u32; rows * columns];
+    for (k, polygons) in polygons {
+        counts.iter_mut().for_each(|count| *count = 0);
+        fill_slice(&polygons, rows, columns, subsamples, &mut counts);
+        let slice = &mut voxel_data[k * rows * columns..(k + 1) * rows * columns];
+        for (value, &count) in slice.iter_mut().zip(&counts) {
+            *value = (count as f64 / full * MASK_INSIDE as f64).round() as u8;
+        }
+    }
+
+    MaskVolume::from_parts(
+        reference.dimensions(),
+        reference.voxel_spacing(),
+        *reference.index_to_patient(),
+        voxel_data,
+    )
+}
+
+// Count, per voxel, the sub-samples inside the polygons by even-odd scanline filling; with a
+// single sub-sample the voxel centre is tested
+fn fill_slice(polygons: &[Vec<[f64; 2]>], rows: usize, columns: usize, subsamples: usize, counts: &mut [u32]) {
+    let s = subsamples as f64;
+    let mut crossings = Vec::new();
+    for sub_row in 0..rows * subsamples {
+        let y = (sub_row as f64 + 0.5) / s - 0.5;
+        let j = sub_row / subsamples;
+
+        // Crossings of the sample row with every edge; half-open so vertices count once
+        crossings.clear();
+        for polygon in polygons {
+            for (n, a) in polygon.iter().enumerate() {
+                let b = &polygon[(n + 1) % polygon.len()];
+                if (a[1] <= y) != (b[1] <= y) {
+                    crossings.push(a[0] + (y - a[1]) * (b[0] - a[0]) / (b[1] - a[1]));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.total_cmp(b));
+
+        // Samples between consecutive pairs of crossings are inside
+        for span in crossings.chunks_exact(2) {
+            let first = ((span[0] + 0.5) * s - 0.5).ceil().max(0.0);
+            let last = ((span[1] + 0.5) * s - 0.5).ceil().min((columns * subsamples) as f64);
+            let mut sub_column = first as usize;
+            while (sub_column as f64) < last {
+                counts[j * columns + sub_column / subsamples] += 1;
+                sub_column += 1;
+            }
+        }
+    }
+}
+
+/// Extracts the outlines of a mask as closed planar contours in patient coordinates.
+///
+/// Each slice is traced with marching squares at half of `MASK_INSIDE`, interpolating between
+/// voxel centres, so partial-volume masks yield smooth outlines. Outer boundaries and holes
+/// are both returned, as the even-odd rule of `rasterize_contours` expects.
+pub fn mask_to_contours(mask: &MaskVolume) -> Vec<Contour> {
+    let (rows, columns, slices) = mask.dimensions();
+    let level = MASK_INSIDE as f64 / 2.0;
+    let mut contours = Vec::new();
+    for k in 0..slices {
+        let slice = &mask.voxel_data()[k * rows * columns..(k + 1) * rows * columns];
+        // Pad with an empty border, so every outline is closed
+        let value = |x: usize, y: usize| -> f64 {
+            if x == 0 || y == 0 || x > columns || y > rows {
+                0.0
+            } else {
+                slice[(y - 1) * columns + (x - 1)] as f64
+            }
+        };
+        for outline in trace_slice(&value, columns + 2, rows + 2, level) {
+            let points = outline
+                .into_iter()
+                .map(|[x, y]| mask.voxel_to_world([x - 1.0, y - 1.0, k as f64]))
+                .collect();
+            contours.push(Contour {
+                geometry: ContourGeometry::ClosedPlanar,
+                points,
+            });
+        }
+    }
+    contours
+}
+
+// A crossing of the iso-line with a grid edge: the edge from (x, y) to the right (false) or down (true)
+type EdgeKey = (usize, usize, bool);
+
+// Trace the closed iso-lines of a padded grid of `width` x `height` values
+fn trace_slice(value: &dyn Fn(usize, usize) -> f64, width: usize, height: usize, level: f64) -> Vec<Vec<[f64; 2]>> {
+    let inside = |x: usize, y: usize| value(x, y) > level;
+
+    // Link the edges crossed in every cell; each crossed edge joins exactly two cells
+    let mut links: HashMap<EdgeKey, Vec<EdgeKey>> = HashMap::new();
+    let mut link = |a: EdgeKey, b: EdgeKey| {
+        links.entry(a).or_default().push(b);
+        links.entry(b).or_default().push(a);
+    };
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let corners = [inside(x, y), inside(x + 1, y), inside(x + 1, y + 1), inside(x, y + 1)];
+            let (top, right, bottom, left) = ((x, y, false), (x + 1, y, true), (x, y + 1, false), (x, y, true));
+            // Edges around each corner, in corner order
+            let around = [(top, left), (top, right), (right, bottom), (bottom, left)];
+            match corners.iter().filter(|&&c| c).count() {
+                0 | 4 => {}
+                2 if corners[0] == corners[2] => {
+                    // Saddle: cut off the corners that differ from the cell centre
+                    let centre = (value(x, y) + value(x + 1, y) + value(x + 1, y + 1) + value(x, y + 1)) / 4.0 > level;
+                    for (corner, &(a, b)) in around.iter().enumerate() {
+                        if corners[corner] != centre {
+                            link(a, b);
+                        }
+                    }
+                }
+                _ => {
+                    let crossed: Vec<EdgeKey> = [(top, 0, 1), (right, 1, 2), (bottom, 3, 2), (left, 0, 3)]
+                        .into_iter()
+                        .filter(|&(_, a, b)| corners[a] != corners[b])
+                        .map(|(edge, _, _)| edge)
+                        .collect();
+                    link(crossed[0], crossed[1]);
+                }
+            }
+        }
+    }
+
+    // Interpolated position of the crossing along its edge
+    let position = |(x, y, down): EdgeKey| -> [f64; 2] {
+        let (x2, y2) = if down { (x, y + 1) } else { (x + 1, y) };
+        let (a, b) = (value(x, y), value(x2, y2));
+        let t = if a == b { 0.5 } else { (level - a) / (b - a) };
+        [x as f64 + t * (x2 - x) as f64, y as f64 + t * (y2 - y) as f64]
+    };
+
+    // Walk each loop of linked edges once
+    let mut outlines = Vec::new();
+    let mut starts: Vec<EdgeKey> = links.keys().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        if !links.contains_key(&start) {
+            continue;
+        }
+        let mut outline = Vec::new();
+        let (mut previous, mut current) = (start, start);
+        while let Some(next) = links.remove(&current) {
+            outline.push(position(current));
+            let step = next.into_iter().find(|&edge| edge != previous && links.contains_key(&edge));
+            match step {
+                Some(edge) => (previous, current) = (current, edge),
+                None => break,
+            }
+        }
+        if outline.len() >= 3 {
+            outlines.push(outline);
+        }
+    }
+    outlines
+}
+
+// Area enclosed by a planar polygon (mm²), whatever its orientation in space
+pub fn contour_area(points: &[Vec3]) -> f64 {
+    let mut normal = [0.0; 3];
+    for (n, a) in points.iter().enumerate() {
+        let b = &points[(n + 1) % points.len()];
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64, z: f64) -> Contour {
+        Contour {
+            geometry: ContourGeometry::ClosedPlanar,
+            points: vec![[min, min, z], [max, min, z], [max, max, z], [min, max, z]],
+        }
+    }
+
+    #[test]
+    fn test_holes_and_partial_volume() {
+        // 10 x 10 x 2 grid of 1 mm voxels with centres at 0..9
+        let reference = Volume::<i16>::new((10, 10, 2), (1.0, 1.0, 1.0), vec![0; 200]).unwrap();
+        let outer = square(-0.5, 7.5, 0.0);
+        let hole = square(1.5, 5.5, 0.0);
+        let island = square(2.5, 4.5, 0.0);
+
+        let mask = rasterize_contours([&outer, &hole, &island], &reference, &RasterizeOptions::default()).unwrap();
+        let inside = mask.voxel_data().iter().filter(|&&v| v == MASK_INSIDE).count();
+        assert_eq!(inside, 64 - 16 + 4);
+        assert_eq!(mask.voxel(0, 0, 0), Some(MASK_INSIDE));
+        assert_eq!(mask.voxel(2, 2, 0), Some(0));
+        assert_eq!(mask.voxel(3, 3, 0), Some(MASK_INSIDE));
+        assert_eq!(mask.voxel(0, 0, 1), Some(0));
+
+        // A square covering half of its border voxels
+        let options = RasterizeOptions {
+            partial_volume: true,
+            subsamples: 4,
+        };
+        let mask = rasterize_contours([&square(0.0, 3.0, 1.0)], &reference, &options).unwrap();
+        assert_eq!(mask.voxel(1, 1, 1), Some(MASK_INSIDE));
+        assert_eq!(mask.voxel(0, 1, 1), Some(128));
+        assert_eq!(mask.voxel(0, 0, 1), Some(64));
+    }
+
+    #[test]
+    fn test_mask_round_trip() {
+        let reference = Volume::<i16>::new((12, 12, 1), (1.0, 1.0, 2.0), vec![0; 144]).unwrap();
+        let outer = square(0.5, 9.5, 0.0);
+        let hole = square(3.5, 6.5, 0.0);
+        let mask = rasterize_contours([&outer, &hole], &reference, &RasterizeOptions::default()).unwrap();
+
+        let contours = mask_to_contours(&mask);
+        assert_eq!(contours.len(), 2);
+        let mut areas: Vec<f64> = contours.iter().map(|c| contour_area(&c.points)).collect();
+        areas.sort_by(|a, b| a.total_cmp(b));
+        // Outlines run along the voxel faces and cut each corner by a 0.125 mm² triangle
+        assert!((areas[0] - 8.5).abs() < 1e-9 && (areas[1] - 80.5).abs() < 1e-9, "{:?}", areas);
+
+        let again = rasterize_contours(&contours, &reference, &RasterizeOptions::default()).unwrap();
+        assert_eq!(again.voxel_data(), mask.voxel_data());
+    }
+}
@@ -0,0 +1,325 @@
+use super::dicom_helper::{get_first_item, get_value, get_values, MissingAttribute};
+use super::pixel_data::{is_big_endian, read_pixel_data, stored_values, transfer_syntax_uid};
+use super::series_geometry::{resample_slices, SliceOrientation, DUPLICATE_TOLERANCE};
+use crate::define_dicom_struct;
+use crate::volume::{index_to_patient_matrix, Volume};
+use anyhow::{anyhow, Context, Result};
+use dicom_object::{FileDicomObject, InMemDicomObject, OpenFileOptions};
+use std::path::Path;
+
+pub const RTDOSE_MODALITY: &str = "RTDOSE";
+
+// Largest relative deviation between frame spacings of a grid still stacked without resampling
+const GRID_SPACING_TOLERANCE: f64 = 1e-3;
+
+pub type DoseVolume = Volume<f32>;
+
+define_dicom_struct!(RTDose, {
+    (uid, String, "(0008,0018) SOPInstanceUID", false),              // Unique identifier of the dose
+    (series_uid, String, "(0020,000E) SeriesInstanceUID", false),  // Series of the RTDOSE object
+    (study_uid, String, "(0020,000D) StudyInstanceUID", false),     // StudyInstanceUID is required
+    (frame_of_reference_uid, String, "(0020,0052) FrameOfReferenceUID", true), // Shared with the planning CT and plan (Optional)
+    (referenced_plan_uid, String, "(300C,0002) > (0008,1155) ReferencedSOPInstanceUID", true), // Plan the dose was calculated for (Optional)
+    (dose_units, String, "(3004,0002) DoseUnits", false),            // GY or RELATIVE
+    (dose_type, String, "(3004,0004) DoseType", false),              // PHYSICAL, EFFECTIVE or ERROR
+    (dose_summation_type, String, "(3004,000A) DoseSummationType", false), // PLAN, FRACTION, BEAM, ...
+    (number_of_frames, u32, "(0028,0008) NumberOfFrames", false),    // One frame per dose plane
+    (rows, u16, "(0028,0010) Rows", false),                         // Rows (Mandatory)
+    (columns, u16, "(0028,0011) Columns", false),                    // Columns (Mandatory)
+    (pixel_spacing, (f32, f32), "(0028,0030) PixelSpacing", false),  // PixelSpacing (Mandatory)
+    (slice_thickness, f32, "(0018,0050) SliceThickness", true),      // SliceThickness (Optional)
+    (image_position_patient, (f32, f32, f32), "(0020,0032) ImagePositionPatient", false), // Position of the first voxel (Mandatory)
+    (image_orientation_patient, (f32, f32, f32, f32, f32, f32), "(0020,0037) ImageOrientationPatient", false), // ImageOrientationPatient (Mandatory)
+    (grid_frame_offset_vector, Vec<f64>, "(3004,000C) GridFrameOffsetVector", true), // Offsets of the frames along the normal (mm, required for multi-frame grids)
+    (dose_grid_scaling, f64, "(3004,000E) DoseGridScaling", false),  // Factor converting stored values to DoseUnits
+    (bits_allocated, u16, "(0028,0100) BitsAllocated", false),       // BitsAllocated (Mandatory)
+    (bits_stored, u16, "(0028,0101) BitsStored", false),             // BitsStored (Mandatory)
+    (high_bit, u16, "(0028,0102) HighBit", false),                   // HighBit (Mandatory)
+    (pixel_representation, u16, "(0028,0103) PixelRepresentation", false), // Pixel Representation (Mandatory)
+    (transfer_syntax_uid, String, "(0002,0010) TransferSyntaxUID", false), // Transfer syntax the file was encoded with
+    (pixel_data, Vec<u8>, "(7FE0,0010) PixelData", false)            // All frames; empty for header-only reads
+});
+
+impl RTDose {
+    // Function to parse the DICOM file and generate the RTDose structure
+    pub fn from_bytes(dicom_data: &[u8]) -> Result<RTDose> {
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
+
+    // Function to read the RTDose structure with its grid from a file, e.g. to load the grid
+    // of a dose read header-only
+    pub fn from_file(path: &Path) -> Result<RTDose> {
+        let obj = OpenFileOptions::new()
+            .open_file(path)
+            .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+        Self::from_object(&obj)
+    }
+
+    // Function to generate the RTDose structure from an already parsed DICOM file
+    pub fn from_object(obj: &FileDicomObject<InMemDicomObject>) -> Result<RTDose> {
+        let modality = get_value::<String>(obj, "Modality").ok_or(MissingAttribute("Modality"))?;
+        if modality != RTDOSE_MODALITY {
+            return Err(anyhow!("Expected modality {}, found {}", RTDOSE_MODALITY, modality));
+        }
+
+        Ok(RTDose {
+            uid: get_value::<String>(obj, "SOPInstanceUID")
+                .ok_or(MissingAttribute("SOPInstanceUID"))?,
+            series_uid: get_value::<String>(obj, "SeriesInstanceUID")
+                .ok_or(MissingAttribute("SeriesInstanceUID"))?,
+            study_uid: get_value::<String>(obj, "StudyInstanceUID")
+                .ok_or(MissingAttribute("StudyInstanceUID"))?,
+            frame_of_reference_uid: get_value::<String>(obj, "FrameOfReferenceUID"),
+            referenced_plan_uid: get_first_item(obj, "ReferencedRTPlanSequence")
+                .and_then(|item| get_value::<String>(item, "ReferencedSOPInstanceUID")),
+            dose_units: get_value::<String>(obj, "DoseUnits").ok_or(MissingAttribute("DoseUnits"))?,
+            dose_type: get_value::<String>(obj, "DoseType").ok_or(MissingAttribute("DoseType"))?,
+            dose_summation_type: get_value::<String>(obj, "DoseSummationType")
+                .ok_or(MissingAttribute("DoseSummationType"))?,
+            number_of_frames: get_value::<u32>(obj, "NumberOfFrames").unwrap_or(1),
+            rows: get_value::<u16>(obj, "Rows").ok_or(MissingAttribute("Rows"))?,
+            columns: get_value::<u16>(obj, "Columns").ok_or(MissingAttribute("Columns"))?,
+            pixel_spacing: get_values::<f32>(obj, "PixelSpacing")
+                .and_then(|v| match v[..] { [r, c] => Some((r, c)), _ => None })
+                .ok_or(MissingAttribute("PixelSpacing"))?,
+            slice_thickness: get_value::<f32>(obj, "SliceThickness"),
+            image_position_patient: get_values::<f32>(obj, "ImagePositionPatient")
+                .and_then(|v| match v[..] { [x, y, z] => Some((x, y, z)), _ => None })
+                .ok_or(MissingAttribute("ImagePositionPatient"))?,
+            image_orientation_patient: get_values::<f32>(obj, "ImageOrientationPatient")
+                .and_then(|v| match v[..] { [a, b, c, d, e, f] => Some((a, b, c, d, e, f)), _ => None })
+                .ok_or(MissingAttribute("ImageOrientationPatient"))?,
+            grid_frame_offset_vector: get_values::<f64>(obj, "GridFrameOffsetVector"),
+            // Required with the pixel data, which Rows and Columns describe; a default would be
+            // off by orders of magnitude
+            dose_grid_scaling: get_value::<f64>(obj, "DoseGridScaling")
+                .ok_or(MissingAttribute("DoseGridScaling"))?,
+            bits_allocated: get_value::<u16>(obj, "BitsAllocated")
+                .ok_or(MissingAttribute("BitsAllocated"))?,
+            bits_stored: get_value::<u16>(obj, "BitsStored")
+                .ok_or(MissingAttribute("BitsStored"))?,
+            high_bit: get_value::<u16>(obj, "HighBit").ok_or(MissingAttribute("HighBit"))?,
+            pixel_representation: get_value::<u16>(obj, "PixelRepresentation")
+                .ok_or(MissingAttribute("PixelRepresentation"))?,
+            transfer_syntax_uid: transfer_syntax_uid(obj),
+            pixel_data: read_pixel_data(obj)?, // Decoded to native samples; empty for header-only reads
+        })
+    }
+
+    // Whether the dose values are absolute (Gy), as opposed to relative to an unstated reference
+    pub fn is_absolute(&self) -> bool {
+        self.dose_units.trim().eq_ignore_ascii_case("GY")
+    }
+
+    /// Positions of the frames along the slice normal, relative to ImagePositionPatient (mm).
+    ///
+    /// GridFrameOffsetVector holds offsets relative to ImagePositionPatient when its first
+    /// value is zero; otherwise the values are positions along the normal, which are made
+    /// relative to the plane of ImagePositionPatient.
+    ///
+    /// # Errors
+    /// Returns an error if a multi-frame grid lacks GridFrameOffsetVector, or if its length
+    /// differs from NumberOfFrames.
+    pub fn frame_offsets(&self) -> Result<Vec<f64>> {
+        let frames = self.number_of_frames.max(1) as usize;
+        let offsets = match &self.grid_frame_offset_vector {
+            Some(offsets) => offsets.clone(),
+            None if frames == 1 => return Ok(vec![0.0]),
+            None => return Err(MissingAttribute("GridFrameOffsetVector").into()),
+        };
+        if offsets.len() != frames {
+            return Err(anyhow!(
+                "GridFrameOffsetVector holds {} offsets for {} frames",
+                offsets.len(),
+                frames
+            ));
+        }
+        if offsets[0] == 0.0 {
+            return Ok(offsets);
+        }
+        // Positions along the normal: relative to the plane of ImagePositionPatient
+        let orientation = SliceOrientation::from_iop(self.image_orientation_patient)?;
+        let (x, y, z) = self.image_position_patient;
+        let origin = orientation.project(&[x as f64, y as f64, z as f64]);
+        Ok(offsets.iter().map(|offset| offset - origin).collect())
+    }
+
+    // Dose values of all frames, frame by frame, in DoseUnits
+    pub fn get_dose_values(&self) -> Result<Vec<f32>> {
+        let stored = stored_values(
+            &self.pixel_data,
+            self.bits_allocated,
+            self.bits_stored,
+            self.high_bit,
+            self.pixel_representation,
+            is_big_endian(&self.transfer_syntax_uid),
+        )
+        .context("Failed to process dose grid pixel data")?;
+        Ok(stored
+            .into_iter()
+            .map(|v| (v as f64 * self.dose_grid_scaling) as f32)
+            .collect())
+    }
+
+    /// Builds the dose grid as a volume in DoseUnits (Gy for absolute doses) placed in patient
+    /// space by its own geometry.
+    ///
+    /// Frames are stacked in order of increasing offset along the normal. Grids with
+    /// non-uniform frame spacing are resampled onto their smallest spacing, so that the volume
+    /// has a regular grid. Use `Volume::resample_onto` to bring the dose onto a CT grid.
+    ///
+    /// # Errors
+    /// Returns an error if the pixel data does not hold every frame (e.g. a header-only read),
+    /// or if the frame offsets or orientation are invalid.
+    pub fn to_volume(&self) -> Result<DoseVolume> {
+        let (rows, columns) = (self.rows as usize, self.columns as usize);
+        let frame_len = rows * columns;
+        let offsets = self.frame_offsets()?;
+        let values = self.get_dose_values()?;
+        if values.len() != frame_len * offsets.len() {
+            return Err(anyhow!(
+                "Dose grid {} holds {} values, expected {} frames of {}x{}",
+                self.uid,
+                values.len(),
+                offsets.len(),
+                rows,
+                columns
+            ));
+        }
+
+        // Frames in order of increasing offset
+        let mut order: Vec<usize> = (0..offsets.len()).collect();
+        order.sort_by(|&a, &b| offsets[a].total_cmp(&offsets[b]));
+        let positions: Vec<f64> = order.iter().map(|&i| offsets[i]).collect();
+        if positions.windows(2).any(|w| w[1] - w[0] <= DUPLICATE_TOLERANCE) {
+            return Err(anyhow!("Dose grid {} has frames at the same offset", self.uid));
+        }
+        let frames: Vec<Vec<f32>> = order
+            .iter()
+            .map(|&i| values[i * frame_len..(i + 1) * frame_len].to_vec())
+            .collect();
+
+        let spacings: Vec<f64> = positions.windows(2).map(|w| w[1] - w[0]).collect();
+        let min_spacing = spacings.iter().cloned().reduce(f64::min);
+        let max_spacing = spacings.iter().cloned().reduce(f64::max);
+        let (voxel_data, slices, slice_spacing) = match (min_spacing, max_spacing) {
+            (Some(min), Some(max)) if max - min > GRID_SPACING_TOLERANCE * min => {
+                let (data, count) = resample_slices(&frames, &positions, min);
+                (data, count, min)
+            }
+            (Some(_), Some(_)) => {
+                let spacing = (positions[positions.len() - 1] - positions[0]) / (positions.len() - 1) as f64;
+                (frames.concat(), positions.len(), spacing)
+            }
+            _ => (frames.concat(), 1, self.slice_thickness.map_or(1.0, |t| t as f64)),
+        };
+
+        // The first stacked frame lies `positions[0]` along the normal from ImagePositionPatient
+        let orientation = SliceOrientation::from_iop(self.image_orientation_patient)?;
+        let (x, y, z) = self.image_position_patient;
+        let origin = [
+            x as f64 + positions[0] * orientation.normal[0],
+            y as f64 + positions[0] * orientation.normal[1],
+            z as f64 + positions[0] * orientation.normal[2],
+        ];
+        let to_f32 = |v: [f64; 3]| (v[0] as f32, v[1] as f32, v[2] as f32);
+        let voxel_spacing = (self.pixel_spacing.0, self.pixel_spacing.1, slice_spacing as f32);
+        DoseVolume::from_parts(
+            (rows, columns, slices),
+            voxel_spacing,
+            index_to_patient_matrix(
+                to_f32(origin),
+                to_f32(orientation.row),
+                to_f32(orientation.column),
+                to_f32(orientation.normal),
+                voxel_spacing,
+            ),
+            voxel_data,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::test_util::test_rt_dose;
+
+    fn dose(offsets: Vec<f64>, stored: &[u16]) -> RTDose {
+        RTDose {
+            number_of_frames: offsets.len() as u32,
+            grid_frame_offset_vector: Some(offsets),
+            pixel_data: stored.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ..test_rt_dose()
+        }
+    }
+
+    #[test]
+    fn test_volume_in_gy_with_affine() {
+        // Offsets given as absolute z positions, frames stored from top to bottom
+        let grid = dose(vec![-1.0, -3.0, -5.0], &[4, 6, 2, 4, 0, 2]);
+        assert_eq!(grid.frame_offsets().unwrap(), vec![4.0, 2.0, 0.0]);
+
+        let volume = grid.to_volume().unwrap();
+        assert_eq!(volume.dimensions(), (1, 2, 3));
+        assert_eq!(volume.voxel_spacing(), (2.0, 3.0, 2.0));
+        assert_eq!(volume.voxel_data(), &[0.0, 1.0, 1.0, 2.0, 2.0, 3.0]);
+        assert_eq!(volume.voxel_to_world([1.0, 0.0, 2.0]), [13.0, 20.0, -1.0]);
+    }
+
+    #[test]
+    fn test_dose_grid_scaling_is_required() {
+        use crate::dicom::test_util::{item, text};
+        use dicom_core::{Tag, VR};
+        use dicom_object::meta::FileMetaTableBuilder;
+
+        let header = |scaling: Option<&str>| {
+            let mut elements = vec![
+                text(Tag(0x0008, 0x0018), VR::UI, "9.3"),
+                text(Tag(0x0008, 0x0060), VR::CS, "RTDOSE"),
+                text(Tag(0x0020, 0x000D), VR::UI, "1.2"),
+                text(Tag(0x0020, 0x000E), VR::UI, "9.4"),
+                text(Tag(0x0020, 0x0032), VR::DS, "10\\20\\-5"),
+                text(Tag(0x0020, 0x0037), VR::DS, "1\\0\\0\\0\\1\\0"),
+                text(Tag(0x0028, 0x0010), VR::US, "1"),
+                text(Tag(0x0028, 0x0011), VR::US, "2"),
+                text(Tag(0x0028, 0x0030), VR::DS, "2\\3"),
+                text(Tag(0x0028, 0x0100), VR::US, "16"),
+                text(Tag(0x0028, 0x0101), VR::US, "16"),
+                text(Tag(0x0028, 0x0102), VR::US, "15"),
+                text(Tag(0x0028, 0x0103), VR::US, "0"),
+                text(Tag(0x3004, 0x0002), VR::CS, "GY"),
+                text(Tag(0x3004, 0x0004), VR::CS, "PHYSICAL"),
+                text(Tag(0x3004, 0x000A), VR::CS, "PLAN"),
+            ];
+            if let Some(scaling) = scaling {
+                elements.push(text(Tag(0x3004, 0x000E), VR::DS, scaling));
+            }
+            item(elements)
+                .with_meta(
+                    FileMetaTableBuilder::new()
+                        .transfer_syntax("1.2.840.10008.1.2.1")
+                        .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.481.2")
+                        .media_storage_sop_instance_uid("9.3"),
+                )
+                .unwrap()
+        };
+
+        let err = RTDose::from_object(&header(None)).unwrap_err();
+        assert_eq!(err.downcast_ref::<MissingAttribute>(), Some(&MissingAttribute("DoseGridScaling")));
+        assert_eq!(RTDose::from_object(&header(Some("0.001"))).unwrap().dose_grid_scaling, 0.001);
+    }
+
+    #[test]
+    fn test_non_uniform_grid_is_resampled() {
+        let grid = dose(vec![0.0, 1.0, 3.0], &[0, 0, 2, 2, 6, 6]);
+        let volume = grid.to_volume().unwrap();
+        assert_eq!(volume.dimensions(), (1, 2, 4));
+        assert_eq!(volume.voxel_data(), &[0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0]);
+
+        // Header-only grids cannot be stacked
+        let mut header = grid;
+        header.pixel_data.clear();
+        assert!(header.to_volume().is_err());
+    }
+}
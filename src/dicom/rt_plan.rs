@@ -0,0 +1,665 @@
+use super::dicom_helper::{get_first_item, get_items, get_value, get_values, MissingAttribute};
+use super::series_geometry::Vec3;
+use anyhow::{anyhow, Context, Result};
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use std::collections::HashMap;
+
+pub const RTPLAN_MODALITY: &str = "RTPLAN";
+
+// Source-axis distance assumed when a beam does not give one (mm)
+const DEFAULT_SOURCE_AXIS_DISTANCE: f64 = 1000.0;
+
+// A dose reference of the plan, e.g. the prescription to a target
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DoseReference {
+    pub number: i32,                           // (300A,0012) DoseReferenceNumber
+    pub structure_type: Option<String>,        // (300A,0014) DoseReferenceStructureType, e.g. POINT, VOLUME
+    pub description: Option<String>,           // (300A,0016) DoseReferenceDescription
+    pub reference_type: Option<String>,        // (300A,0020) DoseReferenceType: TARGET or ORGAN_AT_RISK
+    pub referenced_roi_number: Option<i32>,    // (3006,0084) ReferencedROINumber
+    pub point: Option<Vec3>,                   // (300A,0018) DoseReferencePointCoordinates (mm)
+    pub target_prescription_dose: Option<f64>, // (300A,0026) TargetPrescriptionDose (Gy)
+}
+
+// A beam delivered in a fraction group
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReferencedBeam {
+    pub beam_number: i32,           // (300C,0006) ReferencedBeamNumber
+    pub beam_dose: Option<f64>,     // (300A,0084) BeamDose at the dose specification point (Gy)
+    pub beam_meterset: Option<f64>, // (300A,0086) BeamMeterset (MU)
+}
+
+// A fraction group: the beams or brachytherapy setups delivered together each fraction
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FractionGroup {
+    pub number: i32,                         // (300A,0071) FractionGroupNumber
+    pub fractions_planned: Option<i32>,      // (300A,0078) NumberOfFractionsPlanned
+    pub beams: Vec<ReferencedBeam>,          // (300C,0004) ReferencedBeamSequence
+    pub brachy_application_setups: Vec<i32>, // ReferencedBrachyApplicationSetupNumber of each setup
+}
+
+// A beam limiting device (jaws or MLC) of a beam
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BeamLimitingDevice {
+    pub device_type: String,               // (300A,00B8) RTBeamLimitingDeviceType, e.g. ASYMX, MLCX
+    pub number_of_pairs: usize,            // (300A,00BC) NumberOfLeafJawPairs
+    pub leaf_boundaries: Option<Vec<f64>>, // (300A,00BE) LeafPositionBoundaries (mm), MLC only
+}
+
+// Positions of the leaves or jaws of a device at a control point
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DevicePosition {
+    pub device_type: String, // (300A,00B8) RTBeamLimitingDeviceType
+    pub positions: Vec<f64>, // (300A,011C) LeafJawPositions (mm): first bank, then second bank
+}
+
+/// A control point of a beam.
+///
+/// DICOM only repeats the attributes that change from the previous control point; the values
+/// here are resolved, i.e. carried over from earlier control points when absent.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ControlPoint {
+    pub index: i32,                                // (300A,0112) ControlPointIndex
+    pub cumulative_meterset_weight: Option<f64>,   // (300A,0134) CumulativeMetersetWeight
+    pub nominal_beam_energy: Option<f64>,          // (300A,0114) NominalBeamEnergy (MeV)
+    pub gantry_angle: Option<f64>,                 // (300A,011E) GantryAngle (degrees)
+    pub gantry_rotation_direction: Option<String>, // (300A,011F) GantryRotationDirection: CW, CC or NONE
+    pub collimator_angle: Option<f64>,             // (300A,0120) BeamLimitingDeviceAngle (degrees)
+    pub couch_angle: Option<f64>,                  // (300A,0122) PatientSupportAngle (degrees)
+    pub isocenter: Option<Vec3>,                   // (300A,012C) IsocenterPosition (mm)
+    pub device_positions: Vec<DevicePosition>,     // (300A,011A) BeamLimitingDevicePositionSequence
+}
+
+impl ControlPoint {
+    // Leaf or jaw positions of a device, e.g. "MLCX" or "ASYMY"
+    pub fn positions(&self, device_type: &str) -> Option<&[f64]> {
+        self.device_positions
+            .iter()
+            .find(|device| device.device_type == device_type)
+            .map(|device| device.positions.as_slice())
+    }
+}
+
+// A treatment beam with its control points
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Beam {
+    pub number: i32,                                   // (300A,00C0) BeamNumber
+    pub name: Option<String>,                          // (300A,00C2) BeamName
+    pub description: Option<String>,                   // (300A,00C3) BeamDescription
+    pub beam_type: Option<String>,                     // (300A,00C4) BeamType: STATIC or DYNAMIC
+    pub radiation_type: Option<String>,                // (300A,00C6) RadiationType, e.g. PHOTON
+    pub treatment_delivery_type: Option<String>,       // (300A,00CE) TreatmentDeliveryType, e.g. TREATMENT, SETUP
+    pub treatment_machine_name: Option<String>,        // (300A,00B2) TreatmentMachineName
+    pub source_axis_distance: Option<f64>,             // (300A,00B4) SourceAxisDistance (mm)
+    pub patient_position: Option<String>,              // (0018,5100) PatientPosition of the referenced patient setup
+    pub final_cumulative_meterset_weight: Option<f64>, // (300A,010E) FinalCumulativeMetersetWeight
+    pub limiting_devices: Vec<BeamLimitingDevice>,     // (300A,00B6) BeamLimitingDeviceSequence
+    pub control_points: Vec<ControlPoint>,             // Ordered by ControlPointIndex
+}
+
+impl Beam {
+    // Isocenter of the first control point (mm)
+    pub fn isocenter(&self) -> Option<Vec3> {
+        self.control_points.first()?.isocenter
+    }
+
+    // The multileaf collimator of the beam, if any
+    pub fn mlc(&self) -> Option<&BeamLimitingDevice> {
+        self.limiting_devices
+            .iter()
+            .find(|device| device.device_type.starts_with("MLC"))
+    }
+
+    /// Central axis of the beam at a control point, as the source and isocenter positions in
+    /// patient coordinates (mm).
+    ///
+    /// The gantry and couch angles follow IEC 61217 and are mapped to patient coordinates for
+    /// the patient position of the beam (HFS when not given). Returns None for other patient
+    /// positions or when the control point has no isocenter or gantry angle.
+    pub fn central_axis(&self, control_point: usize) -> Option<(Vec3, Vec3)> {
+        let point = self.control_points.get(control_point)?;
+        let isocenter = point.isocenter?;
+        let gantry = point.gantry_angle?.to_radians();
+        let couch = point.couch_angle.unwrap_or(0.0).to_radians();
+        // Source direction in the patient support system: the fixed system rotated by the couch
+        let (x, y, z) = (gantry.sin() * couch.cos(), -gantry.sin() * couch.sin(), gantry.cos());
+        let direction = match self.patient_position.as_deref().unwrap_or("HFS") {
+            "HFS" => [x, -z, y],
+            "HFP" => [-x, z, y],
+            "FFS" => [-x, -z, -y],
+            "FFP" => [x, z, -y],
+            _ => return None,
+        };
+        let distance = self.source_axis_distance.unwrap_or(DEFAULT_SOURCE_AXIS_DISTANCE);
+        let source = [0, 1, 2].map(|axis| isocenter[axis] + distance * direction[axis]);
+        Some((source, isocenter))
+    }
+}
+
+// A dwell position of a brachytherapy channel
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BrachyControlPoint {
+    pub index: i32,                          // (300A,0112) ControlPointIndex
+    pub relative_position: Option<f64>,      // (300A,02D2) ControlPointRelativePosition along the channel (mm)
+    pub position: Option<Vec3>,              // (300A,02D4) ControlPoint3DPosition (mm)
+    pub cumulative_time_weight: Option<f64>, // (300A,02D6) CumulativeTimeWeight
+}
+
+// A channel (catheter or applicator tube) of a brachytherapy application setup
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BrachyChannel {
+    pub number: i32,                           // (300A,0282) ChannelNumber
+    pub length: Option<f64>,                   // (300A,0284) ChannelLength (mm)
+    pub total_time: Option<f64>,               // (300A,0286) ChannelTotalTime (s)
+    pub source_movement_type: Option<String>,  // (300A,0288) SourceMovementType, e.g. STEPWISE
+    pub referenced_source_number: Option<i32>, // (300C,000E) ReferencedSourceNumber
+    pub control_points: Vec<BrachyControlPoint>,
+}
+
+// A brachytherapy application setup with its channels
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BrachyApplicationSetup {
+    pub number: i32,                // (300A,0234) ApplicationSetupNumber
+    pub setup_type: Option<String>, // (300A,0232) ApplicationSetupType, e.g. INTRACAVITARY
+    pub name: Option<String>,       // (300A,0236) ApplicationSetupName
+    pub channels: Vec<BrachyChannel>,
+}
+
+// An RT Plan: prescription, fraction groups and external beams or brachytherapy setups
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RTPlan {
+    pub uid: String,                                            // (0008,0018) SOPInstanceUID
+    pub series_uid: String,                                     // (0020,000E) SeriesInstanceUID of the RTPLAN series
+    pub study_uid: String,                                      // (0020,000D) StudyInstanceUID
+    pub label: String,                                          // (300A,0002) RTPlanLabel
+    pub name: Option<String>,                                   // (300A,0003) RTPlanName
+    pub date: Option<String>,                                   // (300A,0006) RTPlanDate
+    pub geometry: Option<String>,                               // (300A,000C) RTPlanGeometry: PATIENT or TREATMENT_DEVICE
+    pub frame_of_reference_uid: Option<String>,                 // (0020,0052) FrameOfReferenceUID
+    pub referenced_structure_set_uid: Option<String>,           // Structure set the plan was made on, if given
+    pub dose_references: Vec<DoseReference>,                    // Ordered by DoseReferenceNumber
+    pub fraction_groups: Vec<FractionGroup>,                    // Ordered by FractionGroupNumber
+    pub beams: Vec<Beam>,                                       // Ordered by BeamNumber
+    pub brachy_application_setups: Vec<BrachyApplicationSetup>, // Ordered by ApplicationSetupNumber
+}
+
+impl RTPlan {
+    // Function to parse the DICOM file and generate the RTPlan structure
+    pub fn from_bytes(dicom_data: &[u8]) -> Result<RTPlan> {
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
+
+    // Function to generate the RTPlan structure from an already parsed DICOM file
+    pub fn from_object(obj: &InMemDicomObject) -> Result<RTPlan> {
+        let modality = get_value::<String>(obj, "Modality").ok_or(MissingAttribute("Modality"))?;
+        if modality != RTPLAN_MODALITY {
+            return Err(anyhow!("Expected modality {}, found {}", RTPLAN_MODALITY, modality));
+        }
+        let uid = get_value::<String>(obj, "SOPInstanceUID").ok_or(MissingAttribute("SOPInstanceUID"))?;
+        let series_uid =
+            get_value::<String>(obj, "SeriesInstanceUID").ok_or(MissingAttribute("SeriesInstanceUID"))?;
+        let study_uid =
+            get_value::<String>(obj, "StudyInstanceUID").ok_or(MissingAttribute("StudyInstanceUID"))?;
+        let label = get_value::<String>(obj, "RTPlanLabel").ok_or(MissingAttribute("RTPlanLabel"))?;
+
+        // Patient positions of the setups, looked up by the beams
+        let patient_positions: HashMap<i32, String> = get_items(obj, "PatientSetupSequence")
+            .iter()
+            .filter_map(|item| {
+                Some((
+                    get_value::<i32>(item, "PatientSetupNumber")?,
+                    get_value::<String>(item, "PatientPosition").filter(|v| !v.is_empty())?,
+                ))
+            })
+            .collect();
+
+        let mut dose_references: Vec<DoseReference> = get_items(obj, "DoseReferenceSequence")
+            .iter()
+            .map(parse_dose_reference)
+            .collect::<Result<_>>()?;
+        dose_references.sort_by_key(|reference| reference.number);
+        let mut fraction_groups: Vec<FractionGroup> = get_items(obj, "FractionGroupSequence")
+            .iter()
+            .map(parse_fraction_group)
+            .collect::<Result<_>>()?;
+        fraction_groups.sort_by_key(|group| group.number);
+        let mut beams: Vec<Beam> = get_items(obj, "BeamSequence")
+            .iter()
+            .map(|item| parse_beam(item, &patient_positions))
+            .collect::<Result<_>>()?;
+        beams.sort_by_key(|beam| beam.number);
+        let mut brachy_application_setups: Vec<BrachyApplicationSetup> = get_items(obj, "ApplicationSetupSequence")
+            .iter()
+            .map(parse_application_setup)
+            .collect::<Result<_>>()?;
+        brachy_application_setups.sort_by_key(|setup| setup.number);
+
+        Ok(RTPlan {
+            uid,
+            series_uid,
+            study_uid,
+            label,
+            name: get_value::<String>(obj, "RTPlanName"),
+            date: get_value::<String>(obj, "RTPlanDate").filter(|v| !v.is_empty()),
+            geometry: get_value::<String>(obj, "RTPlanGeometry"),
+            frame_of_reference_uid: get_value::<String>(obj, "FrameOfReferenceUID"),
+            referenced_structure_set_uid: get_first_item(obj, "ReferencedStructureSetSequence")
+                .and_then(|item| get_value::<String>(item, "ReferencedSOPInstanceUID")),
+            dose_references,
+            fraction_groups,
+            beams,
+            brachy_application_setups,
+        })
+    }
+
+    // Query a beam by its BeamNumber
+    pub fn beam(&self, number: i32) -> Option<&Beam> {
+        self.beams.iter().find(|beam| beam.number == number)
+    }
+
+    // Highest prescription dose to a target (Gy), if the plan prescribes one
+    pub fn prescription_dose(&self) -> Option<f64> {
+        self.dose_references
+            .iter()
+            .filter(|reference| reference.reference_type.as_deref() != Some("ORGAN_AT_RISK"))
+            .filter_map(|reference| reference.target_prescription_dose)
+            .reduce(f64::max)
+    }
+
+    // Distinct isocenters of the treatment beams (mm), in beam order
+    pub fn isocenters(&self) -> Vec<Vec3> {
+        let mut isocenters: Vec<Vec3> = Vec::new();
+        for isocenter in self.beams.iter().filter_map(|beam| beam.isocenter()) {
+            if !isocenters.contains(&isocenter) {
+                isocenters.push(isocenter);
+            }
+        }
+        isocenters
+    }
+}
+
+// Result of checking that a plan shares the frame of reference of its images, structures and
+// doses
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlanConsistencyReport {
+    pub plan_uid: String,
+    pub frame_of_reference_uid: Option<String>,
+    pub image_series: Vec<String>,                        // Series sharing the frame of reference of the plan
+    pub missing_structure_set: Option<String>,            // Referenced structure set that is not loaded
+    pub structure_set_frame_of_reference: Option<String>, // Frame of reference of the structure set when it differs
+    pub mismatched_doses: Vec<String>,                    // Doses of the plan in another frame of reference
+    pub isocenters_outside_images: Vec<i32>,              // Beams whose isocenter lies outside the images (BeamNumber)
+}
+
+impl PlanConsistencyReport {
+    // Whether the plan can be overlaid on its images as is, with the structures it was made on
+    pub fn is_consistent(&self) -> bool {
+        !self.image_series.is_empty()
+            && self.missing_structure_set.is_none()
+            && self.structure_set_frame_of_reference.is_none()
+            && self.mismatched_doses.is_empty()
+            && self.isocenters_outside_images.is_empty()
+    }
+}
+
+fn parse_vec3(item: &InMemDicomObject, tag: &str) -> Option<Vec3> {
+    get_values::<f64>(item, tag).and_then(|v| match v[..] {
+        [x, y, z] => Some([x, y, z]),
+        _ => None,
+    })
+}
+
+fn parse_dose_reference(item: &InMemDicomObject) -> Result<DoseReference> {
+    Ok(DoseReference {
+        number: get_value::<i32>(item, "DoseReferenceNumber")
+            .ok_or(MissingAttribute("DoseReferenceNumber"))?,
+        structure_type: get_value::<String>(item, "DoseReferenceStructureType"),
+        description: get_value::<String>(item, "DoseReferenceDescription"),
+        reference_type: get_value::<String>(item, "DoseReferenceType"),
+        referenced_roi_number: get_value::<i32>(item, "ReferencedROINumber"),
+        point: parse_vec3(item, "DoseReferencePointCoordinates"),
+        target_prescription_dose: get_value::<f64>(item, "TargetPrescriptionDose"),
+    })
+}
+
+fn parse_fraction_group(item: &InMemDicomObject) -> Result<FractionGroup> {
+    let beams = get_items(item, "ReferencedBeamSequence")
+        .iter()
+        .map(|beam| {
+            Ok(ReferencedBeam {
+                beam_number: get_value::<i32>(beam, "ReferencedBeamNumber")
+                    .ok_or(MissingAttribute("ReferencedBeamNumber"))?,
+                beam_dose: get_value::<f64>(beam, "BeamDose"),
+                beam_meterset: get_value::<f64>(beam, "BeamMeterset"),
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(FractionGroup {
+        number: get_value::<i32>(item, "FractionGroupNumber")
+            .ok_or(MissingAttribute("FractionGroupNumber"))?,
+        fractions_planned: get_value::<i32>(item, "NumberOfFractionsPlanned"),
+        beams,
+        brachy_application_setups: get_items(item, "ReferencedBrachyApplicationSetupSequence")
+            .iter()
+            .filter_map(|setup| get_value::<i32>(setup, "ReferencedBrachyApplicationSetupNumber"))
+            .collect(),
+    })
+}
+
+fn parse_beam(item: &InMemDicomObject, patient_positions: &HashMap<i32, String>) -> Result<Beam> {
+    let number = get_value::<i32>(item, "BeamNumber").ok_or(MissingAttribute("BeamNumber"))?;
+    let limiting_devices = get_items(item, "BeamLimitingDeviceSequence")
+        .iter()
+        .map(|device| {
+            Ok(BeamLimitingDevice {
+                device_type: get_value::<String>(device, "RTBeamLimitingDeviceType")
+                    .ok_or(MissingAttribute("RTBeamLimitingDeviceType"))?,
+                number_of_pairs: get_value::<usize>(device, "NumberOfLeafJawPairs")
+                    .ok_or(MissingAttribute("NumberOfLeafJawPairs"))?,
+                leaf_boundaries: get_values::<f64>(device, "LeafPositionBoundaries"),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    // Later control points only hold what changed, so each one starts from its predecessor in
+    // ControlPointIndex order, whatever the order of the sequence
+    let mut points: Vec<(i32, &InMemDicomObject)> = get_items(item, "ControlPointSequence")
+        .iter()
+        .map(|point| {
+            let index = get_value::<i32>(point, "ControlPointIndex")
+                .ok_or(MissingAttribute("ControlPointIndex"))
+                .with_context(|| format!("Beam {}", number))?;
+            Ok((index, point))
+        })
+        .collect::<Result<_>>()?;
+    points.sort_by_key(|(index, _)| *index);
+    let mut control_points: Vec<ControlPoint> = Vec::new();
+    for (index, point) in points {
+        let previous = control_points.last();
+        let inherited = |value: Option<f64>, field: fn(&ControlPoint) -> Option<f64>| value.or(previous.and_then(field));
+        let mut device_positions = previous.map(|p| p.device_positions.clone()).unwrap_or_default();
+        for device in get_items(point, "BeamLimitingDevicePositionSequence") {
+            let device_type = get_value::<String>(device, "RTBeamLimitingDeviceType")
+                .ok_or(MissingAttribute("RTBeamLimitingDeviceType"))?;
+            let positions = get_values::<f64>(device, "LeafJawPositions")
+                .ok_or(MissingAttribute("LeafJawPositions"))?;
+            match device_positions.iter_mut().find(|p| p.device_type == device_type) {
+                Some(existing) => existing.positions = positions,
+                None => device_positions.push(DevicePosition { device_type, positions }),
+            }
+        }
+        control_points.push(ControlPoint {
+            index,
+            cumulative_meterset_weight: get_value::<f64>(point, "CumulativeMetersetWeight"),
+            nominal_beam_energy: inherited(get_value::<f64>(point, "NominalBeamEnergy"), |p| p.nominal_beam_energy),
+            gantry_angle: inherited(get_value::<f64>(point, "GantryAngle"), |p| p.gantry_angle),
+            gantry_rotation_direction: get_value::<String>(point, "GantryRotationDirection")
+                .or_else(|| previous.and_then(|p| p.gantry_rotation_direction.clone())),
+            collimator_angle: inherited(get_value::<f64>(point, "BeamLimitingDeviceAngle"), |p| p.collimator_angle),
+            couch_angle: inherited(get_value::<f64>(point, "PatientSupportAngle"), |p| p.couch_angle),
+            isocenter: parse_vec3(point, "IsocenterPosition").or(previous.and_then(|p| p.isocenter)),
+            device_positions,
+        });
+    }
+
+    Ok(Beam {
+        number,
+        name: get_value::<String>(item, "BeamName"),
+        description: get_value::<String>(item, "BeamDescription"),
+        beam_type: get_value::<String>(item, "BeamType"),
+        radiation_type: get_value::<String>(item, "RadiationType"),
+        treatment_delivery_type: get_value::<String>(item, "TreatmentDeliveryType"),
+        treatment_machine_name: get_value::<String>(item, "TreatmentMachineName"),
+        source_axis_distance: get_value::<f64>(item, "SourceAxisDistance"),
+        patient_position: get_value::<i32>(item, "ReferencedPatientSetupNumber")
+            .and_then(|setup| patient_positions.get(&setup).cloned()),
+        final_cumulative_meterset_weight: get_value::<f64>(item, "FinalCumulativeMetersetWeight"),
+        limiting_devices,
+        control_points,
+    })
+}
+
+fn parse_application_setup(item: &InMemDicomObject) -> Result<BrachyApplicationSetup> {
+    let channels = get_items(item, "ChannelSequence")
+        .iter()
+        .map(|channel| {
+            let number =
+                get_value::<i32>(channel, "ChannelNumber").ok_or(MissingAttribute("ChannelNumber"))?;
+            let mut control_points: Vec<BrachyControlPoint> = get_items(channel, "BrachyControlPointSequence")
+                .iter()
+                .map(|point| {
+                    Ok(BrachyControlPoint {
+                        index: get_value::<i32>(point, "ControlPointIndex")
+                            .ok_or(MissingAttribute("ControlPointIndex"))
+                            .with_context(|| format!("Channel {}", number))?,
+                        relative_position: get_value::<f64>(point, "ControlPointRelativePosition"),
+                        position: parse_vec3(point, "ControlPoint3DPosition"),
+                        cumulative_time_weight: get_value::<f64>(point, "CumulativeTimeWeight"),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            control_points.sort_by_key(|point| point.index);
+            Ok(BrachyChannel {
+                number,
+                length: get_value::<f64>(channel, "ChannelLength"),
+                total_time: get_value::<f64>(channel, "ChannelTotalTime"),
+                source_movement_type: get_value::<String>(channel, "SourceMovementType"),
+                referenced_source_number: get_value::<i32>(channel, "ReferencedSourceNumber"),
+                control_points,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(BrachyApplicationSetup {
+        number: get_value::<i32>(item, "ApplicationSetupNumber")
+            .ok_or(MissingAttribute("ApplicationSetupNumber"))?,
+        setup_type: get_value::<String>(item, "ApplicationSetupType"),
+        name: get_value::<String>(item, "ApplicationSetupName"),
+        channels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::{DicomRepo, ImageSeries, StructureSet};
+    use crate::dicom::test_util::{item, sequence, text};
+    use dicom_core::{Tag, VR};
+
+    #[test]
+    fn test_beams_and_control_points() {
+        let mlc = |positions: &str| {
+            sequence(
+                Tag(0x300A, 0x011A),
+                vec![item(vec![
+                    text(Tag(0x300A, 0x00B8), VR::CS, "MLCX"),
+                    text(Tag(0x300A, 0x011C), VR::DS, positions),
+                ])],
+            )
+        };
+        let beam = item(vec![
+            text(Tag(0x300A, 0x00C0), VR::IS, "1"),
+            text(Tag(0x300A, 0x00C2), VR::LO, "Arc"),
+            text(Tag(0x300A, 0x00B4), VR::DS, "1000"),
+            text(Tag(0x300C, 0x006A), VR::IS, "1"),
+            sequence(
+                Tag(0x300A, 0x00B6),
+                vec![item(vec![
+                    text(Tag(0x300A, 0x00B8), VR::CS, "MLCX"),
+                    text(Tag(0x300A, 0x00BC), VR::IS, "2"),
+                    text(Tag(0x300A, 0x00BE), VR::DS, "-10\\0\\10"),
+                ])],
+            ),
+            sequence(
+                Tag(0x300A, 0x0111),
+                // Stored out of order: the second control point inherits from the first by index
+                vec![
+                    // Only the gantry angle and leaves move
+                    item(vec![
+                        text(Tag(0x300A, 0x0112), VR::IS, "1"),
+                        text(Tag(0x300A, 0x011E), VR::DS, "90"),
+                        mlc("-4\\-6\\4\\6"),
+                    ]),
+                    item(vec![
+                        text(Tag(0x300A, 0x0112), VR::IS, "0"),
+                        text(Tag(0x300A, 0x011E), VR::DS, "0"),
+                        text(Tag(0x300A, 0x0120), VR::DS, "30"),
+                        text(Tag(0x300A, 0x0122), VR::DS, "0"),
+                        text(Tag(0x300A, 0x012C), VR::DS, "10\\20\\30"),
+                        mlc("-5\\-5\\5\\5"),
+                    ]),
+                ],
+            ),
+        ]);
+        let obj = item(vec![
+            text(Tag(0x0008, 0x0018), VR::UI, "9.3"),
+            text(Tag(0x0020, 0x000D), VR::UI, "1.2"),
+            text(Tag(0x0020, 0x000E), VR::UI, "9.4"),
+            text(Tag(0x0008, 0x0060), VR::CS, "RTPLAN"),
+            text(Tag(0x0020, 0x0052), VR::UI, "1.2.9"),
+            text(Tag(0x300A, 0x0002), VR::SH, "Prostate"),
+            sequence(
+                Tag(0x300C, 0x0060),
+                vec![item(vec![text(Tag(0x0008, 0x1155), VR::UI, "9.1")])],
+            ),
+            sequence(
+                Tag(0x300A, 0x0010),
+                vec![item(vec![
+                    text(Tag(0x300A, 0x0012), VR::IS, "1"),
+                    text(Tag(0x300A, 0x0020), VR::CS, "TARGET"),
+                    text(Tag(0x300A, 0x0026), VR::DS, "78"),
+                ])],
+            ),
+            sequence(
+                Tag(0x300A, 0x0070),
+                vec![item(vec![
+                    text(Tag(0x300A, 0x0071), VR::IS, "1"),
+                    text(Tag(0x300A, 0x0078), VR::IS, "39"),
+                    sequence(
+                        Tag(0x300C, 0x0004),
+                        vec![item(vec![
+                            text(Tag(0x300C, 0x0006), VR::IS, "1"),
+                            text(Tag(0x300A, 0x0086), VR::DS, "250.5"),
+                        ])],
+                    ),
+                ])],
+            ),
+            sequence(
+                Tag(0x300A, 0x0180),
+                vec![item(vec![
+                    text(Tag(0x300A, 0x0182), VR::IS, "1"),
+                    text(Tag(0x0018, 0x5100), VR::CS, "HFS"),
+                ])],
+            ),
+            sequence(Tag(0x300A, 0x00B0), vec![beam]),
+        ]);
+
+        let plan = RTPlan::from_object(&obj).unwrap();
+        assert_eq!(plan.label, "Prostate");
+        assert_eq!(plan.prescription_dose(), Some(78.0));
+        assert_eq!(plan.fraction_groups[0].fractions_planned, Some(39));
+        assert_eq!(plan.fraction_groups[0].beams[0].beam_meterset, Some(250.5));
+
+        let beam = plan.beam(1).unwrap();
+        assert_eq!(beam.patient_position.as_deref(), Some("HFS"));
+        assert_eq!(beam.mlc().unwrap().leaf_boundaries, Some(vec![-10.0, 0.0, 10.0]));
+        let second = &beam.control_points[1];
+        assert_eq!((second.gantry_angle, second.collimator_angle), (Some(90.0), Some(30.0)));
+        assert_eq!(second.isocenter, Some([10.0, 20.0, 30.0]));
+        assert_eq!(second.positions("MLCX"), Some(&[-4.0, -6.0, 4.0, 6.0][..]));
+
+        // Gantry 0 irradiates from anterior, gantry 90 from the patient's left for HFS
+        let (source, _) = beam.central_axis(0).unwrap();
+        assert_eq!(source, [10.0, -980.0, 30.0]);
+        let (source, isocenter) = beam.central_axis(1).unwrap();
+        assert!((source[0] - 1010.0).abs() < 1e-9 && (source[1] - 20.0).abs() < 1e-9);
+        assert_eq!(isocenter, [10.0, 20.0, 30.0]);
+
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ImageSeries::new("7".into(), "1.2".into(), "CT".into(), None, Some("1.2.9".into()), None, None));
+        repo.add_plan(plan);
+        assert_eq!(repo.plans_of("7").len(), 1);
+        let report = repo.plan_consistency("9.3").unwrap();
+        assert_eq!(report.image_series, vec!["7".to_string()]);
+        assert_eq!(report.missing_structure_set.as_deref(), Some("9.1"));
+        assert!(!report.is_consistent());
+
+        // Consistent once the structure set is loaded in the frame of reference of the plan
+        repo.add_structure_set(StructureSet {
+            uid: "9.1".into(),
+            series_uid: "9.2".into(),
+            study_uid: "1.2".into(),
+            label: "RS".into(),
+            name: None,
+            date: None,
+            frame_of_reference_uid: Some("1.2.9".into()),
+            referenced_series_uid: Some("7".into()),
+            rois: Vec::new(),
+        });
+        let report = repo.plan_consistency("9.3").unwrap();
+        assert!(report.missing_structure_set.is_none());
+        assert!(report.is_consistent());
+    }
+
+    #[test]
+    fn test_brachy_application_setups() {
+        let point = |index: &str, relative_position: &str, position: &str, weight: &str| {
+            item(vec![
+                text(Tag(0x300A, 0x0112), VR::IS, index),
+                text(Tag(0x300A, 0x02D2), VR::DS, relative_position),
+                text(Tag(0x300A, 0x02D4), VR::DS, position),
+                text(Tag(0x300A, 0x02D6), VR::DS, weight),
+            ])
+        };
+        let channel = |number: &str, points: Vec<InMemDicomObject>| {
+            item(vec![
+                text(Tag(0x300A, 0x0282), VR::IS, number),
+                text(Tag(0x300A, 0x0284), VR::DS, "1200"),
+                text(Tag(0x300A, 0x0286), VR::DS, "95.5"),
+                text(Tag(0x300A, 0x0288), VR::CS, "STEPWISE"),
+                text(Tag(0x300C, 0x000E), VR::IS, "1"),
+                sequence(Tag(0x300A, 0x02D0), points),
+            ])
+        };
+        let setup = item(vec![
+            text(Tag(0x300A, 0x0234), VR::IS, "1"),
+            text(Tag(0x300A, 0x0232), VR::CS, "INTRACAVITARY"),
+            text(Tag(0x300A, 0x0236), VR::LO, "Tandem and ring"),
+            sequence(
+                Tag(0x300A, 0x0280),
+                vec![
+                    // Control points listed out of order
+                    channel("2", vec![
+                        point("1", "5", "1\\2\\8", "1"),
+                        point("0", "0", "1\\2\\3", "0"),
+                    ]),
+                    channel("5", vec![point("0", "0", "-4\\0\\3", "0")]),
+                ],
+            ),
+        ]);
+        let obj = item(vec![
+            text(Tag(0x0008, 0x0018), VR::UI, "9.5"),
+            text(Tag(0x0020, 0x000D), VR::UI, "1.2"),
+            text(Tag(0x0020, 0x000E), VR::UI, "9.6"),
+            text(Tag(0x0008, 0x0060), VR::CS, "RTPLAN"),
+            text(Tag(0x300A, 0x0002), VR::SH, "Cervix"),
+            sequence(Tag(0x300A, 0x0230), vec![setup]),
+        ]);
+
+        let plan = RTPlan::from_object(&obj).unwrap();
+        assert!(plan.beams.is_empty());
+        let setup = &plan.brachy_application_setups[0];
+        assert_eq!(setup.setup_type.as_deref(), Some("INTRACAVITARY"));
+        assert_eq!(setup.channels.iter().map(|channel| channel.number).collect::<Vec<_>>(), vec![2, 5]);
+
+        let channel = &setup.channels[0];
+        assert_eq!((channel.length, channel.total_time), (Some(1200.0), Some(95.5)));
+        assert_eq!(channel.source_movement_type.as_deref(), Some("STEPWISE"));
+        assert_eq!(channel.control_points.iter().map(|point| point.index).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(channel.control_points[0].position, Some([1.0, 2.0, 3.0]));
+        assert_eq!(channel.control_points[1].position, Some([1.0, 2.0, 8.0]));
+        assert_eq!(channel.control_points[1].relative_position, Some(5.0));
+        assert_eq!(channel.control_points[1].cumulative_time_weight, Some(1.0));
+        assert_eq!(setup.channels[1].control_points[0].position, Some([-4.0, 0.0, 3.0]));
+    }
+}
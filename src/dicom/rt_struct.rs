@@ -0,0 +1,286 @@
+use super::dicom_helper::{get_first_item, get_items, get_value, get_values, MissingAttribute};
+use super::series_geometry::Vec3;
+use anyhow::{anyhow, Result};
+use dicom_object::{FileDicomObject, InMemDicomObject};
+use std::collections::HashMap;
+
+pub const RTSTRUCT_MODALITY: &str = "RTSTRUCT";
+
+// (3006,0042) ContourGeometricType
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ContourGeometry {
+    Point,         // A single point, e.g. a marker
+    OpenPlanar,    // An open polyline in one plane
+    OpenNonplanar, // An open polyline, e.g. a catheter path
+    ClosedPlanar,  // A closed polygon in one plane, the last point connecting to the first
+}
+
+impl ContourGeometry {
+    fn parse(value: &str) -> Option<ContourGeometry> {
+        match value.trim() {
+            "POINT" => Some(ContourGeometry::Point),
+            "OPEN_PLANAR" => Some(ContourGeometry::OpenPlanar),
+            "OPEN_NONPLANAR" => Some(ContourGeometry::OpenNonplanar),
+            "CLOSED_PLANAR" => Some(ContourGeometry::ClosedPlanar),
+            _ => None,
+        }
+    }
+}
+
+// A contour of a ROI, with its points in patient coordinates (mm)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Contour {
+    pub geometry: ContourGeometry,
+    pub points: Vec<Vec3>,
+}
+
+impl Contour {
+    // Whether the contour outlines an area of a plane
+    pub fn is_closed_planar(&self) -> bool {
+        self.geometry == ContourGeometry::ClosedPlanar && self.points.len() >= 3
+    }
+}
+
+// A region of interest of a structure set, combining its entries of the
+// StructureSetROISequence, ROIContourSequence and RTROIObservationsSequence
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Roi {
+    pub number: i32,                            // (3006,0022) ROINumber
+    pub name: String,                           // (3006,0026) ROIName
+    pub color: Option<[u8; 3]>,                 // (3006,002A) ROIDisplayColor (RGB)
+    pub roi_type: Option<String>,               // (3006,00A4) RTROIInterpretedType, e.g. PTV, ORGAN, EXTERNAL
+    pub observation_label: Option<String>,      // (3006,0085) ROIObservationLabel
+    pub frame_of_reference_uid: Option<String>, // (3006,0024) ReferencedFrameOfReferenceUID
+    pub generation_algorithm: Option<String>,   // (3006,0036) ROIGenerationAlgorithm
+    pub contours: Vec<Contour>,
+}
+
+impl Roi {
+    // Contours outlining an area, i.e. those a mask can be built from
+    pub fn closed_contours(&self) -> impl Iterator<Item = &Contour> {
+        self.contours.iter().filter(|contour| contour.is_closed_planar())
+    }
+}
+
+// An RT Structure Set: the ROIs delineated on the images of a frame of reference
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StructureSet {
+    pub uid: String,                            // (0008,0018) SOPInstanceUID
+    pub series_uid: String,                     // (0020,000E) SeriesInstanceUID of the RTSTRUCT series
+    pub study_uid: String,                      // (0020,000D) StudyInstanceUID
+    pub label: String,                          // (3006,0002) StructureSetLabel
+    pub name: Option<String>,                   // (3006,0004) StructureSetName
+    pub date: Option<String>,                   // (3006,0008) StructureSetDate
+    pub frame_of_reference_uid: Option<String>, // Frame of reference the contours are defined in
+    pub referenced_series_uid: Option<String>,  // Image series the contours were drawn on, if given
+    pub rois: Vec<Roi>,                         // Ordered by ROINumber
+}
+
+impl StructureSet {
+    // Function to parse the DICOM file and generate the StructureSet structure
+    pub fn from_bytes(dicom_data: &[u8]) -> Result<StructureSet> {
+        let obj: FileDicomObject<InMemDicomObject> = FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&obj)
+    }
+
+    // Function to generate the StructureSet structure from an already parsed DICOM file
+    pub fn from_object(obj: &InMemDicomObject) -> Result<StructureSet> {
+        let modality = get_value::<String>(obj, "Modality").ok_or(MissingAttribute("Modality"))?;
+        if modality != RTSTRUCT_MODALITY {
+            return Err(anyhow!("Expected modality {}, found {}", RTSTRUCT_MODALITY, modality));
+        }
+        let uid = get_value::<String>(obj, "SOPInstanceUID").ok_or(MissingAttribute("SOPInstanceUID"))?;
+        let series_uid =
+            get_value::<String>(obj, "SeriesInstanceUID").ok_or(MissingAttribute("SeriesInstanceUID"))?;
+        let study_uid =
+            get_value::<String>(obj, "StudyInstanceUID").ok_or(MissingAttribute("StudyInstanceUID"))?;
+        let label =
+            get_value::<String>(obj, "StructureSetLabel").ok_or(MissingAttribute("StructureSetLabel"))?;
+
+        // The referenced frame of reference names the image series the structures were drawn on
+        let referenced_frame = get_first_item(obj, "ReferencedFrameOfReferenceSequence");
+        let referenced_series_uid = referenced_frame
+            .and_then(|item| get_first_item(item, "RTReferencedStudySequence"))
+            .and_then(|item| get_first_item(item, "RTReferencedSeriesSequence"))
+            .and_then(|item| get_value::<String>(item, "SeriesInstanceUID"));
+
+        let rois = parse_rois(obj)?;
+        let frame_of_reference_uid = referenced_frame
+            .and_then(|item| get_value::<String>(item, "FrameOfReferenceUID"))
+            .or_else(|| rois.iter().find_map(|roi| roi.frame_of_reference_uid.clone()));
+
+        Ok(StructureSet {
+            uid,
+            series_uid,
+            study_uid,
+            label,
+            name: get_value::<String>(obj, "StructureSetName"),
+            date: get_value::<String>(obj, "StructureSetDate"),
+            frame_of_reference_uid,
+            referenced_series_uid,
+            rois,
+        })
+    }
+
+    // Query a ROI by its ROINumber
+    pub fn roi(&self, number: i32) -> Option<&Roi> {
+        self.rois.iter().find(|roi| roi.number == number)
+    }
+
+    // Query a ROI by name, ignoring case
+    pub fn roi_by_name(&self, name: &str) -> Option<&Roi> {
+        self.rois.iter().find(|roi| roi.name.eq_ignore_ascii_case(name))
+    }
+}
+
+// Join the three ROI sequences on the ROI number
+fn parse_rois(obj: &InMemDicomObject) -> Result<Vec<Roi>> {
+    let mut rois: Vec<Roi> = get_items(obj, "StructureSetROISequence")
+        .iter()
+        .map(|item| {
+            Ok(Roi {
+                number: get_value::<i32>(item, "ROINumber").ok_or(MissingAttribute("ROINumber"))?,
+                name: get_value::<String>(item, "ROIName").unwrap_or_default(), // Type 2
+                color: None,
+                roi_type: None,
+                observation_label: None,
+                frame_of_reference_uid: get_value::<String>(item, "ReferencedFrameOfReferenceUID"),
+                generation_algorithm: get_value::<String>(item, "ROIGenerationAlgorithm"),
+                contours: Vec::new(),
+            })
+        })
+        .collect::<Result<_>>()?;
+    let index: HashMap<i32, usize> = rois.iter().enumerate().map(|(i, roi)| (roi.number, i)).collect();
+
+    for item in get_items(obj, "ROIContourSequence") {
+        let number = get_value::<i32>(item, "ReferencedROINumber")
+            .ok_or(MissingAttribute("ReferencedROINumber"))?;
+        let Some(&i) = index.get(&number) else {
+            log::warn!("Ignoring contours of undefined ROI {}", number);
+            continue;
+        };
+        rois[i].color = get_values::<u8>(item, "ROIDisplayColor").and_then(|v| match v[..] {
+            [r, g, b] => Some([r, g, b]),
+            _ => None,
+        });
+        for contour in get_items(item, "ContourSequence") {
+            rois[i].contours.push(parse_contour(contour, number)?);
+        }
+    }
+
+    for item in get_items(obj, "RTROIObservationsSequence") {
+        let roi = get_value::<i32>(item, "ReferencedROINumber").and_then(|number| index.get(&number));
+        if let Some(&i) = roi {
+            rois[i].roi_type = get_value::<String>(item, "RTROIInterpretedType").filter(|v| !v.is_empty());
+            rois[i].observation_label = get_value::<String>(item, "ROIObservationLabel");
+        }
+    }
+
+    rois.sort_by_key(|roi| roi.number);
+    Ok(rois)
+}
+
+fn parse_contour(item: &InMemDicomObject, roi_number: i32) -> Result<Contour> {
+    let geometry = get_value::<String>(item, "ContourGeometricType")
+        .ok_or(MissingAttribute("ContourGeometricType"))?;
+    let geometry = ContourGeometry::parse(&geometry)
+        .ok_or_else(|| anyhow!("Unknown ContourGeometricType {} in ROI {}", geometry, roi_number))?;
+    let data = get_values::<f64>(item, "ContourData").ok_or(MissingAttribute("ContourData"))?;
+    if data.len() % 3 != 0 {
+        return Err(anyhow!("ContourData of ROI {} holds {} values, not a multiple of 3", roi_number, data.len()));
+    }
+    let points: Vec<Vec3> = data.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect();
+    if let Some(count) = get_value::<usize>(item, "NumberOfContourPoints") {
+        if count != points.len() {
+            return Err(anyhow!(
+                "ROI {} has a contour of {} points, NumberOfContourPoints is {}",
+                roi_number,
+                points.len(),
+                count
+            ));
+        }
+    }
+    Ok(Contour { geometry, points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::{DicomRepo, ImageSeries};
+    use crate::dicom::test_util::{item, sequence, text};
+    use dicom_core::{Tag, VR};
+
+    #[test]
+    fn test_rois_joined_across_sequences() {
+        let contour = item(vec![
+            text(Tag(0x3006, 0x0042), VR::CS, "CLOSED_PLANAR"),
+            text(Tag(0x3006, 0x0046), VR::IS, "3"),
+            text(Tag(0x3006, 0x0050), VR::DS, "0\\0\\5\\10\\0\\5\\0\\10\\5"),
+        ]);
+        let obj = item(vec![
+            text(Tag(0x0008, 0x0018), VR::UI, "9.1"),
+            text(Tag(0x0020, 0x000D), VR::UI, "1.2"),
+            text(Tag(0x0020, 0x000E), VR::UI, "9.2"),
+            text(Tag(0x0008, 0x0060), VR::CS, "RTSTRUCT"),
+            text(Tag(0x3006, 0x0002), VR::SH, "Plan1"),
+            sequence(
+                Tag(0x3006, 0x0010),
+                vec![item(vec![
+                    text(Tag(0x0020, 0x0052), VR::UI, "1.2.9"),
+                    sequence(
+                        Tag(0x3006, 0x0012),
+                        vec![item(vec![sequence(
+                            Tag(0x3006, 0x0014),
+                            vec![item(vec![text(Tag(0x0020, 0x000E), VR::UI, "1.2.3")])],
+                        )])],
+                    ),
+                ])],
+            ),
+            sequence(
+                Tag(0x3006, 0x0020),
+                vec![
+                    item(vec![
+                        text(Tag(0x3006, 0x0022), VR::IS, "2"),
+                        text(Tag(0x3006, 0x0026), VR::LO, "PTV"),
+                        text(Tag(0x3006, 0x0024), VR::UI, "1.2.9"),
+                    ]),
+                    item(vec![text(Tag(0x3006, 0x0022), VR::IS, "1"), text(Tag(0x3006, 0x0026), VR::LO, "Body")]),
+                ],
+            ),
+            sequence(
+                Tag(0x3006, 0x0039),
+                vec![item(vec![
+                    text(Tag(0x3006, 0x0084), VR::IS, "2"),
+                    text(Tag(0x3006, 0x002A), VR::IS, "255\\0\\0"),
+                    sequence(Tag(0x3006, 0x0040), vec![contour]),
+                ])],
+            ),
+            sequence(
+                Tag(0x3006, 0x0080),
+                vec![item(vec![
+                    text(Tag(0x3006, 0x0084), VR::IS, "2"),
+                    text(Tag(0x3006, 0x00A4), VR::CS, "PTV"),
+                ])],
+            ),
+        ]);
+
+        let set = StructureSet::from_object(&obj).unwrap();
+        assert_eq!(set.frame_of_reference_uid.as_deref(), Some("1.2.9"));
+        assert_eq!(set.referenced_series_uid.as_deref(), Some("1.2.3"));
+        assert_eq!(set.rois.iter().map(|roi| roi.number).collect::<Vec<_>>(), vec![1, 2]);
+
+        let ptv = set.roi_by_name("ptv").unwrap();
+        assert_eq!(ptv.color, Some([255, 0, 0]));
+        assert_eq!(ptv.roi_type.as_deref(), Some("PTV"));
+        assert_eq!(ptv.closed_contours().count(), 1);
+        assert_eq!(ptv.contours[0].points[1], [10.0, 0.0, 5.0]);
+        assert!(set.roi(1).unwrap().contours.is_empty());
+
+        // Without the referenced series loaded, the CT of the same frame of reference is used
+        let mut repo = DicomRepo::new();
+        repo.add_image_series(ImageSeries::new("7".into(), "1.2".into(), "CT".into(), None, Some("1.2.9".into()), None, None));
+        repo.add_structure_set(set);
+        assert_eq!(repo.structure_sets_of("7").len(), 1);
+        assert_eq!(repo.referenced_series_of(&repo.structure_sets["9.1"]).map(|s| s.uid.as_str()), Some("7"));
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::dicomdir::is_dicomdir;
+use super::ingest::CancellationToken;
+
+/// Options controlling which files are collected from a directory tree.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    // Descend into subdirectories; only the immediate files are listed otherwise
+    pub recursive: bool,
+    // Maximum directory depth below each root (None for unlimited)
+    pub max_depth: Option<usize>,
+    // Follow symbolic links to files and directories; link cycles are detected and skipped
+    pub follow_symlinks: bool,
+    // Glob patterns a file's path relative to its root must match (all files when empty)
+    pub include: Vec<String>,
+    // Glob patterns excluding files or whole directories, relative to the root
+    pub exclude: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            recursive: true,
+            max_depth: None,
+            follow_symlinks: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+// Files found by a directory scan
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    pub files: Vec<PathBuf>,     // Candidate DICOM files
+    pub dicomdirs: Vec<PathBuf>, // DICOMDIR index files, listed separately from the images
+    pub errors: Vec<(PathBuf, String)>, // Entries that could not be read, with the reason
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|err| anyhow!("Invalid glob '{}': {}", pattern, err))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|err| anyhow!("Failed to build glob set: {}", err))
+}
+
+// The include and exclude globs of `ScanOptions`, matched against paths relative to a root
+#[derive(Debug, Clone)]
+pub(crate) struct ScanFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+    include_all: bool,
+}
+
+impl ScanFilter {
+    pub(crate) fn new(options: &ScanOptions) -> Result<Self> {
+        Ok(ScanFilter {
+            include: build_glob_set(&options.include)?,
+            exclude: build_glob_set(&options.exclude)?,
+            include_all: options.include.is_empty(),
+        })
+    }
+
+    fn relative(root: &Path, path: &Path) -> String {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    // Whether a file or directory below `root` is excluded
+    pub(crate) fn is_excluded(&self, root: &Path, path: &Path) -> bool {
+        self.exclude.is_match(Self::relative(root, path))
+    }
+
+    // Whether a file below `root` is collected; any excluded ancestor directory excludes it
+    pub(crate) fn matches(&self, root: &Path, path: &Path) -> bool {
+        let excluded = path
+            .ancestors()
+            .take_while(|ancestor| *ancestor != root)
+            .any(|ancestor| self.is_excluded(root, ancestor));
+        !excluded && (self.include_all || self.include.is_match(Self::relative(root, path)))
+    }
+}
+
+/// Collects the files below a list of directories.
+///
+/// Paths are matched against the include and exclude globs relative to the directory they
+/// were found under, using `/` as separator (e.g. `**/*.dcm`, `**/SCOUT/**`). An excluded
+/// directory is not descended into. When symbolic links are followed, files reached through
+/// several links are listed once.
+///
+/// # Errors
+/// Returns an error for invalid glob patterns or if a root directory cannot be read.
+/// Unreadable entries below a root are skipped and listed in `errors`.
+pub fn collect_dicom_files(directories: &[&str], options: &ScanOptions) -> Result<ScanResult> {
+    collect_dicom_files_with(directories, options, &CancellationToken::new(), |_| {})
+}
+
+// Collect files as `collect_dicom_files` does, reporting each file found and stopping with
+// the files found so far once `cancel` is cancelled
+pub(crate) fn collect_dicom_files_with(
+    directories: &[&str],
+    options: &ScanOptions,
+    cancel: &CancellationToken,
+    mut on_found: impl FnMut(&Path),
+) -> Result<ScanResult> {
+    let filter = ScanFilter::new(options)?;
+
+    let mut result = ScanResult::default();
+    let mut seen = HashSet::new();
+
+    for root in directories {
+        let root = Path::new(root);
+        if !root.is_dir() {
+            return Err(anyhow!("Error reading directory {}: not a directory", root.display()));
+        }
+
+        let max_depth = if options.recursive {
+            options.max_depth.unwrap_or(usize::MAX)
+        } else {
+            1
+        };
+
+        let walker = WalkDir::new(root)
+            .follow_links(options.follow_symlinks)
+            .max_depth(max_depth)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !filter.is_excluded(root, entry.path()));
+
+        for entry in walker {
+            if cancel.is_cancelled() {
+                return Ok(result);
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    // Link cycles, broken links and permission errors skip the entry only
+                    let path = err.path().unwrap_or(root).to_path_buf();
+                    result.errors.push((path, err.to_string()));
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.into_path();
+            if options.follow_symlinks {
+                let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+                if !seen.insert(canonical) {
+                    continue;
+                }
+            }
+
+            if is_dicomdir(&path) {
+                on_found(&path);
+                result.dicomdirs.push(path);
+            } else if filter.matches(root, &path) {
+                on_found(&path);
+                result.files.push(path);
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_recursive_scan_with_globs() {
+        let root = std::env::temp_dir().join(format!("kepler-scan-{}", std::process::id()));
+        for dir in ["a/b", "a/scout", "c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["top.dcm", "a/b/deep.dcm", "a/b/notes.txt", "a/scout/s.dcm", "c/DICOMDIR"] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        let root_str = root.to_str().unwrap();
+
+        let options = ScanOptions {
+            include: vec!["**/*.dcm".to_string()],
+            exclude: vec!["**/scout".to_string()],
+            ..ScanOptions::default()
+        };
+        let result = collect_dicom_files(&[root_str], &options).unwrap();
+        assert_eq!(result.files, vec![root.join("a/b/deep.dcm"), root.join("top.dcm")]);
+        assert_eq!(result.dicomdirs, vec![root.join("c/DICOMDIR")]);
+
+        let flat = ScanOptions {
+            recursive: false,
+            ..ScanOptions::default()
+        };
+        let result = collect_dicom_files(&[root_str], &flat).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(result.files, vec![root.join("top.dcm")]);
+    }
+}
@@ -0,0 +1,460 @@
+use super::image_instance::DicomImage;
+use super::query::none_last;
+use crate::volume::Voxel;
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+
+pub type Vec3 = [f64; 3];
+
+pub fn dot(a: &Vec3, b: &Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn norm(a: &Vec3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+pub fn sub(a: &Vec3, b: &Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn to_vec3(v: (f32, f32, f32)) -> Vec3 {
+    [v.0 as f64, v.1 as f64, v.2 as f64]
+}
+
+pub fn to_tuple(v: &Vec3) -> (f32, f32, f32) {
+    (v[0] as f32, v[1] as f32, v[2] as f32)
+}
+
+// Orientation of an image plane in patient coordinates (LPS), derived from ImageOrientationPatient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SliceOrientation {
+    pub row: Vec3,    // Direction of increasing column index (first IOP triplet)
+    pub column: Vec3, // Direction of increasing row index (second IOP triplet)
+    pub normal: Vec3, // row x column, the stacking direction of an orthogonal series
+}
+
+impl SliceOrientation {
+    // Plain axial orientation, used when a series carries no ImageOrientationPatient
+    pub fn axial() -> Self {
+        SliceOrientation {
+            row: [1.0, 0.0, 0.0],
+            column: [0.0, 1.0, 0.0],
+            normal: [0.0, 0.0, 1.0],
+        }
+    }
+
+    pub fn from_iop(iop: (f32, f32, f32, f32, f32, f32)) -> Result<Self> {
+        let row = [iop.0 as f64, iop.1 as f64, iop.2 as f64];
+        let column = [iop.3 as f64, iop.4 as f64, iop.5 as f64];
+        let (row_len, column_len) = (norm(&row), norm(&column));
+        if row_len < 1e-6 || column_len < 1e-6 {
+            return Err(anyhow!("Degenerate ImageOrientationPatient {:?}", iop));
+        }
+        let row = row.map(|v| v / row_len);
+        let column = column.map(|v| v / column_len);
+        let normal = cross(&row, &column);
+        let normal_len = norm(&normal);
+        if normal_len < 1e-6 {
+            return Err(anyhow!("ImageOrientationPatient cosines are parallel: {:?}", iop));
+        }
+        Ok(SliceOrientation {
+            row,
+            column,
+            normal: normal.map(|v| v / normal_len),
+        })
+    }
+
+    // Distance of a slice position along the slice normal
+    pub fn project(&self, position: &Vec3) -> f64 {
+        dot(position, &self.normal)
+    }
+}
+
+/// Sorts the slices of a series along the normal of their common image plane.
+///
+/// The orientation is taken from the first slice's ImageOrientationPatient (axial when absent),
+/// and each slice is ordered by the projection of its ImagePositionPatient onto the normal,
+/// which is correct for axial, sagittal, coronal and oblique acquisitions alike.
+///
+/// Returns the orientation and the sorted positions along the normal.
+///
+/// # Errors
+/// - If the orientation is degenerate.
+/// - If a series with more than one slice has a slice without ImagePositionPatient.
+pub fn sort_along_normal<T: DicomImage + ?Sized>(images: &mut [&T]) -> Result<(SliceOrientation, Vec<f64>)> {
+    let orientation = match images.first().and_then(|img| img.image_orientation_patient()) {
+        Some(iop) => SliceOrientation::from_iop(iop)?,
+        None => SliceOrientation::axial(),
+    };
+
+    if images.len() > 1 {
+        if let Some(img) = images.iter().find(|img| img.image_position_patient().is_none()) {
+            return Err(anyhow!("ImagePositionPatient is missing in image '{}'", img.uid()));
+        }
+    }
+
+    let position = |img: &T| {
+        img.image_position_patient()
+            .map(|ipp| orientation.project(&to_vec3(ipp)))
+            .unwrap_or(0.0)
+    };
+    // Images at the same position (re-sent or repeated acquisitions) keep InstanceNumber order,
+    // so that duplicate removal keeps the earliest instance
+    images.sort_by(|a, b| {
+        position(a)
+            .partial_cmp(&position(b))
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| none_last(a.instance_number(), b.instance_number()))
+    });
+
+    let positions = images.iter().map(|img| position(img)).collect();
+    Ok((orientation, positions))
+}
+
+// Mean distance between consecutive slices, or None when it cannot be measured
+pub fn mean_slice_spacing(positions: &[f64]) -> Option<f64> {
+    if positions.len() < 2 {
+        return None;
+    }
+    let spacing = (positions[positions.len() - 1] - positions[0]) / (positions.len() - 1) as f64;
+    if spacing > 1e-6 {
+        Some(spacing)
+    } else {
+        None
+    }
+}
+
+/// Measures the in-plane shear of a sorted stack, as produced by a tilted gantry.
+///
+/// Returns the in-plane displacement (mm) of a slice per mm travelled along the normal,
+/// computed from the positions of the first and last slices. An orthogonal stack yields zero.
+pub fn stack_shear<T: DicomImage + ?Sized>(images: &[&T], orientation: &SliceOrientation, positions: &[f64]) -> Vec3 {
+    let (first, last) = match (
+        images.first().and_then(|img| img.image_position_patient()),
+        images.last().and_then(|img| img.image_position_patient()),
+    ) {
+        (Some(first), Some(last)) if images.len() > 1 => (to_vec3(first), to_vec3(last)),
+        _ => return [0.0; 3],
+    };
+    let along_normal = positions[positions.len() - 1] - positions[0];
+    if along_normal <= DUPLICATE_TOLERANCE {
+        return [0.0; 3];
+    }
+    let offset = sub(&last, &first);
+    let n = &orientation.normal;
+    [
+        (offset[0] - along_normal * n[0]) / along_normal,
+        (offset[1] - along_normal * n[1]) / along_normal,
+        (offset[2] - along_normal * n[2]) / along_normal,
+    ]
+}
+
+// Angle (degrees) between the stacking direction of a stack with the given shear and its normal
+pub fn shear_angle(shear: &Vec3) -> f64 {
+    norm(shear).atan().to_degrees()
+}
+
+/// Checks GantryDetectorTilt against the shear angle measured from the slice positions.
+///
+/// They disagree e.g. when a tilted acquisition was reconstructed onto an orthogonal grid but
+/// kept the tag, or when a sheared stack carries no tag. The positions are authoritative for
+/// stacking; the tag is only compared, since its sign convention varies between vendors.
+pub fn tilt_mismatch(gantry_tilt: Option<f32>, shear_angle: f64) -> bool {
+    let tilt = gantry_tilt.map_or(0.0, |tilt| (tilt as f64).abs());
+    (tilt - shear_angle).abs() > TILT_TOLERANCE
+}
+
+/// Shifts a slice in-plane by a sub-pixel offset using bilinear interpolation.
+///
+/// The output pixel (r, c) takes the input value at (r - dy, c - dx); pixels falling outside
+/// the input are filled with `fill`.
+pub fn shift_slice<T: Voxel>(data: &[T], rows: usize, columns: usize, dx: f64, dy: f64, fill: T) -> Vec<T> {
+    let sample = |r: isize, c: isize| -> f64 {
+        if r < 0 || c < 0 || r >= rows as isize || c >= columns as isize {
+            fill.to_f64()
+        } else {
+            data[r as usize * columns + c as usize].to_f64()
+        }
+    };
+
+    let mut shifted = Vec::with_capacity(rows * columns);
+    for r in 0..rows {
+        let y = r as f64 - dy;
+        let (y0, ty) = (y.floor(), y - y.floor());
+        for c in 0..columns {
+            let x = c as f64 - dx;
+            let (x0, tx) = (x.floor(), x - x.floor());
+            let (yi, xi) = (y0 as isize, x0 as isize);
+            let top = sample(yi, xi) * (1.0 - tx) + sample(yi, xi + 1) * tx;
+            let bottom = sample(yi + 1, xi) * (1.0 - tx) + sample(yi + 1, xi + 1) * tx;
+            shifted.push(T::from_f64(top * (1.0 - ty) + bottom * ty));
+        }
+    }
+    shifted
+}
+
+// Median distance between consecutive distinct slices, robust to gaps and duplicates
+pub fn median_slice_spacing(positions: &[f64]) -> Option<f64> {
+    let mut spacings: Vec<f64> = positions
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .filter(|d| *d > DUPLICATE_TOLERANCE)
+        .collect();
+    if spacings.is_empty() {
+        return None;
+    }
+    spacings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Some(spacings[spacings.len() / 2])
+}
+
+// Slices closer than this (mm) along the normal are considered to be at the same position
+pub const DUPLICATE_TOLERANCE: f64 = 1e-3;
+// Relative deviation from the nominal spacing tolerated before a series is non-uniform
+pub const SPACING_TOLERANCE: f64 = 0.01;
+// Maximum sine of the angle between two slice normals for the slices to count as parallel
+pub const PARALLEL_TOLERANCE: f64 = 1e-3;
+// In-plane shear (mm per mm along the normal) below which a stack is treated as orthogonal
+pub const SHEAR_TOLERANCE: f64 = 1e-3;
+// Difference (degrees) between GantryDetectorTilt and the measured shear angle tolerated
+pub const TILT_TOLERANCE: f64 = 0.5;
+
+// A missing stretch of slices between two neighbouring images
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SliceGap {
+    pub before: String,        // Key of the image below the gap
+    pub after: String,         // Key of the image above the gap
+    pub distance: f64,         // Distance between the two images along the normal (mm)
+    pub missing_slices: usize, // Number of slices that would fit at the nominal spacing
+}
+
+// Result of checking the slice geometry of a series before it is stacked into a volume
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SeriesGeometryReport {
+    pub series_uid: String,
+    pub slice_count: usize,
+    pub nominal_spacing: Option<f64>, // Median spacing between distinct slices (mm)
+    pub min_spacing: Option<f64>,
+    pub max_spacing: Option<f64>,
+    pub duplicates: Vec<(String, String)>, // Pairs of image keys at the same position
+    pub gaps: Vec<SliceGap>,
+    pub non_uniform_spacing: bool,
+    pub non_parallel: Vec<String>, // Keys of images whose plane is not parallel to the first one
+    pub gantry_tilt: Option<f32>,  // GantryDetectorTilt of the first image (degrees)
+    pub shear_angle: f64,          // Angle between the stacking direction and the slice normal (degrees)
+    pub tilt_mismatch: bool,       // Whether gantry_tilt disagrees with shear_angle, see `tilt_mismatch`
+}
+
+impl SeriesGeometryReport {
+    /// Checks the geometry of a series whose images are already sorted along the normal.
+    ///
+    /// `keys` identify the images in the same order as `images` and `positions`
+    /// (see `sort_along_normal`).
+    pub fn from_sorted<T: DicomImage + ?Sized>(
+        series_uid: &str,
+        images: &[&T],
+        keys: &[String],
+        orientation: &SliceOrientation,
+        positions: &[f64],
+    ) -> Self {
+        let nominal_spacing = median_slice_spacing(positions);
+
+        let mut duplicates = Vec::new();
+        let mut gaps = Vec::new();
+        let mut distinct_spacings = Vec::new();
+        for (i, w) in positions.windows(2).enumerate() {
+            let distance = w[1] - w[0];
+            if distance <= DUPLICATE_TOLERANCE {
+                duplicates.push((keys[i].clone(), keys[i + 1].clone()));
+                continue;
+            }
+            distinct_spacings.push(distance);
+            if let Some(nominal) = nominal_spacing {
+                if distance > 1.5 * nominal {
+                    gaps.push(SliceGap {
+                        before: keys[i].clone(),
+                        after: keys[i + 1].clone(),
+                        distance,
+                        missing_slices: ((distance / nominal).round() as usize).saturating_sub(1),
+                    });
+                }
+            }
+        }
+
+        let min_spacing = distinct_spacings.iter().cloned().reduce(f64::min);
+        let max_spacing = distinct_spacings.iter().cloned().reduce(f64::max);
+        let non_uniform_spacing = match (nominal_spacing, min_spacing, max_spacing) {
+            (Some(nominal), Some(min), Some(max)) => (max - min) > SPACING_TOLERANCE * nominal,
+            _ => false,
+        };
+
+        let non_parallel = images
+            .iter()
+            .zip(keys)
+            .filter(|(img, _)| match img.image_orientation_patient().map(SliceOrientation::from_iop) {
+                Some(Ok(o)) => norm(&cross(&o.normal, &orientation.normal)) > PARALLEL_TOLERANCE,
+                Some(Err(_)) => true,
+                None => false,
+            })
+            .map(|(_, key)| key.clone())
+            .collect();
+
+        let shear_angle = shear_angle(&stack_shear(images, orientation, positions));
+        let gantry_tilt = images.first().and_then(|img| img.gantry_detector_tilt());
+
+        SeriesGeometryReport {
+            series_uid: series_uid.to_string(),
+            slice_count: images.len(),
+            nominal_spacing,
+            min_spacing,
+            max_spacing,
+            duplicates,
+            gaps,
+            non_uniform_spacing,
+            non_parallel,
+            gantry_tilt,
+            shear_angle,
+            tilt_mismatch: tilt_mismatch(gantry_tilt, shear_angle),
+        }
+    }
+
+    // Whether the stack is sheared, i.e. its slices are not stacked along their normal
+    pub fn is_sheared(&self) -> bool {
+        self.shear_angle.to_radians().tan() > SHEAR_TOLERANCE
+    }
+
+    // Whether the series can be stacked as is into a metrically correct volume
+    pub fn is_regular(&self) -> bool {
+        self.duplicates.is_empty()
+            && self.gaps.is_empty()
+            && !self.non_uniform_spacing
+            && self.non_parallel.is_empty()
+    }
+}
+
+// How the slices of a series are turned into a volume
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeAssemblyOptions {
+    // Keep only the first of several images found at the same position (e.g. re-sent instances);
+    // when false, a series with duplicates is refused rather than stacked with a wrong spacing
+    pub remove_duplicates: bool,
+    // Stack only the images of this AcquisitionNumber, e.g. one phase of a repeated scan; all
+    // images when None
+    pub acquisition_number: Option<i32>,
+    // Resample the slices onto a uniform grid along the normal by linear interpolation
+    pub resample: bool,
+    // Spacing of the resampled grid (mm); the median slice spacing when None
+    pub target_spacing: Option<f32>,
+    // Shift the slices of a sheared (gantry-tilted) stack onto an orthogonal grid; when false the
+    // shear is kept and exposed through the volume's slice direction instead
+    pub correct_gantry_tilt: bool,
+}
+
+impl Default for VolumeAssemblyOptions {
+    fn default() -> Self {
+        VolumeAssemblyOptions {
+            remove_duplicates: true,
+            acquisition_number: None,
+            resample: false,
+            target_spacing: None,
+            correct_gantry_tilt: false,
+        }
+    }
+}
+
+/// Resamples slices at increasing `positions` onto a uniform grid starting at the first slice.
+///
+/// Each output slice is linearly interpolated between the two input slices bracketing it,
+/// so gaps are filled and variable spacing is evened out. Positions must be strictly
+/// increasing (duplicates removed). Returns the voxel data and the number of output slices.
+pub fn resample_slices<T: Voxel>(slices: &[Vec<T>], positions: &[f64], spacing: f64) -> (Vec<T>, usize) {
+    if slices.len() < 2 || spacing <= 0.0 {
+        return (slices.concat(), slices.len());
+    }
+
+    let extent = positions[positions.len() - 1] - positions[0];
+    let count = (extent / spacing + 1e-6).floor() as usize + 1;
+    let slice_len = slices[0].len();
+    let mut data = Vec::with_capacity(count * slice_len);
+
+    let mut lower = 0;
+    for k in 0..count {
+        let z = positions[0] + k as f64 * spacing;
+        while lower + 2 < positions.len() && positions[lower + 1] < z {
+            lower += 1;
+        }
+        let (z0, z1) = (positions[lower], positions[lower + 1]);
+        let t = ((z - z0) / (z1 - z0)).clamp(0.0, 1.0);
+        let (a, b) = (&slices[lower], &slices[lower + 1]);
+        data.extend(a.iter().zip(b).map(|(&va, &vb)| {
+            let (va, vb) = (va.to_f64(), vb.to_f64());
+            T::from_f64(va + t * (vb - va))
+        }));
+    }
+
+    (data, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sagittal_normal_and_projection() {
+        // Sagittal plane: rows run anterior->posterior, columns run superior->inferior
+        let orientation = SliceOrientation::from_iop((0.0, 1.0, 0.0, 0.0, 0.0, -1.0)).unwrap();
+        assert_eq!(orientation.normal, [-1.0, 0.0, 0.0]);
+        // Slices stack along -x, so a more negative x lies further along the normal
+        let a = orientation.project(&[10.0, 0.0, 50.0]);
+        let b = orientation.project(&[8.0, 0.0, -50.0]);
+        assert!((b - a - 2.0).abs() < 1e-9);
+        assert_eq!(mean_slice_spacing(&[a, b]), Some(2.0));
+    }
+
+    #[test]
+    fn test_sort_keeps_instance_order_at_same_position() {
+        use crate::dicom::ct_image::CTImage;
+        use crate::dicom::test_util::test_ct_image;
+        let image = |uid: &str, instance_number: i32, z: f32| CTImage {
+            uid: uid.to_string(),
+            instance_number: Some(instance_number),
+            image_position_patient: Some((0.0, 0.0, z)),
+            ..test_ct_image()
+        };
+        // A re-sent slice at z = 1 with a lower InstanceNumber sorts before the original
+        let (a, b, c) = (image("a", 1, 0.0), image("b", 3, 1.0), image("c", 2, 1.0));
+        let mut images = vec![&a, &b, &c];
+        let (_, positions) = sort_along_normal(&mut images).unwrap();
+        let uids: Vec<&str> = images.iter().map(|img| img.uid.as_str()).collect();
+        assert_eq!(uids, vec!["a", "c", "b"]);
+        assert_eq!(positions, vec![0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_shift_slice() {
+        let data = vec![2i16, 4, 6, 8, 10, 12];
+        // Whole-pixel shift right and down
+        assert_eq!(shift_slice(&data, 2, 3, 1.0, 1.0, 0), vec![0, 0, 0, 0, 2, 4]);
+        // Half-pixel shift right interpolates between neighbours
+        assert_eq!(shift_slice(&data, 2, 3, 0.5, 0.0, 0), vec![1, 3, 5, 4, 9, 11]);
+    }
+
+    #[test]
+    fn test_resample_fills_gap() {
+        // Slices at 0, 1, 2 and 4 mm: the slice at 3 mm is missing
+        let slices = vec![vec![0i16, 10], vec![10, 20], vec![20, 30], vec![40, 50]];
+        let positions = [0.0, 1.0, 2.0, 4.0];
+        assert_eq!(median_slice_spacing(&positions), Some(1.0));
+        let (data, count) = resample_slices(&slices, &positions, 1.0);
+        assert_eq!(count, 5);
+        assert_eq!(data, vec![0, 10, 10, 20, 20, 30, 30, 40, 40, 50]);
+    }
+}
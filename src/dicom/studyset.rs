@@ -1,15 +1,15 @@
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 use dicom_object::{FileDicomObject, InMemDicomObject};
 use crate::define_dicom_struct;
-use super::dicom_helper::get_value;
+use super::dicom_helper::{get_value, MissingAttribute};
 
 
 // Use the macro to define the StudySet struct
 define_dicom_struct!(StudySet, {
-    (study_id, String, "(0020,0010) StudyID", false),           // StudyID is required
+    (study_id, String, "(0020,0010) StudyID", true),           // StudyID is Type 2, may be absent
     (uid, String, "(0020,000D) StudyInstanceUID", false),     // StudyInstanceUID is required
     (patient_id, String, "(0010,0020) PatientID", false),           // PatientID is required
-    (date, String, "(0020,000D) StudyDate", false),       // StudyDate is required
+    (date, String, "(0008,0020) StudyDate", true),       // StudyDate is Type 2, may be absent
     (description, String, "(0008,1030) StudyDescription", true) // StudyDescription is optional
 });
 
@@ -20,19 +20,22 @@ impl StudySet {
         // Parse the DICOM file into a `FileDicomObject`
         let dicom_obj: FileDicomObject<InMemDicomObject> =
             FileDicomObject::from_reader(dicom_data)?;
+        Self::from_object(&dicom_obj)
+    }
 
+    // Function to generate the StudySet structure from an already parsed DICOM file or data set
+    pub fn from_object(dicom_obj: &InMemDicomObject) -> Result<StudySet> {
         // Retrieve required fields using `get_value`
-        let id =
-            get_value::<String>(&dicom_obj, "StudyID").ok_or_else(|| anyhow!("Missing StudyID"))?;
-        let uid = get_value::<String>(&dicom_obj, "StudyInstanceUID")
-            .ok_or_else(|| anyhow!("Missing StudyInstanceUID"))?;
-        let patient_id = get_value::<String>(&dicom_obj, "PatientID")
-            .ok_or_else(|| anyhow!("Missing PatientID"))?;
-        let date = get_value::<String>(&dicom_obj, "StudyDate")
-            .ok_or_else(|| anyhow!("Missing StudyDate"))?;
+        let uid = get_value::<String>(dicom_obj, "StudyInstanceUID")
+            .ok_or(MissingAttribute("StudyInstanceUID"))?;
+        let patient_id = get_value::<String>(dicom_obj, "PatientID")
+            .ok_or(MissingAttribute("PatientID"))?;
 
-        // Optional fields
-        let description = get_value::<String>(&dicom_obj, "StudyDescription");
+        // Optional fields; StudyID and StudyDate are Type 2, so they are often sent empty or
+        // left out by anonymizers and must not drop the study
+        let id = get_value::<String>(dicom_obj, "StudyID").filter(|v| !v.trim().is_empty());
+        let date = get_value::<String>(dicom_obj, "StudyDate").filter(|v| !v.trim().is_empty());
+        let description = get_value::<String>(dicom_obj, "StudyDescription");
 
         // Return the populated struct
         Ok(StudySet {
@@ -0,0 +1,82 @@
+// Fixtures shared by the unit tests of the DICOM models. Tests override the fields they care
+// about with struct update syntax, e.g. `CTImage { uid: .., ..test_ct_image() }`.
+use super::ct_image::CTImage;
+use super::rt_dose::RTDose;
+use dicom_core::value::DataSetSequence;
+use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom_object::InMemDicomObject;
+
+// A data set or sequence item holding the given elements
+pub(crate) fn item(elements: Vec<DataElement<InMemDicomObject>>) -> InMemDicomObject {
+    InMemDicomObject::from_element_iter(elements)
+}
+
+// An element holding a string value, e.g. a UID or a backslash separated list of numbers
+pub(crate) fn text(tag: Tag, vr: VR, value: &str) -> DataElement<InMemDicomObject> {
+    DataElement::new(tag, vr, PrimitiveValue::from(value))
+}
+
+pub(crate) fn sequence(tag: Tag, items: Vec<InMemDicomObject>) -> DataElement<InMemDicomObject> {
+    DataElement::new(tag, VR::SQ, DataSetSequence::from(items))
+}
+
+// A 1x1 signed 16-bit CT image of series 1.2.3 without any optional attribute
+pub(crate) fn test_ct_image() -> CTImage {
+    CTImage {
+        uid: "1.2.3.4".to_string(),
+        series_uid: "1.2.3".to_string(),
+        frame_number: None,
+        instance_number: None,
+        acquisition_number: None,
+        rows: 1,
+        columns: 1,
+        pixel_spacing: None,
+        slice_thickness: None,
+        spacing_between_slices: None,
+        gantry_detector_tilt: None,
+        kvp: None,
+        convolution_kernel: None,
+        image_position_patient: None,
+        image_orientation_patient: None,
+        rescale_slope: None,
+        rescale_intercept: None,
+        window_center: None,
+        window_width: None,
+        bits_allocated: 16,
+        bits_stored: 16,
+        high_bit: 15,
+        pixel_representation: 1,
+        transfer_syntax_uid: "1.2.840.10008.1.2.1".to_string(),
+        pixel_data: vec![0, 0],
+    }
+}
+
+// A single-frame 1x2 dose grid in Gy, 2x3 mm pixels with its first voxel at (10, 20, -5),
+// stored as unsigned 16-bit values scaled by 0.5
+pub(crate) fn test_rt_dose() -> RTDose {
+    RTDose {
+        uid: "9.3".to_string(),
+        series_uid: "9.4".to_string(),
+        study_uid: "1.2".to_string(),
+        frame_of_reference_uid: Some("1.2.9".to_string()),
+        referenced_plan_uid: None,
+        dose_units: "GY".to_string(),
+        dose_type: "PHYSICAL".to_string(),
+        dose_summation_type: "PLAN".to_string(),
+        number_of_frames: 1,
+        rows: 1,
+        columns: 2,
+        pixel_spacing: (2.0, 3.0),
+        slice_thickness: None,
+        image_position_patient: (10.0, 20.0, -5.0),
+        image_orientation_patient: (1.0, 0.0, 0.0, 0.0, 1.0, 0.0),
+        grid_frame_offset_vector: Some(vec![0.0]),
+        dose_grid_scaling: 0.5,
+        bits_allocated: 16,
+        bits_stored: 16,
+        high_bit: 15,
+        pixel_representation: 0,
+        transfer_syntax_uid: "1.2.840.10008.1.2.1".to_string(),
+        pixel_data: vec![0; 4],
+    }
+}
@@ -0,0 +1,498 @@
+use anyhow::{anyhow, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::dicom_repo::DicomRepo;
+use super::dicomdir::is_dicomdir;
+use super::fileio::{ParseOptions, ParsedFile, RtObject};
+use super::image_instance::DicomImage;
+use super::ingest::{FileOutcome, IngestStatus};
+use super::rt_dose::RTDOSE_MODALITY;
+use super::rt_plan::RTPLAN_MODALITY;
+use super::rt_struct::RTSTRUCT_MODALITY;
+use super::scan::{collect_dicom_files, ScanFilter};
+
+/// Options of a `DirectoryWatcher`.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    // How files are selected (`parse.scan`) and read
+    pub parse: ParseOptions,
+    // Time a file must go unchanged before it is parsed, so files still being copied are
+    // not read half-written
+    pub debounce: Duration,
+    // Time a series must go without new files before it is reported as completed
+    pub completion_delay: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            parse: ParseOptions::default(),
+            debounce: Duration::from_millis(500),
+            completion_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+// A change of the watched repository
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RepoEvent {
+    SeriesAdded { series_uid: String },     // The first image of a series was added
+    SeriesCompleted { series_uid: String }, // No image was added to the series for `completion_delay`
+    SeriesRemoved { series_uid: String },   // The last image of the series was deleted
+    RtObjectAdded { modality: String, uid: String },   // An RT structure set, dose or plan was added
+    RtObjectRemoved { modality: String, uid: String }, // The file of an RT structure set, dose or plan was deleted
+    FileRejected { outcome: FileOutcome },  // A new or changed file could not be (fully) ingested
+}
+
+/// Keeps a `DicomRepo` up to date with the DICOM files below a set of directories.
+///
+/// The files present when the watcher starts are ingested first; afterwards new or
+/// rewritten files are parsed and merged, and the images and RT objects of deleted files are
+/// removed.
+/// Changes are reported as `RepoEvent`s on the receiver returned by `start`. Dropping the
+/// watcher stops it.
+pub struct DirectoryWatcher {
+    repo: Arc<RwLock<DicomRepo>>,
+    watcher: Option<RecommendedWatcher>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl DirectoryWatcher {
+    /// Starts watching the given directories.
+    ///
+    /// # Errors
+    /// Returns an error for invalid scan globs or if a directory cannot be read or watched.
+    pub fn start(directories: &[&str], options: WatchOptions) -> Result<(DirectoryWatcher, Receiver<RepoEvent>)> {
+        let filter = ScanFilter::new(&options.parse.scan)?;
+        // Change notifications carry absolute paths, so the roots are made absolute too
+        let roots = directories
+            .iter()
+            .map(|dir| {
+                Path::new(dir)
+                    .canonicalize()
+                    .map_err(|err| anyhow!("Error reading directory {}: {}", dir, err))
+            })
+            .collect::<Result<Vec<PathBuf>>>()?;
+        let root_strs: Vec<&str> = roots.iter().filter_map(|root| root.to_str()).collect();
+        let initial = collect_dicom_files(&root_strs, &options.parse.scan)?;
+
+        let (change_tx, change_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                for path in event.paths {
+                    let _ = change_tx.send(path);
+                }
+            }
+        })
+        .map_err(|err| anyhow!("Failed to create directory watcher: {}", err))?;
+        let mode = if options.parse.scan.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for root in &roots {
+            watcher
+                .watch(root, mode)
+                .map_err(|err| anyhow!("Failed to watch {}: {}", root.display(), err))?;
+        }
+
+        let repo = DicomRepo::new();
+        repo.set_pixel_cache_budget(options.parse.pixel_cache_budget);
+        let repo = Arc::new(RwLock::new(repo));
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut state = WatchState {
+            repo: Arc::clone(&repo),
+            events: event_tx,
+            options,
+            filter,
+            roots,
+            pending: HashMap::new(),
+            files: HashMap::new(),
+            series: HashMap::new(),
+        };
+        let now = Instant::now();
+        for path in initial.files {
+            state.pending.insert(path, now);
+        }
+        let worker = thread::spawn(move || state.run(change_rx));
+
+        Ok((
+            DirectoryWatcher {
+                repo,
+                watcher: Some(watcher),
+                worker: Some(worker),
+            },
+            event_rx,
+        ))
+    }
+
+    // The repository kept up to date by the watcher
+    pub fn repo(&self) -> Arc<RwLock<DicomRepo>> {
+        Arc::clone(&self.repo)
+    }
+}
+
+impl Drop for DirectoryWatcher {
+    fn drop(&mut self) {
+        // Dropping the notify watcher closes the change channel, which ends the worker
+        self.watcher.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// An entity a watched file contributed to the repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FileEntity {
+    Image(String),        // Image key
+    StructureSet(String), // SOPInstanceUID of an RT structure set
+    Dose(String),         // SOPInstanceUID of an RT dose
+    Plan(String),         // SOPInstanceUID of an RT plan
+}
+
+impl FileEntity {
+    // Entities a parsed file adds to the repository
+    fn of(parsed: &ParsedFile) -> Vec<FileEntity> {
+        let mut entities: Vec<FileEntity> = parsed
+            .images
+            .iter()
+            .map(|image| FileEntity::Image(image.key()))
+            .collect();
+        entities.extend(parsed.rt_object.as_ref().map(|rt_object| match rt_object {
+            RtObject::StructureSet(structure_set) => FileEntity::StructureSet(structure_set.uid.clone()),
+            RtObject::Dose(dose) => FileEntity::Dose(dose.uid.clone()),
+            RtObject::Plan(plan) => FileEntity::Plan(plan.uid.clone()),
+        }));
+        entities
+    }
+
+    // Modality and UID of an RT object, as reported in events
+    fn rt_object(&self) -> Option<(&'static str, &str)> {
+        match self {
+            FileEntity::Image(_) => None,
+            FileEntity::StructureSet(uid) => Some((RTSTRUCT_MODALITY, uid)),
+            FileEntity::Dose(uid) => Some((RTDOSE_MODALITY, uid)),
+            FileEntity::Plan(uid) => Some((RTPLAN_MODALITY, uid)),
+        }
+    }
+
+    // Remove the entity from the repository, returning whether it was there
+    fn remove_from(&self, repo: &mut DicomRepo) -> bool {
+        match self {
+            FileEntity::Image(key) => repo.remove_image(key).is_some(),
+            FileEntity::StructureSet(uid) => repo.remove_structure_set(uid).is_some(),
+            FileEntity::Dose(uid) => repo.remove_dose(uid).is_some(),
+            FileEntity::Plan(uid) => repo.remove_plan(uid).is_some(),
+        }
+    }
+}
+
+// Completion tracking of a series
+struct SeriesActivity {
+    last_change: Instant,
+    completed: bool,
+}
+
+// State of the watcher thread
+struct WatchState {
+    repo: Arc<RwLock<DicomRepo>>,
+    events: Sender<RepoEvent>,
+    options: WatchOptions,
+    filter: ScanFilter,
+    roots: Vec<PathBuf>,
+    pending: HashMap<PathBuf, Instant>, // Changed paths with the time of their last change
+    files: HashMap<PathBuf, Vec<FileEntity>>, // Entities added from each file
+    series: HashMap<String, SeriesActivity>,
+}
+
+impl WatchState {
+    fn run(&mut self, changes: Receiver<PathBuf>) {
+        let tick = self.options.debounce.min(self.options.completion_delay) / 2;
+        let tick = tick.max(Duration::from_millis(10));
+        loop {
+            match changes.recv_timeout(tick) {
+                Ok(path) => {
+                    self.pending.insert(path, Instant::now());
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.process_pending();
+            self.report_completed();
+        }
+    }
+
+    // Whether a path below one of the roots is a candidate image file, as a scan would find it
+    fn is_candidate(&self, path: &Path) -> bool {
+        let scan = &self.options.parse.scan;
+        let max_depth = if scan.recursive {
+            scan.max_depth.unwrap_or(usize::MAX)
+        } else {
+            1
+        };
+        !is_dicomdir(path)
+            && self.roots.iter().any(|root| {
+                path.strip_prefix(root)
+                    .is_ok_and(|relative| relative.components().count() <= max_depth)
+                    && self.filter.matches(root, path)
+            })
+    }
+
+    fn process_pending(&mut self) {
+        let now = Instant::now();
+        let settled: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= self.options.debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            self.pending.remove(&path);
+            if path.is_file() {
+                if self.is_candidate(&path) {
+                    self.ingest_file(path);
+                }
+            } else {
+                // Deleted or moved away; a removed directory takes its files along
+                let removed: Vec<PathBuf> = self
+                    .files
+                    .keys()
+                    .filter(|file| file.starts_with(&path) && !file.exists())
+                    .cloned()
+                    .collect();
+                for file in removed {
+                    self.remove_file(&file);
+                }
+            }
+        }
+    }
+
+    fn ingest_file(&mut self, path: PathBuf) {
+        let parsed = if self.options.parse.header_only {
+            ParsedFile::from_header_file(path.clone()).map(|(parsed, _)| parsed)
+        } else {
+            std::fs::read(&path)
+                .map_err(|err| anyhow!(err))
+                .and_then(|buffer| ParsedFile::from_file_bytes(path.clone(), &buffer))
+        };
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                let outcome = FileOutcome {
+                    path,
+                    status: IngestStatus::NotDicom,
+                    reason: Some(err.to_string()),
+                    images: 0,
+                };
+                let _ = self.events.send(RepoEvent::FileRejected { outcome });
+                return;
+            }
+        };
+
+        let outcome = parsed.outcome(&path);
+        let entities = FileEntity::of(&parsed);
+        let series_uids: Vec<String> = parsed
+            .images
+            .iter()
+            .map(|image| image.series_uid().to_string())
+            .collect();
+        // A rewritten file replaces the entities it held before
+        let previous = self.files.remove(&path).unwrap_or_default();
+        let stale: Vec<&FileEntity> = previous.iter().filter(|entity| !entities.contains(entity)).collect();
+        {
+            let mut repo = self.repo.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            for entity in &stale {
+                self.remove_entity(&mut repo, entity);
+            }
+            parsed.add_to(&mut repo);
+        }
+        for entity in entities.iter().filter(|entity| !previous.contains(entity)) {
+            if let Some((modality, uid)) = entity.rt_object() {
+                let _ = self.events.send(RepoEvent::RtObjectAdded {
+                    modality: modality.to_string(),
+                    uid: uid.to_string(),
+                });
+            }
+        }
+        if !outcome.status.is_success() {
+            let _ = self.events.send(RepoEvent::FileRejected { outcome });
+        }
+
+        let now = Instant::now();
+        for series_uid in &series_uids {
+            match self.series.get_mut(series_uid) {
+                Some(activity) => {
+                    activity.last_change = now;
+                    activity.completed = false;
+                }
+                None => {
+                    self.series.insert(
+                        series_uid.clone(),
+                        SeriesActivity {
+                            last_change: now,
+                            completed: false,
+                        },
+                    );
+                    let _ = self.events.send(RepoEvent::SeriesAdded {
+                        series_uid: series_uid.clone(),
+                    });
+                }
+            }
+        }
+        self.files.insert(path, entities);
+        self.remove_empty_series();
+    }
+
+    fn remove_file(&mut self, path: &Path) {
+        let Some(entities) = self.files.remove(path) else {
+            return;
+        };
+        {
+            let mut repo = self.repo.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            for entity in &entities {
+                self.remove_entity(&mut repo, entity);
+            }
+        }
+        self.remove_empty_series();
+    }
+
+    // Remove an entity of a deleted or rewritten file; images are reported with their series
+    // by `remove_empty_series`, RT objects one by one
+    fn remove_entity(&self, repo: &mut DicomRepo, entity: &FileEntity) {
+        if !entity.remove_from(repo) {
+            return;
+        }
+        if let Some((modality, uid)) = entity.rt_object() {
+            let _ = self.events.send(RepoEvent::RtObjectRemoved {
+                modality: modality.to_string(),
+                uid: uid.to_string(),
+            });
+        }
+    }
+
+    // Drop series whose last image is gone
+    fn remove_empty_series(&mut self) {
+        let mut repo = self.repo.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let empty: Vec<String> = self
+            .series
+            .keys()
+            .filter(|uid| repo.series_image_count(uid) == 0)
+            .cloned()
+            .collect();
+        for series_uid in empty {
+            repo.remove_series(&series_uid);
+            self.series.remove(&series_uid);
+            let _ = self.events.send(RepoEvent::SeriesRemoved { series_uid });
+        }
+    }
+
+    fn report_completed(&mut self) {
+        let now = Instant::now();
+        for (series_uid, activity) in &mut self.series {
+            if !activity.completed && now.duration_since(activity.last_change) >= self.options.completion_delay {
+                activity.completed = true;
+                let _ = self.events.send(RepoEvent::SeriesCompleted {
+                    series_uid: series_uid.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom::pixel_cache::tests::write_ct_file;
+    use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+    use dicom_object::meta::FileMetaTableBuilder;
+    use dicom_object::InMemDicomObject;
+    use std::fs;
+
+    fn watch(root: &Path) -> (DirectoryWatcher, Receiver<RepoEvent>) {
+        fs::create_dir_all(root).unwrap();
+        let options = WatchOptions {
+            debounce: Duration::from_millis(50),
+            completion_delay: Duration::from_millis(200),
+            ..WatchOptions::default()
+        };
+        DirectoryWatcher::start(&[root.to_str().unwrap()], options).unwrap()
+    }
+
+    // The next event, skipping rejections: the test files have no patient, so they are also
+    // reported as rejected
+    fn next(events: &Receiver<RepoEvent>) -> RepoEvent {
+        loop {
+            match events.recv_timeout(Duration::from_secs(10)).unwrap() {
+                RepoEvent::FileRejected { .. } => continue,
+                event => break event,
+            }
+        }
+    }
+
+    #[test]
+    fn test_watcher_tracks_added_and_removed_series() {
+        let root = std::env::temp_dir().join(format!("kepler-watch-{}", std::process::id()));
+        let (watcher, events) = watch(&root);
+
+        let file = root.join("ct.dcm");
+        write_ct_file(&file);
+        assert!(matches!(next(&events), RepoEvent::SeriesAdded { series_uid } if series_uid == "1.2.3"));
+        assert!(matches!(next(&events), RepoEvent::SeriesCompleted { series_uid } if series_uid == "1.2.3"));
+        assert_eq!(watcher.repo().read().unwrap().series_image_count("1.2.3"), 1);
+
+        fs::remove_file(&file).unwrap();
+        assert!(matches!(next(&events), RepoEvent::SeriesRemoved { series_uid } if series_uid == "1.2.3"));
+        assert_eq!(watcher.repo().read().unwrap().series_image_count("1.2.3"), 0);
+
+        drop(watcher);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watcher_removes_rt_objects_of_deleted_files() {
+        let root = std::env::temp_dir().join(format!("kepler-watch-rt-{}", std::process::id()));
+        let (watcher, events) = watch(&root);
+
+        let text = |tag, vr, value: &str| DataElement::new(tag, vr, PrimitiveValue::from(value));
+        let file = root.join("rs.dcm");
+        InMemDicomObject::from_element_iter([
+            text(Tag(0x0008, 0x0018), VR::UI, "9.1"),
+            text(Tag(0x0008, 0x0060), VR::CS, "RTSTRUCT"),
+            text(Tag(0x0020, 0x000D), VR::UI, "1.2"),
+            text(Tag(0x0020, 0x000E), VR::UI, "9.2"),
+            text(Tag(0x3006, 0x0002), VR::SH, "Plan1"),
+        ])
+        .with_meta(
+            FileMetaTableBuilder::new()
+                .transfer_syntax("1.2.840.10008.1.2.1")
+                .media_storage_sop_class_uid("1.2.840.10008.5.1.4.1.1.481.3")
+                .media_storage_sop_instance_uid("9.1"),
+        )
+        .unwrap()
+        .write_to_file(&file)
+        .unwrap();
+        assert!(matches!(
+            next(&events),
+            RepoEvent::RtObjectAdded { modality, uid } if modality == "RTSTRUCT" && uid == "9.1"
+        ));
+        assert!(watcher.repo().read().unwrap().get_structure_set("9.1").is_some());
+
+        fs::remove_file(&file).unwrap();
+        assert!(matches!(
+            next(&events),
+            RepoEvent::RtObjectRemoved { modality, uid } if modality == "RTSTRUCT" && uid == "9.1"
+        ));
+        assert!(watcher.repo().read().unwrap().get_structure_set("9.1").is_none());
+
+        drop(watcher);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -20,35 +20,15 @@ use view::TransverseView;
 pub mod coordinates;
 pub mod ct_volume;
 pub mod dicom;
-mod texture_3d;
+pub mod texture_3d;
 mod view;
+pub mod volume;
 
-use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
 
 use ct_volume::*;
-use dicom::*;
 
 use std::time::Instant;
 
-fn list_files_in_directory(dir: &str) -> io::Result<Vec<PathBuf>> {
-    let mut file_paths = Vec::new();
-
-    // Open the directory and iterate over its contents
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?; // unwrap the result of read_dir
-        let path = entry.path();
-
-        // Check if the entry is a file (not a directory)
-        if path.is_file() {
-            file_paths.push(path); // Add the full path to the list
-        }
-    }
-
-    Ok(file_paths)
-}
-
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
@@ -200,8 +180,7 @@ impl<'a> State<'a> {
             // Start the timer
             let start_time = Instant::now();
 
-            let file_names = list_files_in_directory("C:\\share\\imrt").unwrap();
-            let repo = dicom::fileio::parse_dcm_directories(vec![
+            let (repo, report) = dicom::fileio::parse_dcm_directories(vec![
                 "C:\\share\\imrt",
                 "C:\\share\\head_mold",
             ])
@@ -209,6 +188,9 @@ impl<'a> State<'a> {
             .unwrap();
             println!("DicomRepo:\n{}", repo.to_string());
             println!("Patients:\n{:?}", repo.get_all_patients());
+            for failure in report.failures() {
+                println!("Skipped {}: {:?} {:?}", failure.path.display(), failure.status, failure.reason);
+            }
             // Stop the timer
             let elapsed_time = start_time.elapsed();
 
@@ -228,43 +210,27 @@ impl<'a> State<'a> {
                 "CTVolume being generated in {:.1} ms.",
                 elapsed_time.as_millis_f32()
             );
-            let mut ct_images = repo.get_images_by_series("1.2.392.200036.9116.2.5.1.144.3437232930.1426478676.964561");
-            ct_images.sort_by(|a, b| {
-                let z_a = a.image_position_patient.map(|pos| pos.2).unwrap_or(0.0);
-                let z_b = b.image_position_patient.map(|pos| pos.2).unwrap_or(0.0);
-                z_a.partial_cmp(&z_b).unwrap_or(std::cmp::Ordering::Equal)
-            });
-            let ct_image = ct_images[0];
-            let (ox, oy, oz0) = ct_images[0].image_position_patient.unwrap();
-            let (_, _, oz1) = ct_images.last().unwrap().image_position_patient.unwrap();
-            let dz = oz1 - oz0;
-            let nx = ct_image.rows as f32;
-            let ny = ct_image.columns as f32;
-            let space = ct_image.pixel_spacing.unwrap();
-
             use crate::coordinates::*;
-            let m_uv = [nx*space.0, 0.0,        0.0, ox,
-                        0.0,        ny*space.1, 0.0, oy,
-                        0.0,        0.0,        dz,  oz0,
-                        0.0,        0.0,        0.0, 1.0];
-            let matrix_uv = Matrix4x4::<f32>::from_array(m_uv);
+            let matrix_uv = vol.texture_to_patient();
             println!("{:?}", matrix_uv);
-            let d = f32::max(nx * space.0, ny * space.1);
-            let m_screen = [d,    0.0,  0.0, ox,
-                            0.0,  0.0,  d,   oy,
-                            0.0,  -d,   0.0, (oz0+oz1)/2.0+d/2.0,
+            // Coronal screen plane spanning the in-plane extent of the volume, centred on it
+            let (min, max) = vol.bounding_box();
+            let d = f64::max(max[0] - min[0], max[1] - min[1]);
+            let m_screen = [d,    0.0,  0.0, min[0],
+                            0.0,  0.0,  d,   min[1],
+                            0.0,  -d,   0.0, (min[2] + max[2]) / 2.0 + d / 2.0,
                             0.0,  0.0,  0.0, 1.0];
-            let matrix_screen = Matrix4x4::<f32>::from_array(m_screen);
+            let matrix_screen = Matrix4x4::<f64>::from_array(m_screen);
             println!("{:?}", matrix_screen);
             
             
-            let base_uv = Base::<f32> {
+            let base_uv = Base::<f64> {
                 label: "CT Volume: UV".to_string(),
                 matrix: matrix_uv,
             };
             println!("{:?}", matrix_uv.inv());
 
-            let base_screen = Base::<f32> {
+            let base_screen = Base::<f64> {
                 label: "CT Volume: screen".to_string(),
                 matrix: matrix_screen,
             };
@@ -2,6 +2,8 @@ use anyhow::*;
 use log::{debug, error, info};
 use wgpu::util::DeviceExt;
 
+use crate::volume::{Volume, Voxel};
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -20,7 +22,70 @@ impl Texture {
         height: u32,
         depth: u32,
     ) -> Result<Self> {
-        let texture_format = wgpu::TextureFormat::Rg8Unorm;
+        Self::from_bytes_with_format(
+            device,
+            queue,
+            bytes,
+            label,
+            width,
+            height,
+            depth,
+            wgpu::TextureFormat::Rg8Unorm,
+        )
+    }
+
+    // Upload a volume with one voxel per texel, in the texture format of its voxel type. u8, u16
+    // and i16 volumes bind as `texture_3d<f32>` with a filtering sampler; 16-bit voxels are packed
+    // into the red and green bytes (i16 offset by 32768). f32 volumes are R32Float, which needs a
+    // non-filterable float binding and a non-filtering sampler.
+    pub fn from_volume<T: Voxel>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        volume: &Volume<T>,
+        label: &str,
+    ) -> Result<Self> {
+        let (rows, columns, slices) = volume.dimensions();
+        Self::from_bytes_with_format(
+            device,
+            queue,
+            &T::texels(volume.voxel_data()),
+            label,
+            columns as u32,
+            rows as u32,
+            slices as u32,
+            T::TEXTURE_FORMAT,
+        )
+    }
+
+    // Read a 3D texture from bytes laid out texel by texel in `texture_format`
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_bytes_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        width: u32,
+        height: u32,
+        depth: u32,
+        texture_format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let bytes_per_texel = texture_format
+            .block_copy_size(None)
+            .ok_or_else(|| anyhow!("Texture format {:?} cannot be copied to", texture_format))?;
+        let expected = bytes_per_texel as usize * width as usize * height as usize * depth as usize;
+        if bytes.len() < expected {
+            return Err(anyhow!(
+                "Texture '{}' needs {} bytes but only {} were given",
+                label,
+                expected,
+                bytes.len()
+            ));
+        }
+        // Integer and 32-bit float textures cannot be sampled with linear filtering
+        let filter_mode = match texture_format.sample_type(None, None) {
+            Some(wgpu::TextureSampleType::Float { filterable: true }) => wgpu::FilterMode::Linear,
+            _ => wgpu::FilterMode::Nearest,
+        };
         let size = wgpu::Extent3d {
             width,
             height,
@@ -49,7 +114,7 @@ impl Texture {
             bytes,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(2 * width),
+                bytes_per_row: Some(bytes_per_texel * width),
                 rows_per_image: Some(height),
             },
             size,
@@ -61,7 +126,7 @@ impl Texture {
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
                 address_mode_v: wgpu::AddressMode::ClampToEdge,
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
+                mag_filter: filter_mode,
                 min_filter: wgpu::FilterMode::Nearest,
                 mipmap_filter: wgpu::FilterMode::Nearest,
                 ..Default::default()
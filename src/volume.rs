@@ -0,0 +1,679 @@
+use crate::coordinates::Matrix4x4;
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::fmt;
+
+// Minimum direction cosine component that contributes a letter to an orientation label
+const ORIENTATION_LABEL_TOLERANCE: f64 = 1e-3;
+
+/// Scalar type that can be stored in a [`Volume`] and uploaded as a 3D texture.
+pub trait Voxel: Copy + Default + PartialOrd + bytemuck::Pod + Send + Sync + 'static {
+    // Texture format of the texels `texels` produces, one voxel per texel
+    const TEXTURE_FORMAT: wgpu::TextureFormat;
+
+    fn to_f64(self) -> f64;
+
+    // Rounds and saturates for integer types
+    fn from_f64(value: f64) -> Self;
+
+    // Bytes of `voxels` laid out texel by texel in `TEXTURE_FORMAT`
+    fn texels(voxels: &[Self]) -> Cow<'_, [u8]> {
+        Cow::Borrowed(bytemuck::cast_slice(voxels))
+    }
+}
+
+macro_rules! impl_integer_voxel {
+    ($($ty:ty => $format:ident $(|$v:ident| $to_unsigned:expr)?),* $(,)?) => {
+        $(
+            impl Voxel for $ty {
+                const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::$format;
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn from_f64(value: f64) -> Self {
+                    // Float-to-int `as` casts saturate and map NaN to zero
+                    value.round() as $ty
+                }
+
+                $(
+                    fn texels(voxels: &[Self]) -> Cow<'_, [u8]> {
+                        Cow::Owned(voxels.iter().flat_map(|&$v| ($to_unsigned).to_le_bytes()).collect())
+                    }
+                )?
+            }
+        )*
+    };
+}
+
+// 16-bit voxels are packed into byte pairs, low byte first, as in the existing slice shader: the
+// integer formats R16Uint/R16Sint cannot be sampled as float, and the normalized ones need a
+// device feature. Signed voxels are offset by 32768 so the unpacked values keep their order.
+impl_integer_voxel!(
+    u8 => R8Unorm,
+    u16 => Rg8Unorm |v| v,
+    i16 => Rg8Unorm |v| (v as i32 + 32768) as u16,
+);
+
+impl Voxel for f32 {
+    const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+// A 3D grid of voxels placed in patient space
+#[derive(Clone)]
+pub struct Volume<T: Voxel> {
+    pub(crate) dimensions: (usize, usize, usize), // (rows, columns, number of slices)
+    pub(crate) voxel_spacing: (f32, f32, f32), // (row spacing, column spacing, slice spacing) in mm
+    // Maps voxel indices (column, row, slice, 1) to DICOM patient coordinates (LPS, mm)
+    pub(crate) index_to_patient: Matrix4x4<f64>,
+    pub(crate) voxel_data: Vec<T>, // 3D voxel data
+}
+
+impl<T: Voxel> fmt::Debug for Volume<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Volume")
+            .field("dimensions", &self.dimensions)
+            .field("voxel_spacing", &self.voxel_spacing)
+            .field("index_to_patient", &self.index_to_patient.data)
+            .field("voxel_data", &format!("{} slices", self.voxel_data.len()))
+            .finish()
+    }
+}
+
+/// Builds the index-to-patient matrix of a volume from its DICOM geometry.
+///
+/// `row_direction` is the direction of increasing column index and `column_direction` the
+/// direction of increasing row index, as in ImageOrientationPatient; `slice_direction` is the
+/// direction of increasing slice index. `voxel_spacing` is (row spacing, column spacing, slice
+/// spacing) in mm and `origin` the position of the first voxel of the first slice.
+pub fn index_to_patient_matrix(
+    origin: (f32, f32, f32),
+    row_direction: (f32, f32, f32),
+    column_direction: (f32, f32, f32),
+    slice_direction: (f32, f32, f32),
+    voxel_spacing: (f32, f32, f32),
+) -> Matrix4x4<f64> {
+    let (dr, dc, ds) = (
+        voxel_spacing.0 as f64,
+        voxel_spacing.1 as f64,
+        voxel_spacing.2 as f64,
+    );
+    Matrix4x4::from_array([
+        row_direction.0 as f64 * dc, column_direction.0 as f64 * dr, slice_direction.0 as f64 * ds, origin.0 as f64,
+        row_direction.1 as f64 * dc, column_direction.1 as f64 * dr, slice_direction.1 as f64 * ds, origin.1 as f64,
+        row_direction.2 as f64 * dc, column_direction.2 as f64 * dr, slice_direction.2 as f64 * ds, origin.2 as f64,
+        0.0, 0.0, 0.0, 1.0,
+    ])
+}
+
+/// Labels a patient-space direction with the anatomical directions it points to (LPS).
+///
+/// Letters are ordered by decreasing contribution, so an oblique axis mostly pointing left
+/// and slightly posterior is labelled "LP". Returns an empty string for a zero vector.
+pub fn orientation_label(direction: [f64; 3]) -> String {
+    let letters = [('L', 'R'), ('P', 'A'), ('S', 'I')];
+    let mut axes = [0, 1, 2];
+    axes.sort_by(|&a, &b| direction[b].abs().total_cmp(&direction[a].abs()));
+    axes.iter()
+        .filter(|&&axis| direction[axis].abs() > ORIENTATION_LABEL_TOLERANCE)
+        .map(|&axis| {
+            if direction[axis] > 0.0 {
+                letters[axis].0
+            } else {
+                letters[axis].1
+            }
+        })
+        .collect()
+}
+
+// Axis of a volume, named after the voxel index that varies along it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeAxis {
+    Column, // Index i
+    Row,    // Index j
+    Slice,  // Index k
+}
+
+// A 2D cut through a volume, stored row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeSlice<T: Voxel> {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<T>, // width * height values, row-major
+}
+
+impl<T: Voxel> VolumeSlice<T> {
+    pub fn get(&self, x: usize, y: usize) -> Option<T> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.data[y * self.width + x])
+    }
+}
+
+// Voxel value counts over equally wide bins starting at `min`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f64,
+    pub bin_width: f64,
+    pub counts: Vec<usize>,
+}
+
+impl<T: Voxel> Volume<T> {
+    /// Creates an axis-aligned volume with its first voxel at the patient origin.
+    ///
+    /// `dimensions` is (rows, columns, slices) and `voxel_data` is stored slice by slice, row by
+    /// row, so the value of voxel (i, j, k) = (column, row, slice) is at
+    /// `(k * rows + j) * columns + i`.
+    ///
+    /// # Errors
+    /// See [`Volume::from_parts`].
+    pub fn new(
+        dimensions: (usize, usize, usize),
+        voxel_spacing: (f32, f32, f32),
+        voxel_data: Vec<T>,
+    ) -> Result<Self> {
+        let index_to_patient = index_to_patient_matrix(
+            (0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0),
+            (0.0, 1.0, 0.0),
+            (0.0, 0.0, 1.0),
+            voxel_spacing,
+        );
+        Self::from_parts(dimensions, voxel_spacing, index_to_patient, voxel_data)
+    }
+
+    /// Creates a volume with an explicit index-to-patient matrix.
+    ///
+    /// # Errors
+    /// Returns an error if the data length does not equal the product of the dimensions, if a
+    /// spacing is not a positive finite number, or if the matrix is not invertible.
+    pub fn from_parts(
+        dimensions: (usize, usize, usize),
+        voxel_spacing: (f32, f32, f32),
+        index_to_patient: Matrix4x4<f64>,
+        voxel_data: Vec<T>,
+    ) -> Result<Self> {
+        let (rows, columns, slices) = dimensions;
+        let expected = rows
+            .checked_mul(columns)
+            .and_then(|n| n.checked_mul(slices))
+            .ok_or_else(|| anyhow!("Volume dimensions {:?} overflow", dimensions))?;
+        if voxel_data.len() != expected {
+            return Err(anyhow!(
+                "Voxel data holds {} values but dimensions {:?} require {}",
+                voxel_data.len(),
+                dimensions,
+                expected
+            ));
+        }
+        let (sr, sc, ss) = voxel_spacing;
+        if [sr, sc, ss].iter().any(|s| !s.is_finite() || *s <= 0.0) {
+            return Err(anyhow!("Invalid voxel spacing {:?}", voxel_spacing));
+        }
+        if index_to_patient.inv().is_none() {
+            return Err(anyhow!("Index-to-patient matrix is not invertible"));
+        }
+
+        Ok(Self {
+            dimensions,
+            voxel_spacing,
+            index_to_patient,
+            voxel_data,
+        })
+    }
+
+    // (rows, columns, slices)
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        self.dimensions
+    }
+
+    // (row spacing, column spacing, slice spacing) in mm
+    pub fn voxel_spacing(&self) -> (f32, f32, f32) {
+        self.voxel_spacing
+    }
+
+    pub fn voxel_data(&self) -> &[T] {
+        &self.voxel_data
+    }
+
+    pub fn into_voxel_data(self) -> Vec<T> {
+        self.voxel_data
+    }
+
+    // Offset of voxel (column, row, slice) in voxel_data, None if out of bounds
+    fn offset(&self, i: usize, j: usize, k: usize) -> Option<usize> {
+        let (rows, columns, slices) = self.dimensions;
+        if i >= columns || j >= rows || k >= slices {
+            return None;
+        }
+        Some((k * rows + j) * columns + i)
+    }
+
+    // Value of voxel (column, row, slice), None if out of bounds
+    pub fn voxel(&self, i: usize, j: usize, k: usize) -> Option<T> {
+        self.offset(i, j, k).map(|offset| self.voxel_data[offset])
+    }
+
+    /// Extracts the 2D cut at `index` perpendicular to `axis`.
+    ///
+    /// The cut keeps the remaining axes in index order: a `Slice` cut is columns × rows, a `Row`
+    /// cut columns × slices and a `Column` cut rows × slices (width × height).
+    /// Returns None if `index` is out of bounds.
+    pub fn slice(&self, axis: VolumeAxis, index: usize) -> Option<VolumeSlice<T>> {
+        let (rows, columns, slices) = self.dimensions;
+        match axis {
+            VolumeAxis::Slice if index < slices => {
+                let start = index * rows * columns;
+                Some(VolumeSlice {
+                    width: columns,
+                    height: rows,
+                    data: self.voxel_data[start..start + rows * columns].to_vec(),
+                })
+            }
+            VolumeAxis::Row if index < rows => Some(VolumeSlice {
+                width: columns,
+                height: slices,
+                data: (0..slices)
+                    .flat_map(|k| {
+                        let start = (k * rows + index) * columns;
+                        self.voxel_data[start..start + columns].iter().copied()
+                    })
+                    .collect(),
+            }),
+            VolumeAxis::Column if index < columns => Some(VolumeSlice {
+                width: rows,
+                height: slices,
+                data: (0..slices)
+                    .flat_map(|k| (0..rows).map(move |j| (k, j)))
+                    .map(|(k, j)| self.voxel_data[(k * rows + j) * columns + index])
+                    .collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    // Iterates over ([column, row, slice], value) in memory order
+    pub fn iter(&self) -> impl Iterator<Item = ([usize; 3], T)> + '_ {
+        let (rows, columns, _) = self.dimensions;
+        self.voxel_data.iter().enumerate().map(move |(offset, &value)| {
+            let i = offset % columns;
+            let j = (offset / columns) % rows;
+            let k = offset / (columns * rows);
+            ([i, j, k], value)
+        })
+    }
+
+    // Smallest and largest voxel value, None for an empty volume; NaNs are ignored
+    pub fn min_max(&self) -> Option<(T, T)> {
+        let mut values = self.voxel_data.iter().copied().filter(|v| !v.to_f64().is_nan());
+        let first = values.next()?;
+        Some(values.fold((first, first), |(min, max), v| {
+            (if v < min { v } else { min }, if v > max { v } else { max })
+        }))
+    }
+
+    /// Counts voxel values in `bin_count` equally wide bins spanning the value range.
+    ///
+    /// Returns None for an empty volume or zero bins.
+    pub fn histogram(&self, bin_count: usize) -> Option<Histogram> {
+        let (min, max) = self.min_max()?;
+        if bin_count == 0 {
+            return None;
+        }
+        let (min, max) = (min.to_f64(), max.to_f64());
+        // Integer ranges cover whole values, so the largest value falls inside the last bin
+        let range = if min.fract() == 0.0 && max.fract() == 0.0 {
+            max - min + 1.0
+        } else {
+            max - min
+        };
+        let bin_width = if range > 0.0 { range / bin_count as f64 } else { 1.0 };
+        let mut counts = vec![0; bin_count];
+        for v in self.voxel_data.iter().map(|v| v.to_f64()).filter(|v| !v.is_nan()) {
+            let bin = ((v - min) / bin_width) as usize;
+            counts[bin.min(bin_count - 1)] += 1;
+        }
+        Some(Histogram {
+            min,
+            bin_width,
+            counts,
+        })
+    }
+
+    // Applies `f` to every voxel, keeping the geometry
+    pub fn map<U: Voxel>(&self, f: impl Fn(T) -> U) -> Volume<U> {
+        Volume {
+            dimensions: self.dimensions,
+            voxel_spacing: self.voxel_spacing,
+            index_to_patient: self.index_to_patient,
+            voxel_data: self.voxel_data.iter().map(|&v| f(v)).collect(),
+        }
+    }
+
+    // Converts to another voxel type, rounding and saturating into integer types
+    pub fn cast<U: Voxel>(&self) -> Volume<U> {
+        self.map(|v| U::from_f64(v.to_f64()))
+    }
+
+    // Converts to another voxel type as `value * slope + intercept`, e.g. dose grid scaling
+    pub fn rescale<U: Voxel>(&self, slope: f64, intercept: f64) -> Volume<U> {
+        self.map(|v| U::from_f64(v.to_f64() * slope + intercept))
+    }
+
+    // Raw voxel bytes in memory order, ready for texture upload
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.voxel_data)
+    }
+
+    // Voxel indices are (column, row, slice), matching the memory layout of voxel_data
+    pub fn index_to_patient(&self) -> &Matrix4x4<f64> {
+        &self.index_to_patient
+    }
+
+    // None if the geometry is degenerate (e.g. zero spacing)
+    pub fn patient_to_index(&self) -> Option<Matrix4x4<f64>> {
+        self.index_to_patient.inv()
+    }
+
+    // Patient position (LPS, mm) of the first voxel of the first slice
+    pub fn origin(&self) -> [f64; 3] {
+        let m = &self.index_to_patient.data;
+        [m[0][3], m[1][3], m[2][3]]
+    }
+
+    // Unit vector of increasing index along `axis` (0: column, 1: row, 2: slice)
+    pub fn direction(&self, axis: usize) -> [f64; 3] {
+        let m = &self.index_to_patient.data;
+        let v = [m[0][axis], m[1][axis], m[2][axis]];
+        let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        if length == 0.0 {
+            return v;
+        }
+        [v[0] / length, v[1] / length, v[2] / length]
+    }
+
+    /// Converts a (possibly fractional) voxel index (column, row, slice) to patient coordinates.
+    pub fn voxel_to_world(&self, index: [f64; 3]) -> [f64; 3] {
+        let p = self.index_to_patient.apply(&[index[0], index[1], index[2], 1.0]);
+        [p[0], p[1], p[2]]
+    }
+
+    /// Converts patient coordinates to a fractional voxel index (column, row, slice).
+    ///
+    /// Returns None if the index-to-patient matrix is not invertible.
+    pub fn world_to_voxel(&self, point: [f64; 3]) -> Option<[f64; 3]> {
+        let index = self
+            .patient_to_index()?
+            .apply(&[point[0], point[1], point[2], 1.0]);
+        Some([index[0], index[1], index[2]])
+    }
+
+    /// Axis-aligned bounding box of the volume in patient coordinates as (min, max).
+    ///
+    /// The box covers the outer faces of the voxels, i.e. half a voxel beyond the centres of
+    /// the first and last voxels along each axis.
+    pub fn bounding_box(&self) -> ([f64; 3], [f64; 3]) {
+        let (rows, columns, slices) = self.dimensions;
+        let extent = [columns as f64 - 0.5, rows as f64 - 0.5, slices as f64 - 0.5];
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for corner in 0..8 {
+            let index = [
+                if corner & 1 == 0 { -0.5 } else { extent[0] },
+                if corner & 2 == 0 { -0.5 } else { extent[1] },
+                if corner & 4 == 0 { -0.5 } else { extent[2] },
+            ];
+            let p = self.voxel_to_world(index);
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        (min, max)
+    }
+
+    /// Samples the volume at a fractional voxel index (column, row, slice) by trilinear
+    /// interpolation.
+    ///
+    /// Indices up to half a voxel beyond the first and last voxel centres take the value of the
+    /// outer voxels, so the sampled extent matches `bounding_box`. Returns None further out.
+    pub fn interpolate(&self, index: [f64; 3]) -> Option<f64> {
+        let (rows, columns, slices) = self.dimensions;
+        let sizes = [columns, rows, slices];
+        let mut lower = [0usize; 3];
+        let mut weight = [0.0f64; 3];
+        for axis in 0..3 {
+            let size = sizes[axis] as f64;
+            if !(index[axis] >= -0.5 && index[axis] <= size - 0.5) {
+                return None;
+            }
+            let position = index[axis].clamp(0.0, size - 1.0);
+            lower[axis] = (position.floor() as usize).min(sizes[axis].saturating_sub(2));
+            weight[axis] = position - lower[axis] as f64;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut offset = [0usize; 3];
+            let mut w = 1.0;
+            for axis in 0..3 {
+                let upper = corner >> axis & 1 == 1;
+                if sizes[axis] == 1 && upper {
+                    w = 0.0;
+                    break;
+                }
+                offset[axis] = lower[axis] + upper as usize;
+                w *= if upper { weight[axis] } else { 1.0 - weight[axis] };
+            }
+            if w > 0.0 {
+                value += w * self.voxel_data[self.offset(offset[0], offset[1], offset[2])?].to_f64();
+            }
+        }
+        Some(value)
+    }
+
+    /// Samples the volume at a patient position (LPS, mm), see [`Volume::interpolate`].
+    pub fn sample_world(&self, point: [f64; 3]) -> Option<f64> {
+        self.interpolate(self.world_to_voxel(point)?)
+    }
+
+    /// Resamples the volume onto the grid of `reference` by trilinear interpolation, e.g. to
+    /// overlay a dose grid on a CT.
+    ///
+    /// Voxels of the reference grid outside this volume are set to `T::default()`.
+    ///
+    /// # Errors
+    /// Returns an error if the geometry of this volume is degenerate.
+    pub fn resample_onto<U: Voxel>(&self, reference: &Volume<U>) -> Result<Volume<T>> {
+        let to_index = self
+            .patient_to_index()
+            .ok_or_else(|| anyhow!("Index-to-patient matrix is not invertible"))?;
+        // Reference indices map to indices of this volume through a single affine transform
+        let transform = to_index.multiply(reference.index_to_patient());
+        let voxel_data = reference
+            .iter()
+            .map(|([i, j, k], _)| {
+                let index = transform.apply(&[i as f64, j as f64, k as f64, 1.0]);
+                self.interpolate([index[0], index[1], index[2]])
+                    .map_or_else(T::default, T::from_f64)
+            })
+            .collect();
+        Volume::from_parts(
+            reference.dimensions,
+            reference.voxel_spacing,
+            reference.index_to_patient,
+            voxel_data,
+        )
+    }
+
+    // Anatomical labels of the column, row and slice axes, e.g. ["L", "P", "S"] for axial
+    pub fn orientation_labels(&self) -> [String; 3] {
+        [0, 1, 2].map(|axis| orientation_label(self.direction(axis)))
+    }
+
+    /// Maps normalized texture coordinates ([0, 1] across each axis) to patient coordinates.
+    ///
+    /// Texture coordinate 0 and 1 lie on the outer faces of the first and last voxels.
+    pub fn texture_to_patient(&self) -> Matrix4x4<f64> {
+        let (rows, columns, slices) = self.dimensions;
+        let texture_to_index = Matrix4x4::from_array([
+            columns as f64, 0.0, 0.0, -0.5,
+            0.0, rows as f64, 0.0, -0.5,
+            0.0, 0.0, slices as f64, -0.5,
+            0.0, 0.0, 0.0, 1.0,
+        ]);
+        self.index_to_patient.multiply(&texture_to_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(
+        row_direction: (f32, f32, f32),
+        column_direction: (f32, f32, f32),
+        slice_direction: (f32, f32, f32),
+    ) -> Volume<i16> {
+        let voxel_spacing = (0.5, 0.75, 2.0);
+        Volume::from_parts(
+            (4, 3, 2),
+            voxel_spacing,
+            index_to_patient_matrix(
+                (-10.0, 20.0, 30.0),
+                row_direction,
+                column_direction,
+                slice_direction,
+                voxel_spacing,
+            ),
+            (0..24).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_texture_formats() {
+        // Integer voxels bind to the filtering float sampler of the slice views
+        let filterable = Some(wgpu::TextureSampleType::Float { filterable: true });
+        for format in [u8::TEXTURE_FORMAT, u16::TEXTURE_FORMAT, i16::TEXTURE_FORMAT] {
+            assert_eq!(format.sample_type(None, None), filterable);
+        }
+        assert_eq!(
+            f32::TEXTURE_FORMAT.sample_type(None, None),
+            Some(wgpu::TextureSampleType::Float { filterable: false })
+        );
+
+        assert_eq!(u16::texels(&[0x1234]).as_ref(), &[0x34, 0x12]);
+        assert_eq!(i16::texels(&[-32768, -1024, 0]).as_ref(), &[0x00, 0x00, 0x00, 0x7C, 0x00, 0x80]);
+        assert_eq!(f32::texels(&[1.0]).as_ref(), &1.0f32.to_ne_bytes());
+        for (texels, format) in [(u8::texels(&[1, 2]), u8::TEXTURE_FORMAT), (i16::texels(&[1, 2]), i16::TEXTURE_FORMAT)] {
+            assert_eq!(texels.len(), 2 * format.block_copy_size(None).unwrap() as usize);
+        }
+    }
+
+    #[test]
+    fn test_new_validates_length_and_spacing() {
+        assert!(Volume::<i16>::new((2, 2, 2), (1.0, 1.0, 1.0), vec![0; 7]).is_err());
+        assert!(Volume::<i16>::new((2, 2, 2), (1.0, 0.0, 1.0), vec![0; 8]).is_err());
+        assert!(Volume::<i16>::new((2, 2, 2), (1.0, 1.0, 1.0), vec![0; 8]).is_ok());
+    }
+
+    #[test]
+    fn test_voxel_access_and_slices() {
+        // 4 rows, 3 columns, 2 slices holding 0..24 in memory order
+        let vol = volume((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+        assert_eq!(vol.voxel(2, 1, 1), Some(12 + 3 + 2));
+        assert_eq!(vol.voxel(3, 0, 0), None);
+
+        let axial = vol.slice(VolumeAxis::Slice, 1).unwrap();
+        assert_eq!((axial.width, axial.height), (3, 4));
+        assert_eq!(axial.get(2, 1), vol.voxel(2, 1, 1));
+
+        let row = vol.slice(VolumeAxis::Row, 2).unwrap();
+        assert_eq!((row.width, row.height), (3, 2));
+        assert_eq!(row.data, vec![6, 7, 8, 18, 19, 20]);
+
+        let column = vol.slice(VolumeAxis::Column, 1).unwrap();
+        assert_eq!((column.width, column.height), (4, 2));
+        assert_eq!(column.data, vec![1, 4, 7, 10, 13, 16, 19, 22]);
+        assert!(vol.slice(VolumeAxis::Column, 3).is_none());
+
+        assert!(vol.iter().all(|([i, j, k], v)| vol.voxel(i, j, k) == Some(v)));
+    }
+
+    #[test]
+    fn test_min_max_and_histogram() {
+        let vol = volume((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+        assert_eq!(vol.min_max(), Some((0, 23)));
+        let histogram = vol.histogram(4).unwrap();
+        assert_eq!(histogram.bin_width, 6.0);
+        assert_eq!(histogram.counts, vec![6, 6, 6, 6]);
+    }
+
+    #[test]
+    fn test_conversions_keep_geometry() {
+        let vol = volume((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+        let dose: Volume<f32> = vol.rescale(0.5, 0.0);
+        assert_eq!(dose.voxel(1, 0, 0), Some(0.5));
+        assert_eq!(dose.origin(), vol.origin());
+
+        // Rounds to nearest and saturates into the target range
+        let mask: Volume<u8> = dose.map(|v| v * 30.0).cast();
+        assert_eq!(mask.voxel(1, 0, 0), Some(15));
+        assert_eq!(mask.voxel(2, 3, 1), Some(255));
+        assert_eq!(mask.as_bytes().len(), 24);
+    }
+
+    #[test]
+    fn test_voxel_world_round_trip() {
+        let vol = volume((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+        // Column steps use the column spacing, row steps the row spacing
+        assert_eq!(vol.voxel_to_world([2.0, 3.0, 1.0]), [-8.5, 21.5, 32.0]);
+        let index = vol.world_to_voxel([-8.5, 21.5, 32.0]).unwrap();
+        for (a, b) in index.iter().zip([2.0, 3.0, 1.0]) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        let (min, max) = vol.bounding_box();
+        assert_eq!(min, [-10.375, 19.75, 29.0]);
+        assert_eq!(max, [-10.375 + 3.0 * 0.75, 19.75 + 4.0 * 0.5, 33.0]);
+    }
+
+    #[test]
+    fn test_interpolate_and_resample() {
+        // Values 0..8 along the columns of a single row and slice, 2 mm apart
+        let line = Volume::new((1, 5, 1), (1.0, 2.0, 1.0), vec![0.0f32, 2.0, 4.0, 6.0, 8.0]).unwrap();
+        assert_eq!(line.interpolate([1.25, 0.0, 0.0]), Some(2.5));
+        assert_eq!(line.interpolate([-0.5, 0.0, 0.0]), Some(0.0));
+        assert_eq!(line.interpolate([4.6, 0.0, 0.0]), None);
+        assert_eq!(line.sample_world([3.0, 0.0, 0.0]), Some(3.0));
+
+        // Onto a 1 mm grid: every other sample falls between two source voxels
+        let fine = Volume::new((1, 9, 1), (1.0, 1.0, 1.0), vec![0i16; 9]).unwrap();
+        let resampled = line.resample_onto(&fine).unwrap();
+        assert_eq!(resampled.voxel_data(), &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(resampled.voxel_spacing(), fine.voxel_spacing());
+    }
+
+    #[test]
+    fn test_orientation_labels() {
+        let axial = volume((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+        assert_eq!(axial.orientation_labels(), ["L", "P", "S"]);
+
+        let coronal = volume((1.0, 0.0, 0.0), (0.0, 0.0, -1.0), (0.0, -1.0, 0.0));
+        assert_eq!(coronal.orientation_labels(), ["L", "I", "A"]);
+
+        assert_eq!(orientation_label([-0.8, 0.6, 0.0]), "RP");
+    }
+}